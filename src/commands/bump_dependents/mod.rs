@@ -0,0 +1,558 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::Context;
+use cargo_metadata::semver::{Version, VersionReq};
+use clap::Parser;
+use schemars::JsonSchema;
+use serde::Serialize;
+use toml_edit::{DocumentMut, Item, TableLike, Value};
+
+use crate::utils;
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+#[derive(Debug, Parser)]
+// `--version` is the released version, not the propagated version flag
+#[command(
+    about = "Raise the requirements on a released package across the repository, update the lockfiles and optionally push them to a follow-up branch.",
+    disable_version_flag = true
+)]
+pub struct Options {
+    /// Name of the released package
+    #[arg(long)]
+    package: String,
+    /// Released version
+    #[arg(long)]
+    version: Version,
+    /// Do not run `cargo update` in the workspaces whose manifests changed
+    #[arg(long, default_value_t = false)]
+    no_update_lockfiles: bool,
+    /// Registry the package was released to, requirements on crates.io are bumped when unset
+    #[arg(long)]
+    registry: Option<String>,
+    /// Commit the changes on this branch and push it, to open the follow-up pull request from
+    #[arg(long)]
+    push_branch: Option<String>,
+    /// Remote to push the follow-up branch to
+    #[arg(long, default_value = "origin")]
+    remote: String,
+    /// Only report what would be changed
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+#[derive(Serialize, JsonSchema, Debug, PartialEq)]
+pub struct BumpedRequirement {
+    pub manifest: PathBuf,
+    /// Dependency table holding the requirement, e.g. `dev-dependencies`
+    pub table: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct BumpDependentsResult {
+    package: String,
+    version: String,
+    bumped: Vec<BumpedRequirement>,
+    updated_lockfiles: Vec<PathBuf>,
+    /// Branch the changes were pushed to
+    pushed_branch: Option<String>,
+    dry_run: bool,
+}
+
+impl Display for BumpDependentsResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for bumped in &self.bumped {
+            writeln!(
+                f,
+                "{} [{}]: {} -> {}",
+                bumped.manifest.to_string_lossy(),
+                bumped.table,
+                bumped.from,
+                bumped.to
+            )?;
+        }
+        write!(
+            f,
+            "Bumped {} requirements on {} {}, updated {} lockfiles",
+            self.bumped.len(),
+            self.package,
+            self.version,
+            self.updated_lockfiles.len()
+        )?;
+        if let Some(branch) = &self.pushed_branch {
+            write!(f, ", pushed to {}", branch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Same requirement operator, on the released version
+fn bumped_requirement(requirement: &str, version: &Version) -> String {
+    match requirement.trim().chars().next() {
+        Some(operator @ ('=' | '~')) => format!("{}{}", operator, version),
+        _ => version.to_string(),
+    }
+}
+
+fn bump_table(
+    label: String,
+    table: &mut dyn TableLike,
+    package: &str,
+    registry: Option<&str>,
+    version: &Version,
+    bumped: &mut Vec<(String, String, String)>,
+) {
+    for (key, item) in table.iter_mut() {
+        let field = |field: &str| {
+            item.as_table_like()
+                .and_then(|t| t.get(field))
+                .and_then(|p| p.as_str())
+        };
+        // A same-named package from another registry is a different crate
+        if field("package").unwrap_or(key.get()) != package || field("registry") != registry {
+            continue;
+        }
+        let requirement = match item.as_table_like() {
+            Some(t) => t.get("version").and_then(|v| v.as_str()),
+            None => item.as_str(),
+        };
+        let Some(requirement) = requirement.map(|r| r.to_string()) else {
+            continue;
+        };
+        // Requirements already accepting the version are left alone
+        if VersionReq::parse(&requirement)
+            .map(|r| r.matches(version))
+            .unwrap_or(true)
+        {
+            continue;
+        }
+        let new_requirement = bumped_requirement(&requirement, version);
+        match item.as_table_like_mut() {
+            Some(t) => {
+                t.insert("version", toml_edit::value(new_requirement.clone()));
+            }
+            None => {
+                if let Some(value) = item.as_value_mut() {
+                    let decor = value.decor().clone();
+                    *value = Value::from(new_requirement.clone());
+                    *value.decor_mut() = decor;
+                }
+            }
+        }
+        bumped.push((label.clone(), requirement, new_requirement));
+    }
+}
+
+/// Raise every requirement of the manifest on `package` from `registry` not accepting `version`,
+/// returning the bumped `(table, from, to)`
+fn bump_document(
+    document: &mut DocumentMut,
+    package: &str,
+    registry: Option<&str>,
+    version: &Version,
+) -> Vec<(String, String, String)> {
+    let mut bumped = vec![];
+    for name in DEPENDENCY_TABLES {
+        if let Some(table) = document.get_mut(name).and_then(Item::as_table_like_mut) {
+            bump_table(
+                name.to_string(),
+                table,
+                package,
+                registry,
+                version,
+                &mut bumped,
+            );
+        }
+    }
+    if let Some(table) = document
+        .get_mut("workspace")
+        .and_then(Item::as_table_like_mut)
+        .and_then(|w| w.get_mut("dependencies"))
+        .and_then(Item::as_table_like_mut)
+    {
+        bump_table(
+            "workspace.dependencies".to_string(),
+            table,
+            package,
+            registry,
+            version,
+            &mut bumped,
+        );
+    }
+    if let Some(targets) = document.get_mut("target").and_then(Item::as_table_like_mut) {
+        for (cfg, target) in targets.iter_mut() {
+            for name in DEPENDENCY_TABLES {
+                if let Some(table) = target.get_mut(name).and_then(Item::as_table_like_mut) {
+                    bump_table(
+                        format!("target.{}.{}", cfg.get(), name),
+                        table,
+                        package,
+                        registry,
+                        version,
+                        &mut bumped,
+                    );
+                }
+            }
+        }
+    }
+    bumped
+}
+
+pub async fn bump_dependents(
+    options: Box<Options>,
+    working_directory: PathBuf,
+) -> anyhow::Result<BumpDependentsResult> {
+    let mut roots = utils::get_cargo_roots(working_directory.clone())
+        .with_context(|| format!("Failed to get roots from {:?}", working_directory))?;
+    let mut bumped: Vec<BumpedRequirement> = vec![];
+    let mut updated_lockfiles: Vec<PathBuf> = vec![];
+    // Workspaces nested in another one are appended as they are found
+    let mut index = 0;
+    while let Some(root) = roots.get(index).cloned() {
+        index += 1;
+        let metadata = utils::cargo_metadata(&root, false)?;
+        roots.extend(utils::foreign_manifests(&root, &metadata).nested_workspaces);
+        let mut manifests: Vec<PathBuf> = metadata
+            .workspace_packages()
+            .iter()
+            .map(|p| p.manifest_path.clone().into_std_path_buf())
+            .collect();
+        let root_manifest = root.join("Cargo.toml");
+        if !manifests.contains(&root_manifest) {
+            manifests.push(root_manifest);
+        }
+        let mut workspace_changed = false;
+        for manifest in manifests {
+            let mut document: DocumentMut = fs::read_to_string(&manifest)?
+                .parse()
+                .with_context(|| format!("Could not parse {}", manifest.to_string_lossy()))?;
+            let manifest_bumped = bump_document(
+                &mut document,
+                &options.package,
+                options.registry.as_deref(),
+                &options.version,
+            );
+            if manifest_bumped.is_empty() {
+                continue;
+            }
+            workspace_changed = true;
+            if !options.dry_run {
+                fs::write(&manifest, document.to_string())?;
+            }
+            let relative = manifest
+                .strip_prefix(&working_directory)
+                .unwrap_or(&manifest)
+                .to_path_buf();
+            bumped.extend(
+                manifest_bumped
+                    .into_iter()
+                    .map(|(table, from, to)| BumpedRequirement {
+                        manifest: relative.clone(),
+                        table,
+                        from,
+                        to,
+                    }),
+            );
+        }
+        if workspace_changed && !options.dry_run && !options.no_update_lockfiles {
+            let status = Command::new("cargo")
+                .args(["update", "--package", &options.package])
+                .current_dir(&root)
+                .status()?;
+            if !status.success() {
+                anyhow::bail!(
+                    "Could not update the lockfile of {}",
+                    root.to_string_lossy()
+                );
+            }
+            updated_lockfiles.push(root.join("Cargo.lock"));
+        }
+    }
+    let pushed_branch = match &options.push_branch {
+        Some(branch) if !options.dry_run && !bumped.is_empty() => {
+            let mut changed: Vec<PathBuf> = bumped.iter().map(|b| b.manifest.clone()).collect();
+            changed.extend(updated_lockfiles.iter().map(|l| {
+                l.strip_prefix(&working_directory)
+                    .unwrap_or(l)
+                    .to_path_buf()
+            }));
+            push_follow_up(
+                &working_directory,
+                &changed,
+                branch,
+                &options.remote,
+                &format!(
+                    "Bump requirements on {} to {}",
+                    options.package, options.version
+                ),
+            )?;
+            Some(branch.clone())
+        }
+        _ => None,
+    };
+    Ok(BumpDependentsResult {
+        package: options.package,
+        version: options.version.to_string(),
+        bumped,
+        updated_lockfiles,
+        pushed_branch,
+        dry_run: options.dry_run,
+    })
+}
+
+/// Run git, returning its trimmed output
+fn git(working_directory: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(working_directory)
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!("`git {}` failed", args.join(" "));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Commit `changed` on `branch`, started from the current `HEAD`, and push it to `remote`, which
+/// must not have that branch yet. The original `HEAD` is checked out again afterwards.
+fn push_follow_up(
+    working_directory: &Path,
+    changed: &[PathBuf],
+    branch: &str,
+    remote: &str,
+    message: &str,
+) -> anyhow::Result<()> {
+    let original = match git(working_directory, &["rev-parse", "--abbrev-ref", "HEAD"])? {
+        detached if detached == "HEAD" => git(working_directory, &["rev-parse", "HEAD"])?,
+        branch => branch,
+    };
+    git(working_directory, &["checkout", "-B", branch])?;
+    let mut add = vec!["add".to_string(), "--".to_string()];
+    add.extend(changed.iter().map(|c| c.to_string_lossy().to_string()));
+    let pushed = git(
+        working_directory,
+        &add.iter().map(String::as_str).collect::<Vec<_>>(),
+    )
+    .and_then(|_| git(working_directory, &["commit", "--message", message]))
+    .and_then(|_| {
+        // An empty expected value makes the push fail if someone already pushed the branch
+        git(
+            working_directory,
+            &[
+                "push",
+                &format!("--force-with-lease=refs/heads/{}:", branch),
+                remote,
+                &format!("HEAD:refs/heads/{}", branch),
+            ],
+        )
+    });
+    git(working_directory, &["checkout", "--quiet", &original])?;
+    pushed.map(|_| ()).with_context(|| {
+        format!(
+            "Could not push {}, it may already exist on {}",
+            branch, remote
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::TempDir;
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn test_bump_document() {
+        let mut document: DocumentMut = indoc! {r#"
+            [package]
+            name = "server"
+
+            [dependencies]
+            hub-core = "0.3" # shared types
+            other = "1.0"
+            renamed = { package = "hub-core", version = "=0.3.1", registry = "foresight" }
+
+            [dev-dependencies]
+            hub-core = { version = "0.4", features = ["test"] }
+
+            [target.'cfg(unix)'.build-dependencies]
+            hub-core = "~0.3.0"
+
+            [workspace.dependencies]
+            hub-core = { version = "0.3", default-features = false }
+        "#}
+        .parse()
+        .expect("Could not parse manifest");
+        let version = Version::parse("0.4.2").expect("Invalid version");
+        let bumped = bump_document(&mut document, "hub-core", Some("foresight"), &version);
+        assert_eq!(
+            bumped,
+            vec![(
+                "dependencies".to_string(),
+                "=0.3.1".to_string(),
+                "=0.4.2".to_string()
+            )]
+        );
+        let bumped = bump_document(&mut document, "hub-core", None, &version);
+        assert_eq!(
+            bumped,
+            vec![
+                (
+                    "dependencies".to_string(),
+                    "0.3".to_string(),
+                    "0.4.2".to_string()
+                ),
+                (
+                    "workspace.dependencies".to_string(),
+                    "0.3".to_string(),
+                    "0.4.2".to_string()
+                ),
+                (
+                    "target.cfg(unix).build-dependencies".to_string(),
+                    "~0.3.0".to_string(),
+                    "~0.4.2".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            document.to_string(),
+            indoc! {r#"
+                [package]
+                name = "server"
+
+                [dependencies]
+                hub-core = "0.4.2" # shared types
+                other = "1.0"
+                renamed = { package = "hub-core", version = "=0.4.2", registry = "foresight" }
+
+                [dev-dependencies]
+                hub-core = { version = "0.4", features = ["test"] }
+
+                [target.'cfg(unix)'.build-dependencies]
+                hub-core = "~0.4.2"
+
+                [workspace.dependencies]
+                hub-core = { version = "0.4.2", default-features = false }
+            "#}
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bump_dependents_nested_workspaces() {
+        let tmp = TempDir::new().expect("Could not create temp dir");
+        let root = tmp.path().canonicalize().expect("Could not canonicalize");
+        let package = |name: &str| {
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\n\n[dependencies]\nhub-core = \"0.3\"\n",
+                name
+            )
+        };
+        for (manifest, content) in [
+            (
+                "Cargo.toml",
+                "[workspace]\nmembers = [\"server\"]\nexclude = [\"tools\"]\n".to_string(),
+            ),
+            ("server/Cargo.toml", package("server")),
+            (
+                "tools/Cargo.toml",
+                "[workspace]\nmembers = [\"cli\"]\n".to_string(),
+            ),
+            ("tools/cli/Cargo.toml", package("cli")),
+        ] {
+            let path = root.join(manifest);
+            fs::create_dir_all(path.parent().expect("No parent").join("src"))
+                .expect("Could not create src");
+            fs::write(path.parent().expect("No parent").join("src/lib.rs"), "")
+                .expect("Could not write lib.rs");
+            fs::write(path, content).expect("Could not write manifest");
+        }
+        let result = bump_dependents(
+            Box::new(Options {
+                package: "hub-core".to_string(),
+                version: Version::parse("0.4.0").expect("Invalid version"),
+                no_update_lockfiles: true,
+                registry: None,
+                push_branch: None,
+                remote: "origin".to_string(),
+                dry_run: true,
+            }),
+            root.clone(),
+        )
+        .await
+        .expect("Could not bump dependents");
+        let manifests: Vec<PathBuf> = result.bumped.into_iter().map(|b| b.manifest).collect();
+        assert_eq!(
+            manifests,
+            vec![
+                PathBuf::from("server/Cargo.toml"),
+                PathBuf::from("tools/cli/Cargo.toml")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_push_follow_up() {
+        let remote = TempDir::new().expect("Could not create temp dir");
+        let tmp = TempDir::new().expect("Could not create temp dir");
+        let path = tmp.path();
+        git(remote.path(), &["init", "--bare", "--quiet"]).expect("Could not init remote");
+        git(path, &["init", "--quiet"]).expect("Could not init repository");
+        git(path, &["config", "user.name", "test"]).expect("Could not configure git");
+        git(path, &["config", "user.email", "test@example.com"]).expect("Could not configure git");
+        fs::write(path.join("Cargo.toml"), "[package]\n").expect("Could not write manifest");
+        fs::write(path.join("untracked"), "").expect("Could not write file");
+        git(path, &["add", "Cargo.toml"]).expect("Could not add manifest");
+        git(path, &["commit", "--quiet", "--message", "init"]).expect("Could not commit");
+        let original =
+            git(path, &["rev-parse", "--abbrev-ref", "HEAD"]).expect("Could not read HEAD");
+        git(
+            path,
+            &["remote", "add", "origin", &remote.path().to_string_lossy()],
+        )
+        .expect("Could not add remote");
+        fs::write(path.join("Cargo.toml"), "[package]\nname = \"server\"\n")
+            .expect("Could not write manifest");
+        push_follow_up(
+            path,
+            &[PathBuf::from("Cargo.toml")],
+            "bump/hub-core",
+            "origin",
+            "Bump requirements on hub-core to 0.4.2",
+        )
+        .expect("Could not push");
+        let output = Command::new("git")
+            .args(["log", "--format=%s", "--name-only", "bump/hub-core", "-1"])
+            .current_dir(remote.path())
+            .output()
+            .expect("Could not read remote log");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            "Bump requirements on hub-core to 0.4.2\n\nCargo.toml"
+        );
+        assert_eq!(
+            git(path, &["rev-parse", "--abbrev-ref", "HEAD"]).expect("Could not read HEAD"),
+            original
+        );
+        // The follow-up branch on the remote is never overwritten
+        fs::write(path.join("Cargo.toml"), "[package]\nname = \"other\"\n")
+            .expect("Could not write manifest");
+        assert!(push_follow_up(
+            path,
+            &[PathBuf::from("Cargo.toml")],
+            "bump/hub-core",
+            "origin",
+            "Bump requirements on hub-core to 0.4.3",
+        )
+        .is_err());
+        assert_eq!(
+            git(path, &["rev-parse", "--abbrev-ref", "HEAD"]).expect("Could not read HEAD"),
+            original
+        );
+    }
+}
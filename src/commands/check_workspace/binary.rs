@@ -3,9 +3,12 @@ use object_store::{
     path::Path,
     ObjectStore,
 };
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+use crate::http_client::{ca_bundle, Proxies};
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct PackageMetadataFslabsCiPublishBinary {
     #[serde(default)]
@@ -23,7 +26,7 @@ pub struct PackageMetadataFslabsCiPublishBinary {
     pub targets: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct PackageMetadataFslabsCiPublishBinaryLauncher {
     #[serde(default = "default_launcher_path")]
@@ -38,7 +41,7 @@ impl Default for PackageMetadataFslabsCiPublishBinaryLauncher {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct PackageMetadataFslabsCiPublishBinaryInstaller {
     #[serde(default = "default_installer_path")]
@@ -71,7 +74,7 @@ fn default_installer_path() -> String {
     "installer".to_string()
 }
 
-#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct PackageMetadataFslabsCiPublishBinaryInstallerReleaseChannel {
     pub upgrade_code: Option<String>,
@@ -132,13 +135,25 @@ impl BinaryStore {
         access_key: Option<String>,
     ) -> anyhow::Result<Option<Self>> {
         match (storage_account, container_name, access_key) {
-            (Some(storage_account), Some(container_name), Some(access_key)) => Ok(Some(Self {
-                client: MicrosoftAzureBuilder::new()
+            (Some(storage_account), Some(container_name), Some(access_key)) => {
+                let mut builder = MicrosoftAzureBuilder::new()
                     .with_account(storage_account)
                     .with_access_key(access_key)
-                    .with_container_name(container_name)
-                    .build()?,
-            })),
+                    .with_container_name(container_name);
+                let proxies = Proxies::from_env();
+                if let Some(proxy) = proxies.https {
+                    builder = builder.with_proxy_url(proxy);
+                    if !proxies.no_proxy.is_empty() {
+                        builder = builder.with_proxy_excludes(proxies.no_proxy.join(","));
+                    }
+                    if let Some(bundle) = ca_bundle()? {
+                        builder = builder.with_proxy_ca_certificate(String::from_utf8(bundle)?);
+                    }
+                }
+                Ok(Some(Self {
+                    client: builder.build()?,
+                }))
+            }
             _ => Ok(None),
         }
     }
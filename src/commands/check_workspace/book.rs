@@ -0,0 +1,60 @@
+use object_store::{path::Path, ObjectStore};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::check_workspace::binary::BinaryStore;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct PackageMetadataFslabsCiPublishBook {
+    #[serde(default)]
+    pub publish: bool,
+    /// Path of the mdBook, relative to the package
+    #[serde(default = "default_book_path")]
+    pub path: String,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl Default for PackageMetadataFslabsCiPublishBook {
+    fn default() -> Self {
+        Self {
+            publish: false,
+            path: default_book_path(),
+            error: None,
+        }
+    }
+}
+
+fn default_book_path() -> String {
+    "book".to_string()
+}
+
+impl PackageMetadataFslabsCiPublishBook {
+    pub async fn check(
+        &mut self,
+        name: String,
+        version: String,
+        store: &Option<BinaryStore>,
+        release_channel: String,
+    ) -> anyhow::Result<()> {
+        if !self.publish {
+            return Ok(());
+        }
+        let Some(object_store) = store else {
+            return Ok(());
+        };
+        log::debug!(
+            "BOOK: checking if book of version {} of {} already exists for channel {}",
+            version,
+            name,
+            release_channel
+        );
+        let index_path = Path::from(format!(
+            "book/{}/{}/{}/index.html",
+            name, release_channel, version
+        ));
+        self.publish = object_store.get_client().head(&index_path).await.is_err();
+        Ok(())
+    }
+}
@@ -1,19 +1,39 @@
 use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
 
 use anyhow::Context;
+use cargo_metadata::semver::Version;
+use cargo_metadata::Package;
 use http_body_util::BodyExt;
 use http_body_util::Empty;
 use hyper::body::Bytes;
 use hyper::{Method, Request, Uri};
-use hyper_rustls::{ConfigBuilderExt, HttpsConnector};
-use hyper_util::client::legacy::connect::HttpConnector;
 use hyper_util::client::legacy::Client as HyperClient;
 use hyper_util::rt::TokioExecutor;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-const CARGO_DEFAULT_API_URL: &str = "https://crates.io/api/v1/crates/";
+use crate::http_client::{https_connector, HttpsClientConnector};
+use crate::utils::same_version;
 
-#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+const CARGO_DEFAULT_API_URL: &str = "https://crates.io/api/v1/crates/";
+/// crates.io rejects `.crate` archives larger than 10MB
+const CARGO_MAX_PACKAGE_SIZE: u64 = 10 * 1024 * 1024;
+/// Single files above this size are most likely not meant to be packaged
+const CARGO_MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// File names and extensions that should never end up in a published crate
+const CARGO_SECRET_FILES: &[&str] = &[
+    ".env",
+    ".npmrc",
+    "credentials",
+    "credentials.toml",
+    "id_rsa",
+    "id_ed25519",
+];
+const CARGO_SECRET_EXTENSIONS: &[&str] = &[".pem", ".key", ".p12", ".pfx"];
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct PackageMetadataFslabsCiPublishCargo {
     #[serde(default)]
@@ -21,7 +41,11 @@ pub struct PackageMetadataFslabsCiPublishCargo {
     pub registry: Option<Vec<String>>,
     #[serde(default)]
     pub allow_public: bool,
+    /// Registries pre-release versions (e.g. `1.2.0-rc.1`) may be published to, any when unset
+    pub prerelease_registries: Option<Vec<String>>,
     pub error: Option<String>,
+    #[serde(skip_deserializing)]
+    pub validation_errors: Vec<String>,
 }
 
 impl PackageMetadataFslabsCiPublishCargo {
@@ -49,6 +73,21 @@ impl PackageMetadataFslabsCiPublishCargo {
             return Ok(());
         }
         let registry_name = registries.first().unwrap().clone();
+        let is_prerelease = Version::parse(&version)
+            .map(|v| !v.pre.is_empty())
+            .unwrap_or(false);
+        if let (true, Some(allowed)) = (is_prerelease, &self.prerelease_registries) {
+            if !allowed.contains(&registry_name) {
+                self.publish = false;
+                self.error = Some(format!(
+                    "Pre-release {} of {} can only be published to {}",
+                    version,
+                    name,
+                    allowed.join(", ")
+                ));
+                return Ok(());
+            }
+        }
         log::debug!(
             "CARGO: checking if version {} of {} already exists for registry {}",
             version,
@@ -61,23 +100,199 @@ impl PackageMetadataFslabsCiPublishCargo {
         // We are sure that there is only one
         Ok(())
     }
+
+    /// Check that the manifest carries the fields registries expect on published crates
+    pub fn validate_metadata(&mut self, package: &Package) {
+        let fields = [
+            ("description", package.description.is_some()),
+            (
+                "license",
+                package.license.is_some() || package.license_file.is_some(),
+            ),
+            ("repository", package.repository.is_some()),
+            ("readme", package.readme.is_some()),
+        ];
+        for (field, present) in fields {
+            if !present {
+                self.validation_errors
+                    .push(format!("missing `{}` field", field));
+            }
+        }
+    }
+
+    /// Check the files that `cargo package` would ship for secrets and huge files, and the size
+    /// of the compressed `.crate` against the registry limit
+    pub fn validate_package(
+        &mut self,
+        manifest_dir: &Path,
+        name: &str,
+        version: &str,
+    ) -> anyhow::Result<()> {
+        let output = Command::new("cargo")
+            .args(["package", "--list", "--allow-dirty"])
+            .current_dir(manifest_dir)
+            .output()
+            .with_context(|| "Could not run cargo package --list")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "cargo package --list failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        let files: Vec<(String, u64)> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|file| {
+                // Generated files such as `Cargo.toml.orig` do not exist on disk
+                let size = manifest_dir
+                    .join(file)
+                    .metadata()
+                    .map(|m| m.len())
+                    .unwrap_or(0);
+                (file.to_string(), size)
+            })
+            .collect();
+        self.validation_errors.extend(check_package_files(&files));
+        // The limit applies to the archive, only building it tells its size. Each run gets its own
+        // target dir, so concurrent runs and same-named crates do not clobber each other.
+        let target_dir = tempfile::Builder::new()
+            .prefix(&format!("fslabscli-package-{}-", name))
+            .tempdir()?;
+        let output = Command::new("cargo")
+            .args(["package", "--no-verify", "--allow-dirty", "--target-dir"])
+            .arg(target_dir.path())
+            .current_dir(manifest_dir)
+            .output()
+            .with_context(|| "Could not run cargo package")?;
+        let crate_file = target_dir
+            .path()
+            .join("package")
+            .join(format!("{}-{}.crate", name, version));
+        let size = crate_file.metadata().map(|m| m.len());
+        if !output.status.success() {
+            anyhow::bail!(
+                "cargo package failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        self.validation_errors.extend(check_crate_size(
+            size.with_context(|| format!("Could not find {}", crate_file.to_string_lossy()))?,
+        ));
+        Ok(())
+    }
+}
+
+fn check_package_files(files: &[(String, u64)]) -> Vec<String> {
+    let mut errors = vec![];
+    for (file, size) in files {
+        let file_name = Path::new(file)
+            .file_name()
+            .map(|f| f.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if CARGO_SECRET_FILES.contains(&file_name.as_str())
+            || file_name.starts_with(".env.")
+            || CARGO_SECRET_EXTENSIONS
+                .iter()
+                .any(|e| file_name.ends_with(e))
+        {
+            errors.push(format!("`{}` looks like a secret", file));
+        }
+        if *size > CARGO_MAX_FILE_SIZE {
+            errors.push(format!("`{}` is too large ({} bytes)", file, size));
+        }
+    }
+    errors
+}
+
+fn check_crate_size(size: u64) -> Option<String> {
+    (size > CARGO_MAX_PACKAGE_SIZE).then(|| {
+        format!(
+            "package is too large ({} bytes compressed, limit is {} bytes)",
+            size, CARGO_MAX_PACKAGE_SIZE
+        )
+    })
+}
+
+/// How a registry exposes the published versions of a crate
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CargoRegistryKind {
+    /// crates.io-like web api (`{url}{name}` returning the crate versions)
+    #[default]
+    Api,
+    /// Sparse index protocol (`sparse+{url}`), as consumed by cargo itself
+    Sparse,
 }
 
 #[derive(Serialize, Deserialize, Clone, Default, Debug)]
 pub struct CargoRegistry {
     pub crate_url: String,
+    /// Sent as `User-Agent`, some private registries identify clients with it
+    pub user_agent: Option<String>,
+    /// Sent as `Authorization`, like cargo does for token-only registries
     pub token: Option<String>,
+    pub kind: CargoRegistryKind,
 }
 
 impl CargoRegistry {
-    pub fn new(crate_url: String, token: Option<String>) -> Self {
-        Self { crate_url, token }
+    pub fn new(
+        crate_url: String,
+        user_agent: Option<String>,
+        token: Option<String>,
+    ) -> anyhow::Result<Self> {
+        // Reading a git index needs a clone of it, point to the sparse index or the api instead
+        if ["git+", "git://", "ssh://", "file://"]
+            .iter()
+            .any(|p| crate_url.starts_with(p))
+            || crate_url.trim_end_matches('/').ends_with(".git")
+        {
+            anyhow::bail!(
+                "Git index registries are not supported ({}), use the registry sparse index (`sparse+https://...`) or api url",
+                crate_url
+            );
+        }
+        Ok(match crate_url.strip_prefix("sparse+") {
+            Some(index_url) => Self {
+                crate_url: index_url.to_string(),
+                user_agent,
+                token,
+                kind: CargoRegistryKind::Sparse,
+            },
+            None => Self {
+                crate_url,
+                user_agent,
+                token,
+                kind: CargoRegistryKind::Api,
+            },
+        })
+    }
+
+    fn user_agent(&self) -> String {
+        self.user_agent
+            .clone()
+            .unwrap_or_else(|| "fslabsci".to_string())
+    }
+
+    /// Url of the crate file in a sparse index, following cargo's index layout
+    fn sparse_index_url(&self, name: &str) -> String {
+        let name = name.to_lowercase();
+        let prefix = match name.len() {
+            1 => "1".to_string(),
+            2 => "2".to_string(),
+            3 => format!("3/{}", &name[..1]),
+            _ => format!("{}/{}", &name[..2], &name[2..4]),
+        };
+        format!(
+            "{}/{}/{}",
+            self.crate_url.trim_end_matches('/'),
+            prefix,
+            name
+        )
     }
 }
 
 pub struct Cargo {
     registries: HashMap<String, CargoRegistry>,
-    client: HyperClient<HttpsConnector<HttpConnector>, Empty<Bytes>>,
+    client: HyperClient<HttpsClientConnector, Empty<Bytes>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Default, Debug)]
@@ -107,20 +322,12 @@ struct CargoSearchResult {
 
 impl Cargo {
     pub fn new(crates_io_token: Option<String>) -> anyhow::Result<Self> {
-        let https = hyper_rustls::HttpsConnectorBuilder::new()
-            .with_tls_config(
-                rustls::ClientConfig::builder()
-                    .with_native_roots()?
-                    .with_no_client_auth(),
-            )
-            .https_or_http()
-            .enable_http1()
-            .build();
+        let https = https_connector()?;
 
         let mut registries = HashMap::new();
         registries.insert(
             "default".to_string(),
-            CargoRegistry::new(CARGO_DEFAULT_API_URL.to_string(), crates_io_token),
+            CargoRegistry::new(CARGO_DEFAULT_API_URL.to_string(), None, crates_io_token)?,
         );
         Ok(Self {
             client: HyperClient::builder(TokioExecutor::new()).build(https),
@@ -132,9 +339,10 @@ impl Cargo {
         &mut self,
         name: String,
         crate_url: String,
+        user_agent: Option<String>,
         token: Option<String>,
     ) -> anyhow::Result<()> {
-        let reg = CargoRegistry::new(crate_url, token);
+        let reg = CargoRegistry::new(crate_url, user_agent, token)?;
         self.registries.insert(name, reg);
         Ok(())
     }
@@ -149,29 +357,80 @@ impl Cargo {
             .registries
             .get(&registry_name)
             .ok_or_else(|| anyhow::anyhow!("unknown registry"))?;
-        let url: Uri = format!("{}{}", registry.crate_url, name).parse()?;
+        match registry.kind {
+            CargoRegistryKind::Api => self.check_crate_exists_api(registry, name, version).await,
+            CargoRegistryKind::Sparse => {
+                self.check_crate_exists_sparse(registry, name, version)
+                    .await
+            }
+        }
+    }
 
-        let user_agent = registry
-            .token
-            .clone()
-            .unwrap_or_else(|| "fslabsci".to_string());
+    async fn check_crate_exists_sparse(
+        &self,
+        registry: &CargoRegistry,
+        name: String,
+        version: String,
+    ) -> anyhow::Result<bool> {
+        let url: Uri = registry.sparse_index_url(&name).parse()?;
+        let mut req = Request::builder()
+            .method(Method::GET)
+            .uri(url)
+            .header("User-Agent", registry.user_agent());
+        // Token-only registries authenticate index reads like cargo does
+        if let Some(token) = &registry.token {
+            req = req.header("Authorization", token.clone());
+        }
+        let res = self
+            .client
+            .request(req.body(Empty::default())?)
+            .await
+            .with_context(|| "Could not fetch from the crates registry index")?;
+        // Unknown crates are not present in the index
+        if res.status().as_u16() == 404 || res.status().as_u16() == 410 {
+            return Ok(false);
+        }
+        if res.status().as_u16() >= 400 {
+            anyhow::bail!("Something went wrong while getting cargo index data");
+        }
+        let body = res
+            .into_body()
+            .collect()
+            .await
+            .with_context(|| "Could not get body from the crates registry index")?
+            .to_bytes();
+        Ok(String::from_utf8_lossy(&body)
+            .lines()
+            .filter_map(|l| serde_json::from_str::<CargoPackageVersion>(l).ok())
+            .any(|v| same_version(&v.version, &version)))
+    }
+
+    async fn check_crate_exists_api(
+        &self,
+        registry: &CargoRegistry,
+        name: String,
+        version: String,
+    ) -> anyhow::Result<bool> {
+        let url: Uri = format!("{}{}", registry.crate_url, name).parse()?;
 
-        let req = Request::builder()
+        let mut req = Request::builder()
             .method(Method::GET)
             .uri(url.clone())
             .header("Content-Type", "application/json")
             .header("Accept", "application/json")
-            .header("User-Agent", user_agent.clone())
-            .body(Empty::default())?;
+            .header("User-Agent", registry.user_agent());
+        if let Some(token) = &registry.token {
+            req = req.header("Authorization", token.clone());
+        }
 
         let res = self
             .client
-            .request(req)
+            .request(req.body(Empty::default())?)
             .await
             .with_context(|| "Could not fetch from the crates registry")?;
 
         if res.status().as_u16() >= 400 {
-            anyhow::bail!("Something went wrong while getting npm api data");
+            anyhow::bail!("Something went wrong while getting cargo api data");
         }
 
         let body = res
@@ -206,7 +465,7 @@ impl Cargo {
 
         if let Some(package) = package {
             for package_version in package.versions {
-                if package_version.version == version {
+                if same_version(&package_version.version, &version) {
                     return Ok(true);
                 }
             }
@@ -259,6 +518,7 @@ mod tests {
                     registry.clone(),
                     format!("{}/{}", mock_server_uri, prefix),
                     registry_user_agent,
+                    None,
                 )
                 .expect("could not add private registry");
         }
@@ -277,6 +537,110 @@ mod tests {
         }
     }
 
+    #[test]
+    fn cargo_package_files_validation() {
+        let files = vec![
+            ("Cargo.toml".to_string(), 1024),
+            ("src/lib.rs".to_string(), 2048),
+            ("src/credentials.rs".to_string(), 2048),
+        ];
+        assert!(check_package_files(&files).is_empty());
+        let files = vec![
+            ("Cargo.toml".to_string(), 1024),
+            (".env".to_string(), 12),
+            ("certs/server.pem".to_string(), 12),
+            ("assets/model.bin".to_string(), CARGO_MAX_PACKAGE_SIZE + 1),
+        ];
+        let errors = check_package_files(&files);
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].contains(".env"));
+        assert!(errors[1].contains("server.pem"));
+        assert!(errors[2].contains("model.bin"));
+        // Uncompressed files above the limit are fine as long as the archive is not
+        assert_eq!(check_crate_size(CARGO_MAX_PACKAGE_SIZE), None);
+        assert!(check_crate_size(CARGO_MAX_PACKAGE_SIZE + 1)
+            .expect("Crate should be too large")
+            .starts_with("package is too large"));
+    }
+
+    #[test]
+    fn cargo_registry_kind_detection() {
+        let api = CargoRegistry::new(CARGO_DEFAULT_API_URL.to_string(), None, None)
+            .expect("Could not create registry");
+        assert_eq!(api.kind, CargoRegistryKind::Api);
+        let sparse = CargoRegistry::new("sparse+https://index.crates.io/".to_string(), None, None)
+            .expect("Could not create registry");
+        assert_eq!(sparse.kind, CargoRegistryKind::Sparse);
+        assert_eq!(sparse.crate_url, "https://index.crates.io/");
+        assert_eq!(sparse.sparse_index_url("a"), "https://index.crates.io/1/a");
+        assert_eq!(
+            sparse.sparse_index_url("ab"),
+            "https://index.crates.io/2/ab"
+        );
+        assert_eq!(
+            sparse.sparse_index_url("Abc"),
+            "https://index.crates.io/3/a/abc"
+        );
+        assert_eq!(
+            sparse.sparse_index_url("hub_app"),
+            "https://index.crates.io/hu/b_/hub_app"
+        );
+        for git_index in [
+            "https://github.com/rust-lang/crates.io-index.git",
+            "ssh://git@github.com/org/index",
+            "git+https://github.com/org/index",
+        ] {
+            assert!(CargoRegistry::new(git_index.to_string(), None, None).is_err());
+        }
+    }
+
+    async fn cargo_sparse_test(package_version: &str, status: u16, expected_result: bool) {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/index/hu/b_/hub_app"))
+            .and(header("Authorization", "my_token"))
+            .and(header("User-Agent", "shipyard my_user_agent"))
+            .respond_with(ResponseTemplate::new(status).set_body_raw(
+                "{\"name\":\"hub_app\",\"vers\":\"0.1.0\",\"deps\":[]}\n{\"name\":\"hub_app\",\"vers\":\"0.2.0\",\"deps\":[]}\n",
+                "text/plain",
+            ))
+            .mount(&mock_server)
+            .await;
+        let mut cargo = Cargo::new(None).expect("Could not create cargo instance");
+        cargo
+            .add_registry(
+                "sparse".to_string(),
+                format!("sparse+{}/index/", mock_server.uri()),
+                Some("shipyard my_user_agent".to_string()),
+                Some("my_token".to_string()),
+            )
+            .expect("could not add sparse registry");
+        let exists = cargo
+            .check_crate_exists(
+                "sparse".to_string(),
+                "hub_app".to_string(),
+                package_version.to_string(),
+            )
+            .await
+            .expect("Could not check sparse registry");
+        assert_eq!(exists, expected_result);
+    }
+
+    #[tokio::test]
+    async fn cargo_existing_crate_and_version_sparse_reg() {
+        cargo_sparse_test("0.2.0", 200, true).await;
+    }
+
+    #[tokio::test]
+    async fn cargo_existing_crate_and_not_version_sparse_reg() {
+        cargo_sparse_test("99.99.99", 200, false).await;
+    }
+
+    #[tokio::test]
+    async fn cargo_non_existing_crate_sparse_reg() {
+        cargo_sparse_test("0.2.0", 404, false).await;
+    }
+
     #[tokio::test]
     async fn cargo_existing_crate_and_version() {
         cargo_test(
@@ -336,6 +700,23 @@ mod tests {
         )
         .await;
     }
+
+    #[tokio::test]
+    async fn cargo_prerelease_to_undesignated_registry() {
+        let cargo = Cargo::new(None).expect("Could not get cargo client");
+        let mut publish = PackageMetadataFslabsCiPublishCargo {
+            publish: true,
+            registry: Some(vec!["public".to_string()]),
+            prerelease_registries: Some(vec!["foresight".to_string()]),
+            ..Default::default()
+        };
+        publish
+            .check("hub_app".to_string(), "0.5.0-rc.1".to_string(), &cargo)
+            .await
+            .expect("Could not check crate");
+        assert!(!publish.publish);
+        assert!(publish.error.is_some());
+    }
     //
     // #[tokio::test]
     // async fn npm_package_existing_package_custom_registry_npmrc() {
@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use git2::{AttrCheckFlags, FileMode, Repository};
+
+/// What a changed path of the diff is
+#[derive(Debug, PartialEq)]
+pub enum ChangeKind {
+    File,
+    /// Submodule pointer update
+    Submodule,
+    /// Pointer of an LFS tracked file
+    Lfs,
+}
+
+pub fn change_kind(repository: &Repository, path: &Path, mode: FileMode) -> ChangeKind {
+    if mode == FileMode::Commit {
+        return ChangeKind::Submodule;
+    }
+    match repository.get_attr(path, "filter", AttrCheckFlags::default()) {
+        Ok(Some("lfs")) => ChangeKind::Lfs,
+        _ => ChangeKind::File,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_change_kind() {
+        let tmp = TempDir::new().expect("Could not create temp dir");
+        let repository = Repository::init(tmp.path()).expect("Could not init repository");
+        tmp.child(".gitattributes")
+            .write_str("*.glb filter=lfs diff=lfs merge=lfs -text\n")
+            .expect("Could not write .gitattributes");
+        assert_eq!(
+            change_kind(&repository, Path::new("assets/model.glb"), FileMode::Blob),
+            ChangeKind::Lfs
+        );
+        assert_eq!(
+            change_kind(&repository, Path::new("src/lib.rs"), FileMode::Blob),
+            ChangeKind::File
+        );
+        assert_eq!(
+            change_kind(&repository, Path::new("vendor/sdk"), FileMode::Commit),
+            ChangeKind::Submodule
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use indexmap::IndexMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Publish target declared by the package itself, run as shell commands from the package directory
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
+pub struct PackageMetadataFslabsCiPublishCustom {
+    pub name: String,
+    /// Declaring the target is enough to publish it, set to `false` to turn it off
+    #[serde(default = "default_publish")]
+    pub publish: bool,
+    /// Command run before publishing, also run on pull requests as a dry run
+    pub build_command: Option<String>,
+    /// Command succeeding when the version is already published, to skip `publish_command`.
+    /// Without it, `publish_command` runs on every publish and must be idempotent.
+    pub check_command: Option<String>,
+    pub publish_command: String,
+    #[serde(default)]
+    pub env: Option<IndexMap<String, String>>,
+}
+
+fn default_publish() -> bool {
+    true
+}
+
+impl PackageMetadataFslabsCiPublishCustom {
+    /// `name` usable in a workflow job key: lowercased, with characters other than ascii
+    /// alphanumerics, `-` and `_` replaced by `_`
+    pub fn job_suffix(&self) -> String {
+        self.name
+            .chars()
+            .map(
+                |c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    true => c.to_ascii_lowercase(),
+                    false => '_',
+                },
+            )
+            .collect()
+    }
+
+    /// Script of the publish step, skipping `publish_command` when `check_command` succeeds
+    pub fn publish_script(&self) -> String {
+        match &self.check_command {
+            Some(check_command) => format!(
+                "if {}; then\n  echo \"{} is already published\"\nelse\n  {}\nfi",
+                check_command, self.name, self.publish_command
+            ),
+            None => self.publish_command.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_target() {
+        let custom: PackageMetadataFslabsCiPublishCustom = toml::from_str(
+            "name = \"Helm Chart\"\ncheck_command = \"helm show chart oci://x\"\npublish_command = \"helm push\"\n",
+        )
+        .expect("Could not parse custom target");
+        assert!(custom.publish);
+        assert_eq!(custom.job_suffix(), "helm_chart");
+        assert_eq!(
+            custom.publish_script(),
+            "if helm show chart oci://x; then\n  echo \"Helm Chart is already published\"\nelse\n  helm push\nfi"
+        );
+    }
+}
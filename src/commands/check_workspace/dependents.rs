@@ -0,0 +1,90 @@
+use std::collections::{BTreeSet, HashMap};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::check_workspace::graph::dependants_closure;
+use crate::commands::check_workspace::Result as Package;
+
+/// Packages depending on a package, used to evaluate the blast radius of a change
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug, PartialEq)]
+pub struct ResultDependents {
+    pub direct: usize,
+    pub transitive: usize,
+    /// Every direct and transitive dependent, omitted with `--hide-dependencies`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ids: Option<Vec<String>>,
+}
+
+/// Walk the `dependant` edges of every package to fill its `dependents`
+pub fn feed_dependents(packages: &mut HashMap<String, Package>, hide_ids: bool) {
+    let mut all_dependents: HashMap<String, (usize, BTreeSet<String>)> = HashMap::new();
+    for (key, package) in packages.iter() {
+        let mut seen = dependants_closure(packages, std::slice::from_ref(key));
+        seen.remove(key);
+        all_dependents.insert(key.clone(), (package.dependant.len(), seen));
+    }
+    for (key, (direct, ids)) in all_dependents {
+        if let Some(package) = packages.get_mut(&key) {
+            package.dependents = ResultDependents {
+                direct,
+                transitive: ids.len(),
+                ids: match hide_ids {
+                    true => None,
+                    false => Some(ids.into_iter().collect()),
+                },
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::check_workspace::ResultDependency;
+
+    use super::*;
+
+    fn package(name: &str, dependant: &[&str]) -> (String, Package) {
+        (
+            name.to_string(),
+            Package {
+                package: name.to_string(),
+                dependant: dependant
+                    .iter()
+                    .map(|d| ResultDependency {
+                        package: d.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_feed_dependents() {
+        let mut packages: HashMap<String, Package> = HashMap::from([
+            package("core", &["utils", "server"]),
+            package("utils", &["server", "cli"]),
+            package("server", &[]),
+            package("cli", &[]),
+        ]);
+        feed_dependents(&mut packages, false);
+        assert_eq!(
+            packages["core"].dependents,
+            ResultDependents {
+                direct: 2,
+                transitive: 3,
+                ids: Some(vec![
+                    "cli".to_string(),
+                    "server".to_string(),
+                    "utils".to_string()
+                ]),
+            }
+        );
+        assert_eq!(packages["server"].dependents.transitive, 0);
+        feed_dependents(&mut packages, true);
+        assert_eq!(packages["utils"].dependents.ids, None);
+        assert_eq!(packages["utils"].dependents.transitive, 2);
+    }
+}
@@ -0,0 +1,145 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ignore::WalkBuilder;
+use serde::Deserialize;
+
+use crate::commands::check_workspace::{
+    PackageMetadataFslabsCi, PackageMetadataFslabsCiTest, Result, ResultDependency,
+};
+
+pub const DESCRIPTOR_FILE_NAME: &str = "fslabs.toml";
+
+/// `fslabs.toml` describing a member without a `Cargo.toml`, e.g. a typescript package.
+/// Besides `name` and `version`, it holds what `package.metadata.fslabs` holds for cargo members.
+#[derive(Deserialize, Debug)]
+struct Descriptor {
+    name: String,
+    version: String,
+    /// Other members this one depends on
+    #[serde(default)]
+    dependencies: Vec<String>,
+    #[serde(flatten)]
+    fslabs: PackageMetadataFslabsCi,
+}
+
+/// Every `fslabs.toml` under `root` not excluded by `.gitignore` or `.fslabscliignore`
+pub fn find_descriptors(root: &Path) -> Vec<PathBuf> {
+    let mut descriptors: Vec<PathBuf> = WalkBuilder::new(root)
+        .add_custom_ignore_filename(".fslabscliignore")
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name() == DESCRIPTOR_FILE_NAME)
+        .map(|e| e.into_path())
+        .collect();
+    descriptors.sort();
+    descriptors
+}
+
+impl Result {
+    pub fn from_descriptor(descriptor_path: &Path, root_dir: &Path) -> anyhow::Result<Self> {
+        let descriptor: Descriptor = toml::from_str(&fs::read_to_string(descriptor_path)?)?;
+        let path = descriptor_path
+            .canonicalize()?
+            .parent()
+            .unwrap()
+            .to_path_buf();
+        let mut publish = descriptor.fslabs.publish.unwrap_or_default();
+        // Nothing to publish to a cargo registry
+        publish.cargo.registry = Some(vec![]);
+        publish.cargo.publish = false;
+        let workspace = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut path = path.strip_prefix(root_dir)?.to_path_buf();
+        if path.to_string_lossy().is_empty() {
+            path = PathBuf::from(".");
+        }
+        Ok(Self {
+            workspace,
+            package: descriptor.name,
+            version: descriptor.version,
+            path,
+            publish_detail: publish,
+            // There is no cargo test to run, unless the descriptor configures its tests
+            test_detail: descriptor
+                .fslabs
+                .test
+                .unwrap_or_else(|| PackageMetadataFslabsCiTest {
+                    skip: Some(true),
+                    ..Default::default()
+                }),
+            tags: descriptor.fslabs.tags,
+            owners: descriptor.fslabs.owners,
+            dependencies: descriptor
+                .dependencies
+                .into_iter()
+                .map(|d| ResultDependency {
+                    package: d,
+                    version: "*".to_string(),
+                    publishable: false,
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn test_from_descriptor() {
+        let tmp = TempDir::new().expect("Could not create temp dir");
+        let descriptor = tmp.child("web/hub-web/fslabs.toml");
+        descriptor
+            .write_str(indoc! {r#"
+                name = "hub-web"
+                version = "1.2.0"
+                dependencies = ["hub-api-client"]
+                tags = ["frontend"]
+
+                [publish.npm_napi]
+                publish = true
+            "#})
+            .expect("Could not write descriptor");
+        tmp.child("web/hub-web-e2e/fslabs.toml")
+            .write_str(indoc! {r#"
+                name = "hub-web-e2e"
+                version = "1.2.0"
+
+                [test]
+                command = "npm test"
+            "#})
+            .expect("Could not write descriptor");
+        tmp.child("target/fslabs.toml")
+            .write_str("name = \"ignored\"\nversion = \"0.1.0\"\n")
+            .expect("Could not write descriptor");
+        tmp.child(".fslabscliignore")
+            .write_str("target\n")
+            .expect("Could not write ignore file");
+        let root = tmp.path().canonicalize().expect("Could not canonicalize");
+        let descriptors = find_descriptors(&root);
+        assert_eq!(descriptors.len(), 2);
+        let result =
+            Result::from_descriptor(&descriptors[0], &root).expect("Could not load descriptor");
+        assert_eq!(result.package, "hub-web");
+        assert_eq!(result.workspace, "hub-web");
+        assert_eq!(result.path, PathBuf::from("web/hub-web"));
+        assert!(result.publish_detail.npm_napi.publish);
+        assert!(!result.publish_detail.cargo.publish);
+        assert_eq!(result.test_detail.skip, Some(true));
+        assert_eq!(result.dependencies[0].package, "hub-api-client");
+        assert_eq!(result.tags, vec!["frontend".to_string()]);
+        let e2e =
+            Result::from_descriptor(&descriptors[1], &root).expect("Could not load descriptor");
+        assert_eq!(e2e.test_detail.command.as_deref(), Some("npm test"));
+        assert_eq!(e2e.test_detail.skip, None);
+    }
+}
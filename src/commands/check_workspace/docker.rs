@@ -1,25 +1,29 @@
-use oci_distribution::client::{ClientConfig, ClientProtocol};
+use oci_distribution::client::{Certificate, CertificateEncoding, ClientConfig, ClientProtocol};
 use oci_distribution::errors::{OciDistributionError, OciErrorCode};
 use oci_distribution::secrets::RegistryAuth;
 use oci_distribution::{Client as DockerClient, Reference};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::env;
+use std::collections::{BTreeSet, HashMap};
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
+use std::{env, fs};
 
 use anyhow::Context;
 use base64::prelude::*;
 use http_body_util::{BodyExt, Full};
 use hyper::body::Bytes;
 use hyper::{Method, Request};
-use hyper_rustls::{ConfigBuilderExt, HttpsConnector};
-use hyper_util::client::legacy::connect::HttpConnector;
 use hyper_util::client::legacy::Client as HyperClient;
 use hyper_util::rt::TokioExecutor;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+use sha2::{Digest, Sha256};
 use url::Url;
 
+use crate::http_client::{ca_bundle, https_connector, HttpsClientConnector};
+
 #[derive(Serialize, Deserialize, Clone, Default, Debug)]
 struct DockerTokenResp {
     access_token: String,
@@ -37,7 +41,7 @@ struct DockerConfig {
 }
 
 pub struct Docker {
-    hyper_client: HyperClient<HttpsConnector<HttpConnector>, Full<Bytes>>,
+    hyper_client: HyperClient<HttpsClientConnector, Full<Bytes>>,
     docker_client: DockerClient,
     registries_auths: HashMap<String, DockerCredential>,
 }
@@ -51,15 +55,7 @@ enum DockerCredential {
 impl Docker {
     pub fn new(config_path: Option<String>) -> anyhow::Result<Self> {
         let mut registries_auths = HashMap::new();
-        let https = hyper_rustls::HttpsConnectorBuilder::new()
-            .with_tls_config(
-                rustls::ClientConfig::builder()
-                    .with_native_roots()?
-                    .with_no_client_auth(),
-            )
-            .https_or_http()
-            .enable_http1()
-            .build();
+        let https = https_connector()?;
         let config_path = match config_path {
             Some(c) => Some(c),
             None => {
@@ -124,6 +120,14 @@ impl Docker {
             hyper_client: HyperClient::builder(TokioExecutor::new()).build(https),
             docker_client: DockerClient::new(ClientConfig {
                 protocol: ClientProtocol::Https,
+                extra_root_certificates: ca_bundle()?
+                    .map(|data| {
+                        vec![Certificate {
+                            encoding: CertificateEncoding::Pem,
+                            data,
+                        }]
+                    })
+                    .unwrap_or_default(),
                 ..Default::default()
             }),
         })
@@ -134,22 +138,9 @@ impl Docker {
             .insert(name, DockerCredential::UsernamePassword(username, password));
     }
 
-    pub async fn check_image_exists(
-        &mut self,
-        registry_name: String,
-        name: String,
-        version: String,
-    ) -> anyhow::Result<bool> {
-        log::debug!(
-            "Docker: checking if version {} of {} already exists",
-            version,
-            name
-        );
-
-        let image: Reference =
-            format!("{}/{}:{}", registry_name, name.clone(), version.clone()).parse()?;
-
-        let auth = match self.registries_auths.get(&registry_name) {
+    /// Credentials to pull `name` from the given registry, exchanging identity tokens if needed
+    async fn registry_auth(&self, registry_name: &str, name: &str) -> anyhow::Result<RegistryAuth> {
+        let auth = match self.registries_auths.get(registry_name) {
             None => RegistryAuth::Anonymous,
             Some(docker_credential) => match docker_credential {
                 DockerCredential::UsernamePassword(username, password) => {
@@ -167,7 +158,7 @@ impl Docker {
                     let mut enc = ::url::form_urlencoded::Serializer::new("".to_owned());
 
                     enc.append_pair("grant_type", "refresh_token");
-                    enc.append_pair("service", registry_name.as_str());
+                    enc.append_pair("service", registry_name);
                     enc.append_pair("scope", format!("repository:{}:pull", name).as_str());
                     enc.append_pair("refresh_token", token.as_str());
                     let full_body = Full::new(Bytes::from(enc.finish()));
@@ -197,6 +188,25 @@ impl Docker {
                 }
             },
         };
+        Ok(auth)
+    }
+
+    pub async fn check_image_exists(
+        &mut self,
+        registry_name: String,
+        name: String,
+        version: String,
+    ) -> anyhow::Result<bool> {
+        log::debug!(
+            "Docker: checking if version {} of {} already exists",
+            version,
+            name
+        );
+
+        let image: Reference =
+            format!("{}/{}:{}", registry_name, name.clone(), version.clone()).parse()?;
+
+        let auth = self.registry_auth(&registry_name, &name).await?;
         match self
             .docker_client
             .fetch_manifest_digest(&image, &auth)
@@ -221,16 +231,146 @@ impl Docker {
             },
         }
     }
+
+    /// Manifest digest an image reference currently resolves to
+    pub async fn fetch_image_digest(&mut self, image: &str) -> anyhow::Result<String> {
+        let reference: Reference = image.parse()?;
+        let auth = self
+            .registry_auth(reference.registry(), reference.repository())
+            .await?;
+        self.docker_client
+            .fetch_manifest_digest(&reference, &auth)
+            .await
+            .with_context(|| format!("Could not resolve the digest of {}", image))
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
 pub struct PackageMetadataFslabsCiPublishDocker {
     pub publish: bool,
     pub repository: Option<String>,
     pub error: Option<String>,
+    /// Build cache the publish workflow imports, as a `docker buildx build --cache-from` value,
+    /// e.g. `type=registry,ref=registry/image:buildcache`
+    pub cache_from: Option<String>,
+    /// Digest of the build inputs, images are also tagged `inputs-<digest>`
+    #[serde(skip_deserializing)]
+    pub input_digest: Option<String>,
+    /// Tag of an image built from identical inputs, to retag as the version and `latest` instead
+    /// of rebuilding
+    #[serde(skip_deserializing)]
+    pub retag_from: Option<String>,
+}
+
+/// External images a Dockerfile builds from, ignoring its own stages, `scratch` and `ARG` based images
+pub fn base_images(dockerfile: &str) -> Vec<String> {
+    let mut stages: BTreeSet<String> = BTreeSet::new();
+    let mut images = vec![];
+    for line in dockerfile.lines() {
+        let mut words = line.split_whitespace();
+        if !words
+            .next()
+            .map(|w| w.eq_ignore_ascii_case("FROM"))
+            .unwrap_or(false)
+        {
+            continue;
+        }
+        let mut words = words.skip_while(|w| w.starts_with("--"));
+        let Some(image) = words.next() else {
+            continue;
+        };
+        if let (Some(r#as), Some(stage)) = (words.next(), words.next()) {
+            if r#as.eq_ignore_ascii_case("AS") {
+                stages.insert(stage.to_string());
+            }
+        }
+        if image == "scratch" || image.contains('$') || stages.contains(image) {
+            continue;
+        }
+        if !images.contains(&image.to_string()) {
+            images.push(image.to_string());
+        }
+    }
+    images
+}
+
+/// `.dockerignore` of the build context, its patterns being relative to the context root
+fn dockerignore(context: &Path) -> anyhow::Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(context);
+    if let Ok(content) = fs::read_to_string(context.join(".dockerignore")) {
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, pattern) = match line.strip_prefix('!') {
+                Some(pattern) => ("!", pattern),
+                None => ("", line),
+            };
+            // Unlike gitignore, a pattern without a slash only matches at the root
+            builder.add_line(
+                None,
+                &format!("{}/{}", negated, pattern.trim_start_matches('/')),
+            )?;
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// Hash the dockerfile, the digests its base images resolve to and every file of the build
+/// context not excluded by its `.dockerignore`
+fn compute_input_digest(
+    dockerfile: &Path,
+    context: &Path,
+    base_image_digests: &[String],
+) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(fs::read(dockerfile)?);
+    for digest in base_image_digests {
+        hasher.update(digest.as_bytes());
+    }
+    let ignore = dockerignore(context)?;
+    // The build context does not follow `.gitignore` or hidden files rules
+    let mut files: Vec<_> = WalkBuilder::new(context)
+        .standard_filters(false)
+        .filter_entry(move |e| {
+            !ignore
+                .matched(e.path(), e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+                .is_ignore()
+        })
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .map(|e| e.into_path())
+        .collect();
+    files.sort();
+    for file in files {
+        hasher.update(file.strip_prefix(context)?.to_string_lossy().as_bytes());
+        hasher.update(fs::read(&file)?);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
 }
 
 impl PackageMetadataFslabsCiPublishDocker {
+    /// Compute the inputs digest from the dockerfile and context the publish workflow builds with
+    pub async fn compute_input_digest(
+        &mut self,
+        dockerfile: &Path,
+        context: &Path,
+        docker: &mut Docker,
+    ) -> anyhow::Result<()> {
+        let mut base_image_digests = vec![];
+        for image in base_images(&fs::read_to_string(dockerfile)?) {
+            let digest = docker.fetch_image_digest(&image).await?;
+            base_image_digests.push(format!("{}@{}", image, digest));
+        }
+        self.input_digest = Some(compute_input_digest(
+            dockerfile,
+            context,
+            &base_image_digests,
+        )?);
+        Ok(())
+    }
+
     pub async fn check(
         &mut self,
         package: String,
@@ -245,12 +385,106 @@ impl PackageMetadataFslabsCiPublishDocker {
             None => anyhow::bail!("Tried to check docker image without setting the registry"),
         };
         self.publish = !docker
-            .check_image_exists(docker_registry, package, version)
+            .check_image_exists(docker_registry.clone(), package.clone(), version)
             .await?;
+        if let (true, Some(digest)) = (self.publish, self.input_digest.clone()) {
+            let tag = format!("inputs-{}", digest);
+            if docker
+                .check_image_exists(docker_registry, package, tag.clone())
+                .await?
+            {
+                self.retag_from = Some(tag);
+            }
+        }
         Ok(())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn test_base_images() {
+        let dockerfile = indoc! {"
+            ARG RUST_IMAGE=rust:1.76
+            FROM $RUST_IMAGE AS chef
+            FROM --platform=linux/amd64 rust:1.76-bookworm AS builder
+            RUN cargo build --release
+            FROM builder as tester
+            FROM scratch AS empty
+            from debian:bookworm-slim
+            FROM rust:1.76-bookworm
+        "};
+        assert_eq!(
+            base_images(dockerfile),
+            vec![
+                "rust:1.76-bookworm".to_string(),
+                "debian:bookworm-slim".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compute_input_digest_follows_dockerignore() {
+        let context = TempDir::new().expect("Could not create temp dir");
+        let dockerfile = context.child("Dockerfile");
+        dockerfile
+            .write_str("FROM debian:bookworm-slim")
+            .expect("Could not write Dockerfile");
+        context
+            .child("src/main.rs")
+            .write_str("fn main() {}")
+            .expect("Could not write main.rs");
+        context
+            .child(".dockerignore")
+            .write_str("target\n.git\n")
+            .expect("Could not write .dockerignore");
+        context
+            .child(".gitignore")
+            .write_str("target/\ngenerated.rs\n")
+            .expect("Could not write .gitignore");
+        let digest = |base: &[String]| {
+            compute_input_digest(&dockerfile, &context, base).expect("Could not compute digest")
+        };
+        let base = vec!["debian:bookworm-slim@sha256:1".to_string()];
+        let initial = digest(&base);
+
+        context
+            .child("target/debug/app")
+            .write_str("binary")
+            .expect("Could not write app");
+        context
+            .child(".git/HEAD")
+            .write_str("ref: main")
+            .expect("Could not write HEAD");
+        assert_eq!(digest(&base), initial);
+
+        // Only ignored by git, still copied into the image
+        context
+            .child("generated.rs")
+            .write_str("pub const A: u8 = 1;")
+            .expect("Could not write generated.rs");
+        let generated = digest(&base);
+        assert_ne!(generated, initial);
+        // `.dockerignore` patterns are anchored at the context root
+        context
+            .child("src/target")
+            .write_str("kept")
+            .expect("Could not write src/target");
+        assert_ne!(digest(&base), generated);
+
+        assert_ne!(
+            digest(&["debian:bookworm-slim@sha256:2".to_string()]),
+            initial
+        );
+    }
+}
+
 // mod tests {
 //     use std::env;
 //     use std::fs::File;
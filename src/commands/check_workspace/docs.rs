@@ -0,0 +1,48 @@
+use object_store::{path::Path, ObjectStore};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::check_workspace::binary::BinaryStore;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct PackageMetadataFslabsCiPublishDocs {
+    #[serde(default)]
+    pub publish: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Prefix, inside the binary store, of the rendered documentation of a crate version
+pub fn docs_prefix(name: &str, version: &str) -> String {
+    format!("docs/{}/{}", name, version)
+}
+
+impl PackageMetadataFslabsCiPublishDocs {
+    pub async fn check(
+        &mut self,
+        name: String,
+        version: String,
+        store: &Option<BinaryStore>,
+    ) -> anyhow::Result<()> {
+        if !self.publish {
+            return Ok(());
+        }
+        let Some(object_store) = store else {
+            return Ok(());
+        };
+        log::debug!(
+            "DOCS: checking if docs of version {} of {} already exists",
+            version,
+            name,
+        );
+        // `cargo doc` renders the crate under its lib name
+        let index_path = Path::from(format!(
+            "{}/{}/index.html",
+            docs_prefix(&name, &version),
+            name.replace('-', "_")
+        ));
+        self.publish = object_store.get_client().head(&index_path).await.is_err();
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+/// Lifecycle events emitted as NDJSON on stdout by `check-workspace --quiet`
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    StepStarted {
+        step: u8,
+        name: &'a str,
+    },
+    PackageResolved {
+        workspace: &'a str,
+        package: &'a str,
+        version: &'a str,
+    },
+    PackageChecked {
+        workspace: &'a str,
+        package: &'a str,
+        publish: bool,
+    },
+    PackageChanged {
+        workspace: &'a str,
+        package: &'a str,
+        files: &'a [PathBuf],
+    },
+    /// A registry credential was read, never carrying its value
+    SecretResolved {
+        key: &'a str,
+        source: &'a str,
+    },
+    Finished {
+        packages: usize,
+        duration_ms: u128,
+    },
+}
+
+pub struct EventStream {
+    enabled: bool,
+}
+
+impl EventStream {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+
+    pub fn emit(&self, event: Event) {
+        if !self.enabled {
+            return;
+        }
+        match serde_json::to_string(&event) {
+            Ok(line) => println!("{}", line),
+            Err(e) => log::warn!("Could not serialize event {:?}: {}", event, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_serialization() {
+        assert_eq!(
+            serde_json::to_string(&Event::PackageChecked {
+                workspace: "hub",
+                package: "hub_app",
+                publish: true,
+            })
+            .expect("Could not serialize event"),
+            r#"{"event":"package_checked","workspace":"hub","package":"hub_app","publish":true}"#
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+
+use crate::commands::check_workspace::{Result as Package, Results};
+
+/// Every member depending, directly or transitively, on one of `keys`
+pub fn dependants_closure(
+    packages: &HashMap<String, Package>,
+    keys: &[String],
+) -> BTreeSet<String> {
+    let mut seen: BTreeSet<String> = BTreeSet::new();
+    let mut queue: VecDeque<&String> = keys.iter().collect();
+    while let Some(key) = queue.pop_front() {
+        let Some(package) = packages.get(key) else {
+            continue;
+        };
+        for dependant in &package.dependant {
+            if seen.insert(dependant.package.clone()) {
+                queue.push_back(&dependant.package);
+            }
+        }
+    }
+    seen
+}
+
+/// Member owning `path`, relative to the repository root: the one with the deepest directory containing it
+fn owner<'a>(packages: &'a HashMap<String, Package>, path: &Path) -> Option<&'a String> {
+    packages
+        .iter()
+        .filter(|(_, p)| p.path == Path::new(".") || path.starts_with(&p.path))
+        .max_by_key(|(k, p)| match p.path == Path::new(".") {
+            true => (0, k.as_str()),
+            false => (p.path.components().count(), k.as_str()),
+        })
+        .map(|(k, _)| k)
+}
+
+/// Members owning one of the `paths`, and every member depending on them
+pub fn affected_by(packages: &HashMap<String, Package>, paths: &[PathBuf]) -> Vec<String> {
+    let owners: Vec<String> = paths
+        .iter()
+        .filter_map(|p| owner(packages, p).cloned())
+        .collect();
+    let mut affected = dependants_closure(packages, &owners);
+    affected.extend(owners);
+    affected.into_iter().collect()
+}
+
+/// Members grouped by strongly connected components, each group coming after the groups it
+/// depends on. A group of more than one member is a dependency cycle.
+pub fn publish_order(packages: &HashMap<String, Package>) -> Vec<Vec<String>> {
+    // Tarjan emits a component once everything it depends on has been emitted
+    struct Tarjan<'a> {
+        packages: &'a HashMap<String, Package>,
+        index: HashMap<&'a str, (usize, usize)>,
+        stack: Vec<&'a str>,
+        on_stack: BTreeSet<&'a str>,
+        components: Vec<Vec<String>>,
+    }
+
+    impl<'a> Tarjan<'a> {
+        fn visit(&mut self, key: &'a str) {
+            let index = self.index.len();
+            self.index.insert(key, (index, index));
+            self.stack.push(key);
+            self.on_stack.insert(key);
+            let mut dependencies: Vec<&'a str> = self.packages[key]
+                .dependencies
+                .iter()
+                .filter_map(|d| self.packages.get_key_value(&d.package))
+                .map(|(k, _)| k.as_str())
+                .collect();
+            dependencies.sort();
+            for dependency in dependencies {
+                match self.index.get(dependency) {
+                    None => {
+                        self.visit(dependency);
+                        let low = self.index[dependency].1;
+                        let entry = self.index.get_mut(key).unwrap();
+                        entry.1 = entry.1.min(low);
+                    }
+                    Some(&(dependency_index, _)) if self.on_stack.contains(dependency) => {
+                        let entry = self.index.get_mut(key).unwrap();
+                        entry.1 = entry.1.min(dependency_index);
+                    }
+                    _ => {}
+                }
+            }
+            let (index, low) = self.index[key];
+            if index == low {
+                let mut component = vec![];
+                while let Some(member) = self.stack.pop() {
+                    self.on_stack.remove(member);
+                    component.push(member.to_string());
+                    if member == key {
+                        break;
+                    }
+                }
+                component.sort();
+                self.components.push(component);
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        packages,
+        index: HashMap::new(),
+        stack: vec![],
+        on_stack: BTreeSet::new(),
+        components: vec![],
+    };
+    let mut keys: Vec<&String> = packages.keys().collect();
+    keys.sort();
+    for key in keys {
+        if !tarjan.index.contains_key(key.as_str()) {
+            tarjan.visit(key);
+        }
+    }
+    tarjan.components
+}
+
+impl Results {
+    pub fn publish_order(&self) -> Vec<Vec<String>> {
+        publish_order(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::check_workspace::ResultDependency;
+
+    use super::*;
+
+    fn package(name: &str, path: &str, dependencies: &[&str]) -> (String, Package) {
+        (
+            name.to_string(),
+            Package {
+                package: name.to_string(),
+                path: PathBuf::from(path),
+                dependencies: dependencies
+                    .iter()
+                    .map(|d| ResultDependency {
+                        package: d.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn packages() -> HashMap<String, Package> {
+        let mut packages = HashMap::from([
+            package("core", "libs/core", &[]),
+            package("core_macros", "libs/core/macros", &["core"]),
+            package("server", "apps/server", &["core", "utils"]),
+            package("utils", "libs/utils", &["core"]),
+            package("cli", "apps/cli", &["utils"]),
+        ]);
+        let edges: Vec<(String, String)> = packages
+            .values()
+            .flat_map(|p| {
+                p.dependencies
+                    .iter()
+                    .map(|d| (d.package.clone(), p.package.clone()))
+            })
+            .collect();
+        for (dependency, dependant) in edges {
+            packages
+                .get_mut(&dependency)
+                .unwrap()
+                .dependant
+                .push(ResultDependency {
+                    package: dependant,
+                    ..Default::default()
+                });
+        }
+        packages
+    }
+
+    #[test]
+    fn test_affected_by() {
+        let packages = packages();
+        assert_eq!(
+            affected_by(&packages, &[PathBuf::from("libs/utils/src/lib.rs")]),
+            vec!["cli", "server", "utils"]
+        );
+        assert_eq!(
+            affected_by(&packages, &[PathBuf::from("libs/core/macros/src/lib.rs")]),
+            vec!["core_macros"]
+        );
+        assert!(affected_by(&packages, &[PathBuf::from("README.md")]).is_empty());
+    }
+
+    #[test]
+    fn test_publish_order() {
+        let mut packages = packages();
+        assert_eq!(
+            publish_order(&packages),
+            vec![
+                vec!["core"],
+                vec!["utils"],
+                vec!["cli"],
+                vec!["core_macros"],
+                vec!["server"]
+            ]
+        );
+        packages.extend([package("a", "a", &["b"]), package("b", "b", &["a", "core"])]);
+        let order = publish_order(&packages);
+        assert_eq!(order[0], vec!["core"]);
+        assert_eq!(order[1], vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+
+use cargo_metadata::Package;
+use serde::Serialize;
+
+/// A cargo target of a package, with the directory holding its sources
+#[derive(Serialize, Clone, Default, Debug, PartialEq)]
+pub struct ResultTarget {
+    pub kind: String,
+    pub name: String,
+    /// Directory of the target root file, relative to the package
+    pub src_dir: PathBuf,
+}
+
+impl ResultTarget {
+    pub fn from_package(package: &Package, package_path: &Path) -> Vec<Self> {
+        package
+            .targets
+            .iter()
+            .filter_map(|t| {
+                let src_dir = t.src_path.parent()?.strip_prefix(package_path).ok()?;
+                Some(Self {
+                    kind: t.kind.first().cloned().unwrap_or_default(),
+                    name: t.name.clone(),
+                    src_dir: src_dir.to_path_buf().into(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn id(&self) -> String {
+        format!("{}:{}", self.kind, self.name)
+    }
+}
+
+/// Targets (`kind:name`) whose sources contain one of the changed files, relative to the package.
+/// A file belongs to the targets with the deepest source directory containing it. A file outside
+/// of every target (manifest, build script, assets, ...) can affect anything and selects them all.
+pub fn affected_targets(targets: &[ResultTarget], changed_files: &[PathBuf]) -> Vec<String> {
+    let mut affected: Vec<String> = vec![];
+    for file in changed_files {
+        let deepest = targets
+            .iter()
+            .filter(|t| file.starts_with(&t.src_dir))
+            .map(|t| t.src_dir.components().count())
+            .max();
+        let matching: Vec<&ResultTarget> = match deepest {
+            Some(depth) => targets
+                .iter()
+                .filter(|t| file.starts_with(&t.src_dir) && t.src_dir.components().count() == depth)
+                .collect(),
+            None => targets.iter().collect(),
+        };
+        for target in matching {
+            let id = target.id();
+            if !affected.contains(&id) {
+                affected.push(id);
+            }
+        }
+    }
+    affected.sort();
+    affected
+}
+
+/// Nextest filterset running only the tests of the given targets (`kind:name`) of `package`.
+/// `None` when every test should run, e.g. when the build script is affected.
+pub fn nextest_filter(package: &str, targets: &[String]) -> Option<String> {
+    let mut binary_ids: Vec<String> = vec![];
+    for target in targets {
+        let (kind, name) = target.split_once(':')?;
+        let binary_id = match kind {
+            "custom-build" => return None,
+            "bin" | "bench" | "example" => format!("{}::{}/{}", package, kind, name),
+            "test" => format!("{}::{}", package, name),
+            _ => package.to_string(),
+        };
+        let binary_id = format!("binary_id(={})", binary_id);
+        if !binary_ids.contains(&binary_id) {
+            binary_ids.push(binary_id);
+        }
+    }
+    match binary_ids.is_empty() {
+        true => None,
+        false => Some(binary_ids.join(" | ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(kind: &str, name: &str, src_dir: &str) -> ResultTarget {
+        ResultTarget {
+            kind: kind.to_string(),
+            name: name.to_string(),
+            src_dir: PathBuf::from(src_dir),
+        }
+    }
+
+    #[test]
+    fn test_affected_targets() {
+        let targets = vec![
+            target("lib", "hub", "src"),
+            target("bin", "hub_cli", "src/bin"),
+            target("test", "integration", "tests"),
+            target("bench", "perf", "benches"),
+        ];
+        assert_eq!(
+            affected_targets(&targets, &[PathBuf::from("benches/perf.rs")]),
+            vec!["bench:perf".to_string()]
+        );
+        assert_eq!(
+            affected_targets(
+                &targets,
+                &[
+                    PathBuf::from("src/bin/hub_cli.rs"),
+                    PathBuf::from("tests/integration.rs")
+                ]
+            ),
+            vec!["bin:hub_cli".to_string(), "test:integration".to_string()]
+        );
+        assert_eq!(
+            affected_targets(&targets, &[PathBuf::from("Cargo.toml")]).len(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_nextest_filter() {
+        let targets = |ids: &[&str]| ids.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            nextest_filter(
+                "hub",
+                &targets(&["bin:hub_cli", "lib:hub", "test:integration"])
+            ),
+            Some(
+                "binary_id(=hub::bin/hub_cli) | binary_id(=hub) | binary_id(=hub::integration)"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            nextest_filter(
+                "hub",
+                &targets(&["custom-build:build-script-build", "lib:hub"])
+            ),
+            None
+        );
+        assert_eq!(nextest_filter("hub", &[]), None);
+    }
+}
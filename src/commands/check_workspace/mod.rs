@@ -2,17 +2,20 @@ use ignore::WalkBuilder;
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::fs;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
 use std::time::Instant;
 
 use anyhow::Context;
-use cargo_metadata::{DependencyKind, MetadataCommand, Package};
+use cargo_metadata::{DependencyKind, Metadata, Package};
+use clap::builder::PossibleValuesParser;
 use clap::Parser;
 use console::{style, Emoji};
-use git2::{DiffDelta, DiffOptions, Repository};
+use git2::{DiffDelta, DiffOptions, FileMode, Repository};
 use indexmap::IndexMap;
 use indicatif::{HumanDuration, ProgressBar, ProgressStyle};
-use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use serde::{de, Deserialize, Deserializer, Serialize};
 use serde_json::from_value;
 use serde_yaml::Value;
 use toml::from_str as toml_from_str;
@@ -20,22 +23,123 @@ use toml::from_str as toml_from_str;
 use crate::commands::check_workspace::binary::BinaryStore;
 use crate::commands::check_workspace::docker::Docker;
 use binary::PackageMetadataFslabsCiPublishBinary;
+use book::PackageMetadataFslabsCiPublishBook;
 use cargo::{Cargo, PackageMetadataFslabsCiPublishCargo};
+use changes::{change_kind, ChangeKind};
+use custom::PackageMetadataFslabsCiPublishCustom;
+use dependents::{feed_dependents, ResultDependents};
+use descriptor::find_descriptors;
 use docker::PackageMetadataFslabsCiPublishDocker;
+use docs::PackageMetadataFslabsCiPublishDocs;
+use events::{Event, EventStream};
+use graph::{affected_by, dependants_closure};
+use impact::{affected_targets, nextest_filter, ResultTarget};
 use npm::{Npm, PackageMetadataFslabsCiPublishNpmNapi};
+use python::{PackageMetadataFslabsCiPublishPython, PyPi};
+use revisions::{changed_revisions, resolve_revisions, DiffStrategy};
+use schemas::PackageMetadataFslabsCiPublishSchemas;
+use secrets::{
+    resolve_secret, AwsCredentials, AwsSecrets, AzureKeyVaultSecrets, SecretsBackend,
+    SecretsProvider, VaultSecrets,
+};
+use selection::Selection;
+use terraform::PackageMetadataFslabsCiPublishTerraform;
+
+pub(crate) use docker::base_images;
 
 use crate::utils;
 
 mod binary;
+mod book;
 mod cargo;
+mod changes;
+mod custom;
+mod dependents;
+mod descriptor;
 mod docker;
+pub(crate) mod docs;
+pub(crate) mod events;
+mod graph;
+mod impact;
 mod npm;
+mod python;
+mod revisions;
+mod schemas;
+pub(crate) mod secrets;
+mod selection;
+mod terraform;
 
 static LOOKING_GLASS: Emoji<'_, '_> = Emoji("🔍  ", "");
 static TRUCK: Emoji<'_, '_> = Emoji("🚚  ", "");
 static PAPER: Emoji<'_, '_> = Emoji("📃  ", "");
 static SPARKLE: Emoji<'_, '_> = Emoji("✨ ", ":-)");
 
+/// Publish target with a single publish flag, and how to read and turn it off
+struct PublishTarget {
+    name: &'static str,
+    /// Publish flag and error state
+    state: fn(&PackageMetadataFslabsCiPublish) -> (bool, bool),
+    publish: fn(&mut PackageMetadataFslabsCiPublish) -> &mut bool,
+}
+
+/// Every publish target but `custom`, which holds a list of them
+const PUBLISH_TARGETS: [PublishTarget; 9] = [
+    PublishTarget {
+        name: "docker",
+        state: |p| (p.docker.publish, p.docker.error.is_some()),
+        publish: |p| &mut p.docker.publish,
+    },
+    PublishTarget {
+        name: "cargo",
+        state: |p| (p.cargo.publish, p.cargo.error.is_some()),
+        publish: |p| &mut p.cargo.publish,
+    },
+    PublishTarget {
+        name: "npm_napi",
+        state: |p| (p.npm_napi.publish, p.npm_napi.error.is_some()),
+        publish: |p| &mut p.npm_napi.publish,
+    },
+    PublishTarget {
+        name: "binary",
+        state: |p| (p.binary.publish, p.binary.error.is_some()),
+        publish: |p| &mut p.binary.publish,
+    },
+    PublishTarget {
+        name: "docs",
+        state: |p| (p.docs.publish, p.docs.error.is_some()),
+        publish: |p| &mut p.docs.publish,
+    },
+    PublishTarget {
+        name: "book",
+        state: |p| (p.book.publish, p.book.error.is_some()),
+        publish: |p| &mut p.book.publish,
+    },
+    PublishTarget {
+        name: "schemas",
+        state: |p| (p.schemas.publish, p.schemas.error.is_some()),
+        publish: |p| &mut p.schemas.publish,
+    },
+    PublishTarget {
+        name: "terraform",
+        state: |p| (p.terraform.publish, p.terraform.error.is_some()),
+        publish: |p| &mut p.terraform.publish,
+    },
+    PublishTarget {
+        name: "python",
+        state: |p| (p.python.publish, p.python.error.is_some()),
+        publish: |p| &mut p.python.publish,
+    },
+];
+
+/// Names of the publish targets, as given to `--only-targets` and `--skip-targets`
+fn publish_target_names() -> Vec<&'static str> {
+    PUBLISH_TARGETS
+        .iter()
+        .map(|t| t.name)
+        .chain(["custom"])
+        .collect()
+}
+
 #[derive(Debug, Parser, Default)]
 #[command(about = "Check directory for crates that need to be published.")]
 pub struct Options {
@@ -57,8 +161,15 @@ pub struct Options {
     cargo_registry_url: Option<String>,
     #[arg(long)]
     cargo_registry_user_agent: Option<String>,
+    /// Sent as `Authorization` to the private cargo registry, for token-only and sparse registries
+    #[arg(long)]
+    cargo_registry_token: Option<String>,
     #[arg(long, default_value_t = false)]
     cargo_default_publish: bool,
+    #[arg(long)]
+    pypi_registry_url: Option<String>,
+    #[arg(long)]
+    pypi_registry_token: Option<String>,
     #[arg(long, env)]
     binary_store_storage_account: Option<String>,
     #[arg(long, env)]
@@ -71,16 +182,95 @@ pub struct Options {
     toolchain: Option<String>,
     #[arg(long, default_value_t = false)]
     progress: bool,
+    /// Machine mode: stdout only carries each lifecycle event as one NDJSON line, no progress output nor results
+    #[arg(long, default_value_t = false, conflicts_with = "progress")]
+    pub(crate) quiet: bool,
     #[arg(long, default_value_t = false)]
     pub(crate) check_publish: bool,
+    /// Only publish these targets, e.g. `docker,cargo` to retry a failed docker push
+    #[arg(long, value_delimiter = ',', value_parser = PossibleValuesParser::new(publish_target_names()), conflicts_with = "skip_targets")]
+    only_targets: Vec<String>,
+    /// Never publish these targets
+    #[arg(long, value_delimiter = ',', value_parser = PossibleValuesParser::new(publish_target_names()))]
+    skip_targets: Vec<String>,
+    /// Only list the member names, one per line, e.g. for shell completions
+    #[arg(long, default_value_t = false)]
+    pub(crate) list_names: bool,
+    /// Do not reach any registry: publish targets are left unchecked and nothing gets published
+    #[arg(long, default_value_t = false)]
+    offline: bool,
+    /// Run `cargo metadata` even if the workspace manifests did not change since the last run
+    #[arg(long, default_value_t = false)]
+    refresh_metadata: bool,
+    /// Number of workspaces whose metadata is loaded concurrently, defaults to the number of CPUs
+    #[arg(long)]
+    metadata_jobs: Option<usize>,
+    /// Fail on manifests outside of any workspace and on packages defined twice, instead of warning
+    #[arg(long, default_value_t = false)]
+    strict_workspace: bool,
     #[arg(long, default_value_t = false)]
     pub(crate) check_changed: bool,
-    #[arg(long, default_value = "HEAD")]
-    changed_head_ref: String,
-    #[arg(long, default_value = "HEAD~")]
-    changed_base_ref: String,
+    /// Defaults to the Prow or GitHub Actions head commit, or `HEAD`
+    #[arg(long)]
+    changed_head_ref: Option<String>,
+    /// Defaults to the Prow or GitHub Actions base revision, or `HEAD~`
+    #[arg(long)]
+    changed_base_ref: Option<String>,
+    /// Compare the head revision with the base revision, or with their merge base
+    #[arg(long, default_value_t, value_enum)]
+    diff_strategy: DiffStrategy,
+    /// In a shallow clone, fetch up to that many more commits to find the compared revisions
+    #[arg(long)]
+    deepen_shallow: Option<u32>,
     #[arg(long, default_value_t = false)]
     fail_unit_error: bool,
+    /// Only output the dependents counts, not the dependents themselves
+    #[arg(long, default_value_t = false)]
+    hide_dependencies: bool,
+    /// Maximum number of changed files listed per package
+    #[arg(long, default_value_t = 20)]
+    max_changed_files: usize,
+    /// Do not map changed files to the cargo targets they affect
+    #[arg(long, default_value_t = false)]
+    no_tia: bool,
+    /// Validate crate metadata and packaged files before marking crates for cargo publishing
+    #[arg(long, default_value_t = false)]
+    validate_cargo_package: bool,
+    /// Only output the members matching the selection expression, e.g. `changed & publish - orica_*`
+    #[arg(long)]
+    select: Option<Selection>,
+    /// Only output the members owning one of these paths, and the members depending on them
+    #[arg(long, num_args = 1..)]
+    affected_by: Vec<PathBuf>,
+    /// Resolve registry credentials not given on the command line from a secrets backend
+    #[arg(long, value_enum)]
+    secrets_backend: Option<SecretsBackend>,
+    #[arg(long, env = "VAULT_ADDR")]
+    vault_address: Option<String>,
+    #[arg(long, env = "VAULT_TOKEN")]
+    vault_token: Option<String>,
+    #[arg(long, default_value = "secret/data/fslabscli")]
+    vault_secret_path: String,
+    /// e.g. `https://my-vault.vault.azure.net`, the service principal is read from `AZURE_CLIENT_SECRET`
+    #[arg(long, env = "AZURE_KEYVAULT_URL")]
+    azure_keyvault_url: Option<String>,
+    #[arg(long, env = "AZURE_TENANT_ID")]
+    azure_tenant_id: Option<String>,
+    #[arg(long, env = "AZURE_CLIENT_ID")]
+    azure_client_id: Option<String>,
+    #[arg(
+        long,
+        env = "AZURE_AUTHORITY_HOST",
+        default_value = "https://login.microsoftonline.com"
+    )]
+    azure_authority_host: String,
+    /// Secret holding the credentials as a JSON object, read with the usual `AWS_*` credentials
+    #[arg(long, default_value = "fslabscli")]
+    aws_secret_id: String,
+    #[arg(long, env = "AWS_REGION")]
+    aws_region: Option<String>,
+    #[arg(long, env = "AWS_ENDPOINT_URL")]
+    aws_endpoint_url: Option<String>,
 }
 
 impl Options {
@@ -94,14 +284,14 @@ impl Options {
     }
 }
 
-#[derive(Serialize, Clone, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
 pub struct ResultDependency {
     pub package: String,
     pub version: String,
     pub publishable: bool,
 }
 
-#[derive(Serialize, Clone, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
 pub struct Result {
     pub workspace: String,
     pub package: String,
@@ -111,12 +301,35 @@ pub struct Result {
     pub publish: bool,
     pub dependencies: Vec<ResultDependency>,
     pub dependant: Vec<ResultDependency>,
+    pub dependents: ResultDependents,
     pub changed: bool,
     pub dependencies_changed: bool,
     pub test_detail: PackageMetadataFslabsCiTest,
+    pub tags: Vec<String>,
+    pub owners: Vec<String>,
+    /// Publish targets skipped because the version is already present in their registry
+    pub already_published: Vec<String>,
+    /// Configured publish targets left unchecked by `--offline`
+    pub unchecked_targets: Vec<String>,
+    /// Cargo targets (`kind:name`) affected by the changed files
+    pub changed_targets: Vec<String>,
+    /// Nextest filterset running only the tests of `changed_targets`, unset when every test should run
+    pub test_filter: Option<String>,
+    /// Files, relative to the repository root, that marked the package as changed
+    pub changed_files: Vec<PathBuf>,
+    /// Number of changed files, `changed_files` being capped to `--max-changed-files`
+    pub changed_files_count: usize,
+    /// Path dependencies pointing outside of the repository, as `name (path)`
+    pub external_path_dependencies: Vec<String>,
+    #[serde(skip)]
+    pub targets: Vec<ResultTarget>,
+    #[serde(skip)]
+    pub ignore_submodule_changes: bool,
+    #[serde(skip)]
+    pub ignore_lfs_changes: bool,
 }
 
-#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
 pub struct PackageMetadataFslabsCiPublish {
     #[serde(default = "PackageMetadataFslabsCiPublishDocker::default")]
     pub docker: PackageMetadataFslabsCiPublishDocker,
@@ -126,18 +339,93 @@ pub struct PackageMetadataFslabsCiPublish {
     pub npm_napi: PackageMetadataFslabsCiPublishNpmNapi,
     #[serde(default = "PackageMetadataFslabsCiPublishBinary::default")]
     pub binary: PackageMetadataFslabsCiPublishBinary,
+    #[serde(default = "PackageMetadataFslabsCiPublishDocs::default")]
+    pub docs: PackageMetadataFslabsCiPublishDocs,
+    #[serde(default = "PackageMetadataFslabsCiPublishBook::default")]
+    pub book: PackageMetadataFslabsCiPublishBook,
+    #[serde(default = "PackageMetadataFslabsCiPublishSchemas::default")]
+    pub schemas: PackageMetadataFslabsCiPublishSchemas,
+    #[serde(default = "PackageMetadataFslabsCiPublishTerraform::default")]
+    pub terraform: PackageMetadataFslabsCiPublishTerraform,
+    #[serde(default = "PackageMetadataFslabsCiPublishPython::default")]
+    pub python: PackageMetadataFslabsCiPublishPython,
     #[serde(default)]
+    pub custom: Vec<PackageMetadataFslabsCiPublishCustom>,
+    #[serde(default)]
+    #[schemars(with = "Option<IndexMap<String, serde_json::Value>>")]
     pub args: Option<IndexMap<String, Value>>,
     #[serde(default)]
     pub env: Option<IndexMap<String, String>>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+impl PackageMetadataFslabsCiPublish {
+    /// Dockerfile and build context the publish workflow builds the image with, relative to the package
+    pub fn docker_build_paths(&self) -> (String, String) {
+        let arg = |key: &str, default: &str| {
+            self.args
+                .as_ref()
+                .and_then(|args| args.get(key))
+                .and_then(|v| v.as_str())
+                .unwrap_or(default)
+                .to_string()
+        };
+        (arg("dockerfile", "Dockerfile"), arg("docker_context", "."))
+    }
+
+    /// Name, publish flag and error state of every publish target
+    pub fn targets(&self) -> Vec<(&'static str, bool, bool)> {
+        let mut targets: Vec<(&'static str, bool, bool)> = PUBLISH_TARGETS
+            .iter()
+            .map(|t| {
+                let (publish, error) = (t.state)(self);
+                (t.name, publish, error)
+            })
+            .collect();
+        targets.extend(self.custom.iter().map(|c| ("custom", c.publish, false)));
+        targets
+    }
+
+    /// Turn every publish target off
+    pub fn disable(&mut self) {
+        self.retain_targets(|_| false);
+    }
+
+    /// Turn off the publish targets whose name is rejected by `keep`
+    pub fn retain_targets(&mut self, keep: impl Fn(&str) -> bool) {
+        for target in &PUBLISH_TARGETS {
+            if !keep(target.name) {
+                *(target.publish)(self) = false;
+            }
+        }
+        if !keep("custom") {
+            for custom in self.custom.iter_mut() {
+                custom.publish = false;
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
 pub struct PackageMetadataFslabsCiTest {
     #[serde(default)]
+    #[schemars(with = "Option<IndexMap<String, serde_json::Value>>")]
     pub args: Option<IndexMap<String, Value>>,
     pub env: Option<IndexMap<String, String>>,
     pub skip: Option<bool>,
+    /// Hardware the tests need, e.g. `gpu`
+    #[serde(default)]
+    pub requires: Vec<String>,
+    /// Run the tests under miri, for crates with unsafe code
+    #[serde(default)]
+    pub miri: Option<bool>,
+    /// Sanitizers to also run the tests under on nightly, each as its own JUnit suite, e.g.
+    /// `["address", "thread"]`
+    #[serde(default)]
+    pub sanitizers: Vec<String>,
+    /// Command running the tests instead of the rust test workflow, e.g. `npm test` for a
+    /// `fslabs.toml` member
+    #[serde(default)]
+    pub command: Option<String>,
 }
 
 #[derive(Deserialize, Default, Debug)]
@@ -145,6 +433,41 @@ struct PackageMetadataFslabsCi {
     pub publish: Option<PackageMetadataFslabsCiPublish>,
     #[serde(default)]
     pub test: Option<PackageMetadataFslabsCiTest>,
+    /// Either a list of tags or a table of `tag = true`
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    pub tags: Vec<String>,
+    /// Teams or users owning the package, e.g. `@org/team`
+    #[serde(default)]
+    pub owners: Vec<String>,
+    /// Path dependencies allowed to point outside of the repository, e.g. vendored crates
+    #[serde(default)]
+    pub allowed_external_paths: Vec<String>,
+    /// Submodule pointer updates under the package do not mark it as changed
+    #[serde(default)]
+    pub ignore_submodule_changes: bool,
+    /// LFS tracked file updates under the package do not mark it as changed
+    #[serde(default)]
+    pub ignore_lfs_changes: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Tags {
+    List(Vec<String>),
+    Table(IndexMap<String, bool>),
+}
+
+fn deserialize_tags<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<String>, D::Error> {
+    Ok(match Tags::deserialize(deserializer)? {
+        Tags::List(tags) => tags,
+        Tags::Table(tags) => tags
+            .into_iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(tag, _)| tag)
+            .collect(),
+    })
 }
 
 #[derive(Deserialize, Default, Debug)]
@@ -160,8 +483,16 @@ impl Result {
             .parent()
             .unwrap()
             .to_path_buf();
-        let metadata: PackageMetadata =
-            from_value(package.metadata.clone()).unwrap_or_else(|_| PackageMetadata::default());
+        // Invalid fslabs metadata would otherwise silently turn off publishing
+        let metadata: PackageMetadata = match package.metadata.get("fslabs") {
+            Some(_) => from_value(package.metadata.clone()).with_context(|| {
+                format!(
+                    "Invalid [package.metadata.fslabs] of {}",
+                    package.manifest_path
+                )
+            })?,
+            None => PackageMetadata::default(),
+        };
         let mut publish = metadata.fslabs.publish.unwrap_or_default();
         publish.cargo.registry = match package.publish.clone() {
             Some(r) => Some(r.clone()),
@@ -182,6 +513,34 @@ impl Result {
             .map(|r| r.len() == 1)
             .unwrap_or(false);
 
+        let targets = match package.manifest_path.parent() {
+            Some(manifest_dir) => ResultTarget::from_package(&package, manifest_dir.as_std_path()),
+            None => vec![],
+        };
+        // Outside of the repository, changes are not detected and the crate cannot be published
+        let repository_root = Repository::discover(&root_dir)
+            .ok()
+            .and_then(|r| r.workdir().and_then(|w| w.canonicalize().ok()))
+            .unwrap_or_else(|| root_dir.clone());
+        let external_path_dependencies = package
+            .dependencies
+            .iter()
+            .filter(|d| !metadata.fslabs.allowed_external_paths.contains(&d.name))
+            .filter_map(|d| {
+                let dependency_path = d.path.as_ref()?.as_std_path();
+                let dependency_path = dependency_path
+                    .canonicalize()
+                    .unwrap_or_else(|_| dependency_path.to_path_buf());
+                match dependency_path.starts_with(&repository_root) {
+                    true => None,
+                    false => Some(format!(
+                        "{} ({})",
+                        d.name,
+                        dependency_path.to_string_lossy()
+                    )),
+                }
+            })
+            .collect();
         let dependencies = package
             .dependencies
             .into_iter()
@@ -203,20 +562,35 @@ impl Result {
             path,
             publish_detail: publish,
             test_detail: metadata.fslabs.test.unwrap_or_default(),
+            tags: metadata.fslabs.tags,
+            owners: metadata.fslabs.owners,
             dependencies,
+            external_path_dependencies,
+            targets,
+            ignore_submodule_changes: metadata.fslabs.ignore_submodule_changes,
+            ignore_lfs_changes: metadata.fslabs.ignore_lfs_changes,
             ..Default::default()
         })
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn check_publishable(
         &mut self,
         npm: &Npm,
         cargo: &Cargo,
+        pypi: &PyPi,
         docker: &mut Docker,
         binary_store: &Option<BinaryStore>,
         release_channel: String,
         toolchain: String,
     ) -> anyhow::Result<()> {
+        let configured: Vec<&str> = self
+            .publish_detail
+            .targets()
+            .into_iter()
+            .filter(|(_, publish, _)| *publish)
+            .map(|(name, _, _)| name)
+            .collect();
         match self
             .publish_detail
             .docker
@@ -251,7 +625,7 @@ impl Result {
                 self.package.clone(),
                 self.version.clone(),
                 binary_store,
-                release_channel,
+                release_channel.clone(),
                 toolchain,
             )
             .await
@@ -261,16 +635,90 @@ impl Result {
                 self.publish_detail.binary.error = Some(e.to_string());
             }
         };
+        match self
+            .publish_detail
+            .docs
+            .check(self.package.clone(), self.version.clone(), binary_store)
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => self.publish_detail.docs.error = Some(e.to_string()),
+        };
+        match self
+            .publish_detail
+            .book
+            .check(
+                self.package.clone(),
+                self.version.clone(),
+                binary_store,
+                release_channel,
+            )
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => self.publish_detail.book.error = Some(e.to_string()),
+        };
+        match self
+            .publish_detail
+            .schemas
+            .check(self.package.clone(), self.version.clone(), binary_store)
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => self.publish_detail.schemas.error = Some(e.to_string()),
+        };
+        match self
+            .publish_detail
+            .terraform
+            .check(self.package.clone(), self.version.clone(), binary_store)
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => self.publish_detail.terraform.error = Some(e.to_string()),
+        };
+        match self
+            .publish_detail
+            .python
+            .check(self.package.clone(), self.version.clone(), pypi)
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => self.publish_detail.python.error = Some(e.to_string()),
+        };
+        // Configured targets that got turned off without error already have this version
+        self.already_published = self
+            .publish_detail
+            .targets()
+            .into_iter()
+            .filter(|(name, publish, error)| configured.contains(name) && !publish && !error)
+            .map(|(name, _, _)| name.to_string())
+            .collect();
 
         Ok(())
     }
 }
 
+impl Result {
+    /// Whether the package metadata enables the publish target `name`, even when `--check-publish`
+    /// turned it off because this version is already published
+    pub fn configures_target(&self, name: &str) -> bool {
+        self.already_published
+            .iter()
+            .chain(&self.unchecked_targets)
+            .any(|t| t == name)
+            || self
+                .publish_detail
+                .targets()
+                .into_iter()
+                .any(|(target, publish, error)| target == name && (publish || error))
+    }
+}
+
 impl Display for Result {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{} -- {} -- {}: docker: {}, cargo: {}, npm_napi: {}, binary: {}, publish: {}",
+            "{} -- {} -- {}: docker: {}, cargo: {}, npm_napi: {}, binary: {}, docs: {}, book: {}, schemas: {}, terraform: {}, python: {}, publish: {}, already_published: [{}], tags: [{}]",
             self.workspace,
             self.package,
             self.version,
@@ -278,14 +726,84 @@ impl Display for Result {
             self.publish_detail.cargo.publish,
             self.publish_detail.npm_napi.publish,
             self.publish_detail.binary.publish,
-            self.publish
-        )
+            self.publish_detail.docs.publish,
+            self.publish_detail.book.publish,
+            self.publish_detail.schemas.publish,
+            self.publish_detail.terraform.publish,
+            self.publish_detail.python.publish,
+            self.publish,
+            self.already_published.join(", "),
+            self.tags.join(", ")
+        )?;
+        if !self.changed_files.is_empty() {
+            write!(
+                f,
+                ", changed_files: [{}{}]",
+                self.changed_files
+                    .iter()
+                    .map(|p| p.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                match self.changed_files_count > self.changed_files.len() {
+                    true => format!(
+                        ", and {} more",
+                        self.changed_files_count - self.changed_files.len()
+                    ),
+                    false => "".to_string(),
+                }
+            )?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct Results(pub(crate) HashMap<String, Result>);
 
+#[derive(Deserialize)]
+struct VersionedResults {
+    /// Missing from the outputs of older versions
+    schema_version: Option<u32>,
+    #[serde(flatten)]
+    members: HashMap<String, Result>,
+}
+
+impl<'de> Deserialize<'de> for Results {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let results = VersionedResults::deserialize(deserializer)?;
+        match results.schema_version {
+            Some(version) if version > utils::SCHEMA_VERSION => Err(de::Error::custom(format!(
+                "schema version {} is newer than the supported {}",
+                version,
+                utils::SCHEMA_VERSION
+            ))),
+            _ => Ok(Results(results.members)),
+        }
+    }
+}
+
+impl Results {
+    /// Results of a previous `check-workspace --json` run, read from stdin when `path` is `-`
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let (name, content) = match path == Path::new("-") {
+            true => {
+                let mut content = String::new();
+                io::stdin()
+                    .read_to_string(&mut content)
+                    .context("Could not read stdin")?;
+                ("stdin".to_string(), content)
+            }
+            false => (
+                path.to_string_lossy().to_string(),
+                fs::read_to_string(path)
+                    .with_context(|| format!("Could not read {}", path.to_string_lossy()))?,
+            ),
+        };
+        serde_json::from_str(&content)
+            .with_context(|| format!("{} is not a check-workspace JSON output", name))
+    }
+}
+
 impl Display for Results {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         for (k, v) in &self.0 {
@@ -305,16 +823,100 @@ struct RustToolchainFile {
     pub toolchain: RustToolchain,
 }
 
+/// Channel pinned by the `rust-toolchain.toml` of `directory`, if any
+pub(crate) fn read_toolchain(directory: &Path) -> Option<String> {
+    let toml_content = fs::read_to_string(directory.join("rust-toolchain.toml")).ok()?;
+    let rust_toolchain: RustToolchainFile = toml_from_str(&toml_content).ok()?;
+    Some(rust_toolchain.toolchain.channel)
+}
+
 fn parse_toolchain(working_directory: &Path) -> String {
-    let toml_content = match fs::read_to_string(working_directory.join("rust-toolchain.toml")) {
-        Ok(content) => content,
-        Err(_) => return "1.74".to_string(),
-    };
-    let rust_toolchain: RustToolchainFile = match toml_from_str(&toml_content) {
-        Ok(r) => r,
-        Err(_) => return "1.74".to_string(),
-    };
-    rust_toolchain.toolchain.channel
+    read_toolchain(working_directory).unwrap_or_else(|| "1.74".to_string())
+}
+
+/// Root of a workspace and its metadata
+type Workspace = (PathBuf, Metadata);
+
+/// Metadata of every workspace under `roots`, nested ones included, and the manifests
+/// belonging to none
+fn load_workspaces(
+    mut roots: Vec<PathBuf>,
+    refresh: bool,
+    jobs: usize,
+) -> anyhow::Result<(Vec<Workspace>, Vec<PathBuf>)> {
+    // Workspaces nested in another one are loaded in the next round
+    let mut workspaces = vec![];
+    let mut skipped_manifests: Vec<PathBuf> = vec![];
+    while !roots.is_empty() {
+        let metadatas = utils::cargo_metadata_all(&roots, refresh, jobs);
+        let mut nested_roots = vec![];
+        for (root, workspace_metadata) in roots.into_iter().zip(metadatas) {
+            let workspace_metadata = workspace_metadata?;
+            let foreign = utils::foreign_manifests(&root, &workspace_metadata);
+            nested_roots.extend(foreign.nested_workspaces);
+            skipped_manifests.extend(foreign.skipped);
+            workspaces.push((root, workspace_metadata));
+        }
+        roots = nested_roots;
+    }
+    Ok((workspaces, skipped_manifests))
+}
+
+/// Warn, or fail when `strict`, if a member with the same name as `result` was already loaded
+fn check_duplicate(
+    packages: &HashMap<String, Result>,
+    result: &Result,
+    strict: bool,
+) -> anyhow::Result<()> {
+    if let Some(existing) = packages.get(&result.package) {
+        let error_msg = format!(
+            "Package {} is defined in both {} and {}",
+            result.package,
+            existing.path.to_string_lossy(),
+            result.path.to_string_lossy()
+        );
+        if strict {
+            anyhow::bail!(error_msg);
+        }
+        log::warn!("{}", error_msg);
+    }
+    Ok(())
+}
+
+/// Names of the members, without checking anything about them
+pub fn list_member_names(working_directory: PathBuf) -> anyhow::Result<Vec<String>> {
+    let roots = utils::get_cargo_roots(working_directory.clone())
+        .with_context(|| format!("Failed to get roots from {:?}", working_directory))?;
+    let jobs = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    let (workspaces, _) = load_workspaces(roots, false, jobs)?;
+    let mut names: Vec<String> = workspaces
+        .into_iter()
+        .flat_map(|(_, metadata)| metadata.packages.into_iter().map(|p| p.name))
+        .collect();
+    let root = working_directory.canonicalize()?;
+    names.extend(
+        find_descriptors(&root)
+            .iter()
+            .filter_map(|d| Result::from_descriptor(d, &root).ok())
+            .map(|r| r.package),
+    );
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Results loaded from `workspace_json` when given, to skip checking the workspace again
+pub async fn check_workspace_or_load(
+    workspace_json: Option<&Path>,
+    options: Options,
+    working_directory: PathBuf,
+) -> anyhow::Result<Results> {
+    match workspace_json {
+        Some(path) => Results::load(path),
+        None => check_workspace(Box::new(options), working_directory).await,
+    }
 }
 
 pub async fn check_workspace(
@@ -323,6 +925,7 @@ pub async fn check_workspace(
 ) -> anyhow::Result<Results> {
     log::info!("Check directory for crates that need publishing");
     let started = Instant::now();
+    let events = EventStream::new(options.quiet);
     let path = match working_directory.is_absolute() {
         true => working_directory.clone(),
         false => working_directory
@@ -332,6 +935,10 @@ pub async fn check_workspace(
 
     log::debug!("Base directory: {:?}", path);
     // 1. Find all workspaces to investigate
+    events.emit(Event::StepStarted {
+        step: 1,
+        name: "Resolving workspaces",
+    });
     if options.progress {
         println!(
             "{} {}Resolving workspaces...",
@@ -343,6 +950,10 @@ pub async fn check_workspace(
         .with_context(|| format!("Failed to get roots from {:?}", working_directory))?;
     let mut packages: HashMap<String, Result> = HashMap::new();
     // 2. For each workspace, find if one of the subcrates needs publishing
+    events.emit(Event::StepStarted {
+        step: 2,
+        name: "Resolving packages",
+    });
     if options.progress {
         println!(
             "{} {}Resolving packages...",
@@ -350,21 +961,45 @@ pub async fn check_workspace(
             TRUCK
         );
     }
-    for root in roots {
+    let jobs = options.metadata_jobs.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+    let (workspaces, skipped_manifests) = load_workspaces(roots, options.refresh_metadata, jobs)?;
+    if !skipped_manifests.is_empty() {
+        let error_msg = format!(
+            "Skipping manifests which are not part of any workspace: {}",
+            skipped_manifests
+                .iter()
+                .map(|m| m.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if options.strict_workspace {
+            anyhow::bail!(error_msg);
+        }
+        log::warn!("{}", error_msg);
+    }
+    for (root, workspace_metadata) in workspaces {
         if let Some(workspace_name) = root.file_name() {
-            let workspace_metadata = MetadataCommand::new()
-                .current_dir(root.clone())
-                .no_deps()
-                .exec()
-                .unwrap();
             for package in workspace_metadata.packages {
                 match Result::new(
                     workspace_name.to_string_lossy().to_string(),
                     package.clone(),
                     working_directory.clone(),
                 ) {
-                    Ok(package) => {
-                        packages.insert(package.package.clone(), package);
+                    Ok(mut result) => {
+                        if options.validate_cargo_package && result.publish_detail.cargo.publish {
+                            result.publish_detail.cargo.validate_metadata(&package);
+                        }
+                        check_duplicate(&packages, &result, options.strict_workspace)?;
+                        events.emit(Event::PackageResolved {
+                            workspace: &result.workspace,
+                            package: &result.package,
+                            version: &result.version,
+                        });
+                        packages.insert(result.package.clone(), result);
                     }
                     Err(e) => {
                         let error_msg = format!("Could not check package {}: {}", package.name, e);
@@ -379,6 +1014,48 @@ pub async fn check_workspace(
             }
         }
     }
+    let mut external: Vec<String> = packages
+        .values()
+        .filter(|p| !p.external_path_dependencies.is_empty())
+        .map(|p| format!("{}: {}", p.package, p.external_path_dependencies.join(", ")))
+        .collect();
+    if !external.is_empty() {
+        external.sort();
+        anyhow::bail!(
+            "Path dependencies outside of the repository, list them in `package.metadata.fslabs.allowed_external_paths` if expected:\n{}",
+            external.join("\n")
+        );
+    }
+    // Members without a Cargo.toml are described by a fslabs.toml
+    for descriptor in find_descriptors(&working_directory) {
+        match Result::from_descriptor(&descriptor, &working_directory) {
+            Ok(result) => {
+                check_duplicate(&packages, &result, options.strict_workspace)?;
+                events.emit(Event::PackageResolved {
+                    workspace: &result.workspace,
+                    package: &result.package,
+                    version: &result.version,
+                });
+                packages.insert(result.package.clone(), result);
+            }
+            Err(e) => {
+                let error_msg = format!(
+                    "Could not check member {}: {}",
+                    descriptor.to_string_lossy(),
+                    e
+                );
+                if options.fail_unit_error {
+                    anyhow::bail!(error_msg)
+                } else {
+                    log::warn!("{}", error_msg);
+                }
+            }
+        }
+    }
+    events.emit(Event::StepStarted {
+        step: 3,
+        name: "Checking published status",
+    });
     if options.progress {
         println!(
             "{} {}Checking published status...",
@@ -389,10 +1066,103 @@ pub async fn check_workspace(
 
     let package_keys: Vec<String> = packages.keys().cloned().collect();
 
+    let mut secrets = match options.secrets_backend {
+        _ if options.offline => None,
+        Some(SecretsBackend::Vault) => {
+            match (options.vault_address.clone(), options.vault_token.clone()) {
+                (Some(address), Some(token)) => Some(SecretsProvider::Vault(Box::new(
+                    VaultSecrets::new(address, token, options.vault_secret_path.clone())?,
+                ))),
+                _ => anyhow::bail!(
+                    "The vault secrets backend requires --vault-address and --vault-token"
+                ),
+            }
+        }
+        Some(SecretsBackend::AzureKeyVault) => match (
+            options.azure_keyvault_url.clone(),
+            options.azure_tenant_id.clone(),
+            options.azure_client_id.clone(),
+            std::env::var("AZURE_CLIENT_SECRET").ok(),
+        ) {
+            (Some(url), Some(tenant_id), Some(client_id), Some(client_secret)) => {
+                Some(SecretsProvider::AzureKeyVault(Box::new(AzureKeyVaultSecrets::new(
+                    url,
+                    options.azure_authority_host.clone(),
+                    tenant_id,
+                    client_id,
+                    client_secret,
+                )?)))
+            }
+            _ => anyhow::bail!(
+                "The azure key vault secrets backend requires --azure-keyvault-url, --azure-tenant-id, --azure-client-id and AZURE_CLIENT_SECRET"
+            ),
+        },
+        Some(SecretsBackend::AwsSecretsManager) => {
+            match (options.aws_region.clone(), AwsCredentials::from_env()) {
+                (Some(region), Some(credentials)) => {
+                    Some(SecretsProvider::AwsSecretsManager(Box::new(AwsSecrets::new(
+                        region,
+                        options.aws_secret_id.clone(),
+                        options.aws_endpoint_url.clone(),
+                        credentials,
+                    )?)))
+                }
+                _ => anyhow::bail!(
+                    "The aws secrets manager backend requires --aws-region, AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY"
+                ),
+            }
+        }
+        // The credentials stored by `fslabscli login` are only read when asked to
+        Some(SecretsBackend::Keyring) => Some(SecretsProvider::Keyring),
+        None => None,
+    };
+    let npm_registry_token = resolve_secret(
+        &mut secrets,
+        options.npm_registry_token.clone(),
+        "npm_registry_token",
+        &events,
+    )
+    .await?;
+    let cargo_registry_user_agent = resolve_secret(
+        &mut secrets,
+        options.cargo_registry_user_agent.clone(),
+        "cargo_registry_user_agent",
+        &events,
+    )
+    .await?;
+    let cargo_registry_token = resolve_secret(
+        &mut secrets,
+        options.cargo_registry_token.clone(),
+        "cargo_registry_token",
+        &events,
+    )
+    .await?;
+    let docker_registry_password = resolve_secret(
+        &mut secrets,
+        options.docker_registry_password.clone(),
+        "docker_registry_password",
+        &events,
+    )
+    .await?;
+    let pypi_registry_token = resolve_secret(
+        &mut secrets,
+        options.pypi_registry_token.clone(),
+        "pypi_registry_token",
+        &events,
+    )
+    .await?;
+    let binary_store_access_key = resolve_secret(
+        &mut secrets,
+        options.binary_store_access_key.clone(),
+        "binary_store_access_key",
+        &events,
+    )
+    .await?;
+
     // TODO: switch to an ASYNC_ONCE or something
     let npm = Npm::new(
         options.npm_registry_url.clone(),
-        options.npm_registry_token.clone(),
+        npm_registry_token,
         options.npm_registry_npmrc_path.clone(),
         true,
     )?;
@@ -404,21 +1174,23 @@ pub async fn check_workspace(
         cargo.add_registry(
             private_registry,
             private_registry_url,
-            options.cargo_registry_user_agent.clone(),
+            cargo_registry_user_agent,
+            cargo_registry_token,
         )?;
     }
+    let pypi = PyPi::new(options.pypi_registry_url.clone(), pypi_registry_token)?;
     let mut docker = Docker::new(None)?;
     if let (Some(docker_registry), Some(docker_username), Some(docker_password)) = (
         options.docker_registry.clone(),
         options.docker_registry_username.clone(),
-        options.docker_registry_password.clone(),
+        docker_registry_password,
     ) {
         docker.add_registry_auth(docker_registry, docker_username, docker_password)
     }
     let binary_store = BinaryStore::new(
-        options.binary_store_storage_account,
-        options.binary_store_container_name,
-        options.binary_store_access_key,
+        options.binary_store_storage_account.clone(),
+        options.binary_store_container_name.clone(),
+        binary_store_access_key,
     )?;
     let mut pb: Option<ProgressBar> = None;
     if options.progress {
@@ -466,11 +1238,45 @@ pub async fn check_workspace(
             if let Some(ref pb) = pb {
                 pb.set_message(format!("{} : {}", package.workspace, package.package));
             }
-            if options.check_publish {
+            package.publish_detail.retain_targets(|name| {
+                (options.only_targets.is_empty() || options.only_targets.iter().any(|t| t == name))
+                    && !options.skip_targets.iter().any(|t| t == name)
+            });
+            if options.offline {
+                package.unchecked_targets = package
+                    .publish_detail
+                    .targets()
+                    .into_iter()
+                    .filter(|(_, publish, _)| *publish)
+                    .map(|(name, _, _)| name.to_string())
+                    .collect();
+                package.publish_detail.disable();
+            } else if options.check_publish {
+                if package.publish_detail.docker.publish {
+                    let package_path = working_directory.join(&package.path);
+                    let (dockerfile, context) = package.publish_detail.docker_build_paths();
+                    if let Err(e) = package
+                        .publish_detail
+                        .docker
+                        .compute_input_digest(
+                            &package_path.join(dockerfile),
+                            &package_path.join(context),
+                            &mut docker,
+                        )
+                        .await
+                    {
+                        log::warn!(
+                            "Could not compute docker inputs digest of {}: {}",
+                            package.package,
+                            e
+                        );
+                    }
+                }
                 match package
                     .check_publishable(
                         &npm,
                         &cargo,
+                        &pypi,
                         &mut docker,
                         &binary_store,
                         release_channel,
@@ -494,16 +1300,41 @@ pub async fn check_workspace(
                         }
                     }
                 }
+                if options.validate_cargo_package && package.publish_detail.cargo.publish {
+                    let cargo_detail = &mut package.publish_detail.cargo;
+                    if let Err(e) = cargo_detail.validate_package(
+                        &working_directory.join(&package.path),
+                        &package.package,
+                        &package.version,
+                    ) {
+                        cargo_detail.validation_errors.push(e.to_string());
+                    }
+                    if !cargo_detail.validation_errors.is_empty() {
+                        let error_msg = format!(
+                            "Crate {} -- {} is not ready to be published: {}",
+                            package.workspace,
+                            package.package,
+                            cargo_detail.validation_errors.join(", ")
+                        );
+                        if options.fail_unit_error {
+                            anyhow::bail!(error_msg)
+                        }
+                        log::warn!("{}", error_msg);
+                        cargo_detail.publish = false;
+                        cargo_detail.error = Some(error_msg);
+                    }
+                }
             }
 
-            package.publish = vec![
-                package.publish_detail.docker.publish,
-                package.publish_detail.cargo.publish,
-                package.publish_detail.npm_napi.publish,
-                package.publish_detail.binary.publish,
-            ]
-            .into_iter()
-            .any(|x| x);
+            // Only crates cargo publishes are validated, their errors being kept in `error`
+            if !package.publish_detail.cargo.publish {
+                package.publish_detail.cargo.validation_errors.clear();
+            }
+            package.publish = package
+                .publish_detail
+                .targets()
+                .into_iter()
+                .any(|(_, publish, _)| publish);
 
             // If we are in a tag, we are only looking for the packages that build a launcher or installer. Otherwise, we are looking at all the packages
             let package_key = package.package.clone();
@@ -524,9 +1355,18 @@ pub async fn check_workspace(
                     }
                 }
             }
+            events.emit(Event::PackageChecked {
+                workspace: &package.workspace,
+                package: &package.package,
+                publish: package.publish,
+            });
         }
     }
 
+    events.emit(Event::StepStarted {
+        step: 4,
+        name: "Filtering packages dependencies",
+    });
     if options.progress {
         println!(
             "{} {}Filtering packages dependencies...",
@@ -565,6 +1405,10 @@ pub async fn check_workspace(
         }
     }
     // 4 Feed Dependent
+    events.emit(Event::StepStarted {
+        step: 5,
+        name: "Feeding packages dependant",
+    });
     if options.progress {
         println!(
             "{} {}Feeding packages dependant...",
@@ -600,7 +1444,12 @@ pub async fn check_workspace(
             }
         }
     }
+    feed_dependents(&mut packages, options.hide_dependencies);
 
+    events.emit(Event::StepStarted {
+        step: 6,
+        name: "Checking if packages changed",
+    });
     if options.progress {
         println!(
             "{} {}Checking if packages changed...",
@@ -618,10 +1467,23 @@ pub async fn check_workspace(
             .filter_map(|t| t.ok())
             .map(|e| e.into_path())
             .collect();
+        let (head_rev, base_rev) = changed_revisions(
+            options.changed_head_ref.clone(),
+            options.changed_base_ref.clone(),
+        );
+        log::info!("Head revision {} from {}", head_rev.rev, head_rev.source);
+        log::info!("Base revision {} from {}", base_rev.rev, base_rev.source);
+        let (head_oid, base_oid) = resolve_revisions(
+            &working_directory,
+            &head_rev.rev,
+            &base_rev.rev,
+            options.diff_strategy,
+            options.deepen_shallow,
+        )?;
         let repository = Repository::open(working_directory.clone())?;
         // Get the commits objects based on the head ref and base ref
-        let head_commit = repository.revparse_single(&options.changed_head_ref)?;
-        let base_commit = repository.revparse_single(&options.changed_base_ref)?;
+        let head_commit = repository.find_object(head_oid, None)?;
+        let base_commit = repository.find_object(base_oid, None)?;
         // Get the tree for the commits
         let head_tree = head_commit.peel_to_tree()?;
         let base_tree = base_commit.peel_to_tree()?;
@@ -669,27 +1531,72 @@ pub async fn check_workspace(
                         None => false,
                     }
                 };
+                let (ignore_submodule_changes, ignore_lfs_changes) =
+                    (package.ignore_submodule_changes, package.ignore_lfs_changes);
+                let mut changed_files: Vec<PathBuf> = vec![];
                 let mut file_cb = |delta: DiffDelta, _: f32| -> bool {
-                    let check_old_file = check_path(delta.old_file().path());
-                    let check_new_file = check_path(delta.new_file().path());
-                    if check_old_file || check_new_file {
+                    let Some(path) = delta.new_file().path().or(delta.old_file().path()) else {
+                        return true;
+                    };
+                    let mode = match delta.new_file().mode() {
+                        FileMode::Unreadable => delta.old_file().mode(),
+                        mode => mode,
+                    };
+                    let considered = match change_kind(&repository, path, mode) {
+                        // Whether the submodule is checked out does not matter, only its pointer does
+                        ChangeKind::Submodule => {
+                            !ignore_submodule_changes
+                                && (package_folder.is_empty() || path.starts_with(&package_folder))
+                        }
+                        ChangeKind::Lfs if ignore_lfs_changes => false,
+                        _ => {
+                            check_path(delta.old_file().path())
+                                || check_path(delta.new_file().path())
+                        }
+                    };
+                    if considered {
                         let old_oid = delta.old_file().id();
                         let new_oid = delta.new_file().id();
                         if old_oid != new_oid {
-                            package.changed = true;
-                            return false;
+                            if let Some(p) = delta.new_file().path().or(delta.old_file().path()) {
+                                changed_files.push(p.to_path_buf());
+                            }
                         }
                     }
                     true
                 };
-                if diff.foreach(&mut file_cb, None, None, None).is_err() {
+                if diff.foreach(&mut file_cb, None, None, None).is_err() && changed_files.is_empty()
+                {
                     continue;
                 }
+                package.changed = !changed_files.is_empty();
+                if package.changed && !options.no_tia {
+                    let package_files: Vec<PathBuf> = changed_files
+                        .iter()
+                        .filter_map(|f| f.strip_prefix(&package_folder).ok())
+                        .map(|f| f.to_path_buf())
+                        .collect();
+                    package.changed_targets = affected_targets(&package.targets, &package_files);
+                }
+                package.changed_files_count = changed_files.len();
+                changed_files.truncate(options.max_changed_files);
+                package.changed_files = changed_files;
+                if package.changed {
+                    events.emit(Event::PackageChanged {
+                        workspace: &package.workspace,
+                        package: &package.package,
+                        files: &package.changed_files,
+                    });
+                }
             }
         }
         // Now that git changes has been checked, we should loop through all package, if it has changed, we should mark
         // all it's dependant recursively as changed
     }
+    events.emit(Event::StepStarted {
+        step: 7,
+        name: "Marking packages dependency as changed",
+    });
     if options.progress {
         println!(
             "{} {}Marking packages dependency as changed...",
@@ -698,58 +1605,242 @@ pub async fn check_workspace(
         );
     }
     if options.check_changed {
-        if options.progress {
-            pb = Some(ProgressBar::new(packages.len() as u64).with_style(
-                ProgressStyle::with_template("{spinner} {wide_msg} {pos}/{len}")?,
-            ));
-        }
-
-        // Check changed from a git pov
-        for package_key in package_keys.clone() {
-            if let Some(ref pb) = pb {
-                pb.inc(1);
+        let changed: Vec<String> = packages
+            .values()
+            .filter(|p| p.changed)
+            .map(|p| p.package.clone())
+            .collect();
+        for key in dependants_closure(&packages, &changed) {
+            if let Some(package) = packages.get_mut(&key) {
+                package.dependencies_changed = true;
             }
-            if let Some(package) = packages.get(&package_key) {
-                if let Some(ref pb) = pb {
-                    pb.set_message(format!("{} : {}", package.workspace, package.package));
-                }
-                if !package.changed {
-                    continue;
-                }
-                if package.dependencies_changed {
-                    // We already treated it's tree
-                    continue;
-                }
-                let dependant: Vec<String> = package
-                    .dependant
-                    .iter()
-                    .map(|p| p.package.clone())
-                    .collect();
-                mark_dependants_as_changed(&mut packages, &dependant);
+        }
+        // Changes in a dependency can affect any test
+        for package in packages.values_mut() {
+            if package.changed && !package.dependencies_changed {
+                package.test_filter = nextest_filter(&package.package, &package.changed_targets);
             }
         }
     }
+    if let Some(selection) = &options.select {
+        packages.retain(|_, p| selection.matches(p));
+    }
+    if !options.affected_by.is_empty() {
+        let affected = affected_by(&packages, &options.affected_by);
+        packages.retain(|k, _| affected.contains(k));
+    }
     if options.progress {
         println!("{} Done in {}", SPARKLE, HumanDuration(started.elapsed()));
     }
+    events.emit(Event::Finished {
+        packages: packages.len(),
+        duration_ms: started.elapsed().as_millis(),
+    });
 
     Ok(Results(packages))
 }
 
-fn mark_dependants_as_changed(all_packages: &mut HashMap<String, Result>, changed: &Vec<String>) {
-    for package_key in changed {
-        if let Some(package) = all_packages.get_mut(package_key) {
-            if package.dependencies_changed {
-                // already treated
-                continue;
-            }
-            package.dependencies_changed = true;
-            let dependant: Vec<String> = package
-                .dependant
-                .iter()
-                .map(|p| p.package.clone())
-                .collect();
-            mark_dependants_as_changed(all_packages, &dependant);
-        }
+#[cfg(test)]
+mod tests {
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+    use cargo_metadata::MetadataCommand;
+
+    use super::*;
+
+    #[test]
+    fn test_external_path_dependencies() {
+        let tmp = TempDir::new().expect("Could not create temp dir");
+        tmp.child("vendored/Cargo.toml")
+            .write_str("[package]\nname = \"vendored\"\nversion = \"0.1.0\"\n")
+            .expect("Could not write manifest");
+        tmp.child("vendored/src/lib.rs")
+            .touch()
+            .expect("Could not write lib.rs");
+        let manifest = |allowed: &str| {
+            format!(
+                "[package]\nname = \"member\"\nversion = \"0.1.0\"\n\n[dependencies]\nvendored = {{ path = \"../../vendored\" }}\n\n[package.metadata.fslabs]\nallowed_external_paths = [{}]\n",
+                allowed
+            )
+        };
+        tmp.child("repo/member/src/lib.rs")
+            .touch()
+            .expect("Could not write lib.rs");
+        let root = tmp.path().canonicalize().expect("Could not canonicalize");
+        let load = |allowed: &str| {
+            tmp.child("repo/member/Cargo.toml")
+                .write_str(&manifest(allowed))
+                .expect("Could not write manifest");
+            let metadata = MetadataCommand::new()
+                .current_dir(root.join("repo/member"))
+                .no_deps()
+                .exec()
+                .expect("Could not load metadata");
+            Result::new(
+                "member".to_string(),
+                metadata.packages[0].clone(),
+                root.join("repo"),
+            )
+            .expect("Could not load package")
+        };
+        assert_eq!(
+            load("").external_path_dependencies,
+            vec![format!(
+                "vendored ({})",
+                root.join("vendored").to_string_lossy()
+            )]
+        );
+        assert!(load("\"vendored\"").external_path_dependencies.is_empty());
+    }
+
+    #[test]
+    fn test_tags() {
+        let tags = |fslabs: serde_json::Value| {
+            from_value::<PackageMetadata>(serde_json::json!({ "fslabs": fslabs }))
+                .expect("Could not parse metadata")
+                .fslabs
+                .tags
+        };
+        assert_eq!(tags(serde_json::json!({ "tags": ["gpu"] })), vec!["gpu"]);
+        assert_eq!(
+            tags(
+                serde_json::json!({ "tags": { "gpu": true, "windows-only": false, "team-x": true } })
+            ),
+            vec!["gpu", "team-x"]
+        );
+        assert!(tags(serde_json::json!({})).is_empty());
+        assert!(
+            from_value::<PackageMetadata>(serde_json::json!({ "fslabs": { "tags": 1 } })).is_err()
+        );
+    }
+    #[test]
+    fn test_load_results() {
+        let tmp = TempDir::new().expect("Could not create temp dir");
+        let mut member = Result {
+            workspace: "ws".to_string(),
+            package: "member".to_string(),
+            path: PathBuf::from("member"),
+            changed: true,
+            changed_files: vec![PathBuf::from("member/src/lib.rs")],
+            ..Default::default()
+        };
+        member.publish_detail.docker.publish = true;
+        let output =
+            serde_json::to_string(&Results(HashMap::from([("ws/member".to_string(), member)])))
+                .expect("Could not serialize results");
+        tmp.child("workspace.json")
+            .write_str(&output)
+            .expect("Could not write results");
+        let results = Results::load(&tmp.child("workspace.json")).expect("Could not load results");
+        let member = &results.0["ws/member"];
+        assert!(member.changed);
+        assert!(member.publish_detail.docker.publish);
+        assert_eq!(
+            member.changed_files,
+            vec![PathBuf::from("member/src/lib.rs")]
+        );
+        tmp.child("invalid.json")
+            .write_str("{\"ws/member\": 1}")
+            .expect("Could not write results");
+        assert!(Results::load(&tmp.child("invalid.json")).is_err());
+        tmp.child("newer.json")
+            .write_str("{\"schema_version\": 999}")
+            .expect("Could not write results");
+        assert!(Results::load(&tmp.child("newer.json")).is_err());
+    }
+
+    #[test]
+    fn test_retain_targets() {
+        let mut publish = PackageMetadataFslabsCiPublish::default();
+        publish.docker.publish = true;
+        publish.cargo.publish = true;
+        publish.npm_napi.publish = true;
+        publish.custom.push(PackageMetadataFslabsCiPublishCustom {
+            publish: true,
+            ..Default::default()
+        });
+        publish.retain_targets(|name| ["docker", "custom"].contains(&name));
+        let published: Vec<&str> = publish
+            .targets()
+            .into_iter()
+            .filter(|(_, publish, _)| *publish)
+            .map(|(name, _, _)| name)
+            .collect();
+        assert_eq!(published, vec!["docker", "custom"]);
+    }
+
+    #[test]
+    fn test_configures_target() {
+        let mut member = Result {
+            already_published: vec!["docs".to_string()],
+            ..Default::default()
+        };
+        member.publish_detail.docker.publish = true;
+        assert!(member.configures_target("docs"));
+        assert!(member.configures_target("docker"));
+        assert!(!member.configures_target("cargo"));
+    }
+
+    #[tokio::test]
+    async fn test_no_tia_counts_every_changed_file() {
+        let tmp = TempDir::new().expect("Could not create temp dir");
+        tmp.child("Cargo.toml")
+            .write_str("[package]\nname = \"member\"\nversion = \"0.1.0\"\n")
+            .expect("Could not write manifest");
+        tmp.child("src/lib.rs")
+            .write_str("mod extra;\n")
+            .expect("Could not write lib.rs");
+        tmp.child("src/extra.rs")
+            .touch()
+            .expect("Could not write extra.rs");
+        let repository = Repository::init(tmp.path()).expect("Could not init repository");
+        let signature =
+            git2::Signature::now("test", "test@example.com").expect("Invalid signature");
+        let commit = |message: &str| {
+            let mut index = repository.index().expect("Could not get index");
+            index
+                .add_all(["Cargo.toml", "src"], git2::IndexAddOption::DEFAULT, None)
+                .expect("Could not add files");
+            let tree = repository
+                .find_tree(index.write_tree().expect("Could not write tree"))
+                .expect("Could not find tree");
+            let parent = repository.head().ok().and_then(|h| h.peel_to_commit().ok());
+            repository
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    message,
+                    &tree,
+                    &parent.iter().collect::<Vec<_>>(),
+                )
+                .expect("Could not commit");
+        };
+        commit("initial");
+        tmp.child("src/lib.rs")
+            .write_str("mod extra;\npub fn lib() {}\n")
+            .expect("Could not write lib.rs");
+        tmp.child("src/extra.rs")
+            .write_str("pub fn extra() {}\n")
+            .expect("Could not write extra.rs");
+        commit("change");
+
+        let options = Options {
+            check_changed: true,
+            changed_head_ref: Some("HEAD".to_string()),
+            changed_base_ref: Some("HEAD~".to_string()),
+            no_tia: true,
+            ..Options::new()
+        };
+        let results = check_workspace(
+            Box::new(options),
+            tmp.path().canonicalize().expect("Could not canonicalize"),
+        )
+        .await
+        .expect("Could not check workspace");
+        let member = results.0.values().next().expect("Missing member");
+        assert!(member.changed);
+        assert_eq!(member.changed_files_count, 2);
+        assert!(member.changed_targets.is_empty());
     }
 }
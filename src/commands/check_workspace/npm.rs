@@ -7,15 +7,17 @@ use anyhow::Context;
 use http_body_util::{BodyExt, Empty};
 use hyper::body::Bytes;
 use hyper::{Method, Request, Uri};
-use hyper_rustls::{ConfigBuilderExt, HttpsConnector};
-use hyper_util::client::legacy::connect::HttpConnector;
 use hyper_util::client::legacy::Client as HyperClient;
 use hyper_util::rt::TokioExecutor;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::http_client::{https_connector, HttpsClientConnector};
+use crate::utils::same_version;
+
 const NPM_DEFAULT_API_URL: &str = "https://registry.npmjs.org/";
 
-#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
 pub struct PackageMetadataFslabsCiPublishNpmNapi {
     pub publish: bool,
     pub scope: Option<String>,
@@ -143,7 +145,7 @@ struct NpmPackage {
 
 pub struct Npm {
     rc_config: NpmRCConfig,
-    client: HyperClient<HttpsConnector<HttpConnector>, Empty<Bytes>>,
+    client: HyperClient<HttpsClientConnector, Empty<Bytes>>,
 }
 
 impl Npm {
@@ -153,15 +155,7 @@ impl Npm {
         npmrc_path: Option<String>,
         tls: bool,
     ) -> anyhow::Result<Self> {
-        let https = hyper_rustls::HttpsConnectorBuilder::new()
-            .with_tls_config(
-                rustls::ClientConfig::builder()
-                    .with_native_roots()?
-                    .with_no_client_auth(),
-            )
-            .https_or_http()
-            .enable_http1()
-            .build();
+        let https = https_connector()?;
 
         Ok(Self {
             rc_config: NpmRCConfig::new(registry_url, registry_token, npmrc_path, tls),
@@ -228,7 +222,7 @@ impl Npm {
 
         let package: NpmPackage = serde_json::from_str(String::from_utf8_lossy(&body).as_ref())?;
         for (_, package_version) in package.versions {
-            if package_version.version == version {
+            if same_version(&package_version.version, &version) {
                 return Ok(true);
             }
         }
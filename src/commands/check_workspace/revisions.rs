@@ -0,0 +1,294 @@
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+use git2::{Object, Oid, Repository};
+
+/// Commits fetched at once when deepening a shallow clone
+const DEEPEN_STEP: u32 = 50;
+
+/// What the head revision is compared against to find changed packages
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug)]
+pub enum DiffStrategy {
+    /// The base revision itself
+    #[default]
+    Base,
+    /// The merge base of the head and base revisions, ignoring what landed on the base since
+    MergeBase,
+}
+
+/// Revision compared by `--check-changed`, and where it comes from
+#[derive(Debug, PartialEq)]
+pub struct Revision {
+    pub rev: String,
+    pub source: &'static str,
+}
+
+impl Revision {
+    fn new(rev: impl Into<String>, source: &'static str) -> Self {
+        Self {
+            rev: rev.into(),
+            source,
+        }
+    }
+}
+
+/// Head and base revisions to compare: the explicit flags, then the Prow or GitHub Actions
+/// environment, then `HEAD` against `HEAD~`
+pub fn changed_revisions(head: Option<String>, base: Option<String>) -> (Revision, Revision) {
+    changed_revisions_from(head, base, |key| env::var(key).ok())
+}
+
+fn changed_revisions_from(
+    head: Option<String>,
+    base: Option<String>,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> (Revision, Revision) {
+    let var = |key: &str| lookup(key).filter(|v| !v.is_empty());
+    let head = match head {
+        Some(head) => Revision::new(head, "--changed-head-ref"),
+        None => match (var("PULL_PULL_SHA"), var("GITHUB_SHA")) {
+            (Some(sha), _) => Revision::new(sha, "PULL_PULL_SHA"),
+            (None, Some(sha)) => Revision::new(sha, "GITHUB_SHA"),
+            _ => Revision::new("HEAD", "default"),
+        },
+    };
+    let base = match base {
+        Some(base) => Revision::new(base, "--changed-base-ref"),
+        None => match (var("PULL_BASE_SHA"), var("GITHUB_BASE_REF")) {
+            (Some(sha), _) => Revision::new(sha, "PULL_BASE_SHA"),
+            (None, Some(branch)) => Revision::new(format!("origin/{}", branch), "GITHUB_BASE_REF"),
+            _ => Revision::new("HEAD~", "default"),
+        },
+    };
+    (head, base)
+}
+
+/// Commit the head revision gets compared against
+pub fn diff_base<'r>(
+    repository: &'r Repository,
+    strategy: DiffStrategy,
+    head: &Object<'r>,
+    base: Object<'r>,
+) -> anyhow::Result<Object<'r>> {
+    match strategy {
+        DiffStrategy::Base => Ok(base),
+        DiffStrategy::MergeBase => {
+            let merge_base =
+                repository.merge_base(head.peel_to_commit()?.id(), base.peel_to_commit()?.id())?;
+            log::info!("Comparing against merge base {}", merge_base);
+            Ok(repository.find_object(merge_base, None)?)
+        }
+    }
+}
+
+/// Head commit and the commit it gets compared against. In a shallow clone missing them, missing
+/// commit SHAs and `origin/` branches are fetched, then up to `deepen` more commits of history
+/// before giving up.
+pub fn resolve_revisions(
+    path: &Path,
+    head: &str,
+    base: &str,
+    strategy: DiffStrategy,
+    deepen: Option<u32>,
+) -> anyhow::Result<(Oid, Oid)> {
+    let mut deepened = 0;
+    loop {
+        // Reopened on each attempt, so the shallow boundary and fetched objects are up to date
+        let repository = Repository::open(path)?;
+        let missing: Vec<&str> = [head, base]
+            .into_iter()
+            .filter(|r| repository.revparse_single(r).is_err())
+            .collect();
+        if missing.is_empty() {
+            let head_commit = repository.revparse_single(head)?;
+            match diff_base(
+                &repository,
+                strategy,
+                &head_commit,
+                repository.revparse_single(base)?,
+            ) {
+                Ok(base_commit) => return Ok((head_commit.id(), base_commit.id())),
+                Err(e) if !repository.is_shallow() => return Err(e),
+                Err(_) => {}
+            }
+        } else if !repository.is_shallow() {
+            anyhow::bail!("Unknown revisions: {}", missing.join(", "));
+        }
+        let missing = match missing.is_empty() {
+            true => format!("the merge base of {} and {}", head, base),
+            false => missing.join(", "),
+        };
+        if deepen.map(|d| deepened >= d).unwrap_or(true) {
+            anyhow::bail!(
+                "The repository is a shallow clone missing {}: fetch more history (e.g. `fetch-depth: 0`) or pass --deepen-shallow",
+                missing
+            );
+        }
+        deepened += DEEPEN_STEP;
+        log::info!(
+            "Shallow clone missing {}, fetching {} more commits",
+            missing,
+            DEEPEN_STEP
+        );
+        let mut fetch = Command::new("git");
+        fetch.arg("fetch").current_dir(path);
+        let unresolved: Vec<&str> = [head, base]
+            .into_iter()
+            .filter(|r| repository.revparse_single(r).is_err())
+            .collect();
+        // Commits like Prow's PULL_BASE_SHA may be on no fetched branch, deepening never finds them
+        let refspecs: Vec<String> = unresolved
+            .iter()
+            .filter_map(|r| match r.strip_prefix("origin/") {
+                Some(b) => Some(format!("{}:refs/remotes/origin/{}", b, b)),
+                None if Oid::from_str(r).is_ok() && r.len() == 40 => Some(r.to_string()),
+                None => None,
+            })
+            .collect();
+        match refspecs.is_empty() {
+            true => fetch.arg(format!("--deepen={}", DEEPEN_STEP)),
+            false => fetch
+                .arg(format!("--depth={}", deepened))
+                .arg("origin")
+                .args(refspecs),
+        };
+        if !fetch.status()?.success() {
+            anyhow::bail!("Could not fetch more history to find {}", missing);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use assert_fs::TempDir;
+    use git2::Signature;
+
+    use super::*;
+
+    fn commit(repository: &Repository, file: &str, parents: &[Oid]) -> Oid {
+        std::fs::write(
+            repository.workdir().expect("Bare repository").join(file),
+            file,
+        )
+        .expect("Could not write file");
+        let mut index = repository.index().expect("Could not get index");
+        index.add_path(Path::new(file)).expect("Could not add file");
+        let tree = repository
+            .find_tree(index.write_tree().expect("Could not write tree"))
+            .expect("Could not find tree");
+        let parents: Vec<_> = parents
+            .iter()
+            .map(|p| repository.find_commit(*p).expect("Could not find parent"))
+            .collect();
+        let signature = Signature::now("test", "test@example.com").expect("Invalid signature");
+        repository
+            .commit(
+                None,
+                &signature,
+                &signature,
+                file,
+                &tree,
+                &parents.iter().collect::<Vec<_>>(),
+            )
+            .expect("Could not commit")
+    }
+
+    #[test]
+    fn test_diff_base() {
+        let tmp = TempDir::new().expect("Could not create temp dir");
+        let repository = Repository::init(tmp.path()).expect("Could not init repository");
+        let fork = commit(&repository, "fork", &[]);
+        let main = commit(&repository, "main", &[fork]);
+        let branch = commit(&repository, "branch", &[fork]);
+        let object = |oid| repository.find_object(oid, None).expect("Missing object");
+        let head = object(branch);
+        assert_eq!(
+            diff_base(&repository, DiffStrategy::Base, &head, object(main))
+                .expect("Could not find base")
+                .id(),
+            main
+        );
+        assert_eq!(
+            diff_base(&repository, DiffStrategy::MergeBase, &head, object(main))
+                .expect("Could not find merge base")
+                .id(),
+            fork
+        );
+    }
+
+    #[test]
+    fn test_resolve_revisions_fetches_missing_sha() {
+        let tmp = TempDir::new().expect("Could not create temp dir");
+        let origin = Repository::init(tmp.path().join("origin")).expect("Could not init origin");
+        let fork = commit(&origin, "fork", &[]);
+        let main = commit(&origin, "main", &[fork]);
+        // Only on a branch the clone does not fetch, like a base SHA from Prow
+        let base = commit(&origin, "base", &[fork]);
+        origin
+            .reference("refs/heads/main", main, true, "main")
+            .expect("Could not create main");
+        origin
+            .reference("refs/heads/other", base, true, "other")
+            .expect("Could not create other");
+        origin
+            .set_head("refs/heads/main")
+            .expect("Could not set HEAD");
+        let status = Command::new("git")
+            .args(["clone", "--quiet", "--depth=1", "--single-branch"])
+            .arg(format!("file://{}", tmp.path().join("origin").display()))
+            .arg(tmp.path().join("clone"))
+            .status()
+            .expect("Could not run git clone");
+        assert!(status.success());
+        let clone = tmp.path().join("clone");
+        let base = base.to_string();
+        assert!(Repository::open(&clone)
+            .expect("Could not open clone")
+            .revparse_single(&base)
+            .is_err());
+        assert_eq!(
+            resolve_revisions(&clone, "HEAD", &base, DiffStrategy::Base, Some(1))
+                .expect("Could not resolve revisions"),
+            (main, Oid::from_str(&base).expect("Invalid oid"))
+        );
+    }
+
+    #[test]
+    fn test_changed_revisions() {
+        let prow = HashMap::from([("PULL_PULL_SHA", "abc"), ("PULL_BASE_SHA", "def")]);
+        let github = HashMap::from([
+            ("GITHUB_SHA", "123"),
+            ("GITHUB_BASE_REF", "main"),
+            ("PULL_BASE_SHA", ""),
+        ]);
+        let from = |vars: &HashMap<&str, &str>, head: Option<&str>| {
+            changed_revisions_from(head.map(|h| h.to_string()), None, |k| {
+                vars.get(k).map(|v| v.to_string())
+            })
+        };
+        assert_eq!(
+            from(&prow, None),
+            (
+                Revision::new("abc", "PULL_PULL_SHA"),
+                Revision::new("def", "PULL_BASE_SHA")
+            )
+        );
+        assert_eq!(
+            from(&github, Some("HEAD")),
+            (
+                Revision::new("HEAD", "--changed-head-ref"),
+                Revision::new("origin/main", "GITHUB_BASE_REF")
+            )
+        );
+        assert_eq!(
+            from(&HashMap::new(), None),
+            (
+                Revision::new("HEAD", "default"),
+                Revision::new("HEAD~", "default")
+            )
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use cargo_metadata::semver::Version;
+use object_store::{path::Path, ObjectStore};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::check_workspace::binary::BinaryStore;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct PackageMetadataFslabsCiPublishSchemas {
+    #[serde(default)]
+    pub publish: bool,
+    /// Path of the proto / json-schema files, relative to the package
+    #[serde(default = "default_schemas_path")]
+    pub path: String,
+    /// Last published bundle, to check backwards compatibility against
+    #[serde(skip_deserializing)]
+    pub previous_version: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl Default for PackageMetadataFslabsCiPublishSchemas {
+    fn default() -> Self {
+        Self {
+            publish: false,
+            path: default_schemas_path(),
+            previous_version: None,
+            error: None,
+        }
+    }
+}
+
+fn default_schemas_path() -> String {
+    "proto".to_string()
+}
+
+/// Highest published version strictly lower than `version`
+fn previous_version(published: &[String], version: &str) -> Option<String> {
+    let version = Version::parse(version).ok()?;
+    published
+        .iter()
+        .filter_map(|v| Version::parse(v).ok())
+        .filter(|v| *v < version)
+        .max()
+        .map(|v| v.to_string())
+}
+
+impl PackageMetadataFslabsCiPublishSchemas {
+    pub async fn check(
+        &mut self,
+        name: String,
+        version: String,
+        store: &Option<BinaryStore>,
+    ) -> anyhow::Result<()> {
+        if !self.publish {
+            return Ok(());
+        }
+        let Some(object_store) = store else {
+            return Ok(());
+        };
+        log::debug!(
+            "SCHEMAS: checking if schemas of version {} of {} already exists",
+            version,
+            name,
+        );
+        let prefix = Path::from(format!("schemas/{}", name));
+        let published: Vec<String> = object_store
+            .get_client()
+            .list_with_delimiter(Some(&prefix))
+            .await?
+            .objects
+            .into_iter()
+            .filter_map(|o| {
+                o.location
+                    .filename()
+                    .and_then(|f| f.strip_suffix(".tar.gz"))
+                    .map(|v| v.to_string())
+            })
+            .collect();
+        self.publish = !published.contains(&version);
+        self.previous_version = previous_version(&published, &version);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_previous_version() {
+        let published = vec![
+            "0.1.0".to_string(),
+            "0.2.0".to_string(),
+            "0.10.0".to_string(),
+            "latest".to_string(),
+        ];
+        assert_eq!(
+            previous_version(&published, "0.11.0"),
+            Some("0.10.0".to_string())
+        );
+        assert_eq!(
+            previous_version(&published, "0.2.0"),
+            Some("0.1.0".to_string())
+        );
+        assert_eq!(previous_version(&published, "0.1.0"), None);
+    }
+}
@@ -0,0 +1,667 @@
+use std::collections::HashMap;
+use std::env;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use http_body_util::BodyExt;
+use http_body_util::{Empty, Full};
+use hyper::body::Bytes;
+use hyper::{Method, Request, Uri};
+use hyper_util::client::legacy::Client as HyperClient;
+use hyper_util::rt::TokioExecutor;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::commands::check_workspace::events::{Event, EventStream};
+use crate::http_client::{https_connector, HttpsClientConnector};
+
+const KEYRING_SERVICE: &str = "fslabscli";
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum SecretsBackend {
+    Vault,
+    AzureKeyVault,
+    AwsSecretsManager,
+    Keyring,
+}
+
+#[derive(Deserialize, Debug)]
+struct VaultResponse {
+    data: HashMap<String, Value>,
+}
+
+/// Reads credentials from a HashiCorp Vault KV secret (v1 or v2).
+/// The whole secret is fetched once and cached for the lifetime of the command.
+pub struct VaultSecrets {
+    address: String,
+    token: String,
+    path: String,
+    client: HyperClient<HttpsClientConnector, Empty<Bytes>>,
+    cache: Option<HashMap<String, String>>,
+}
+
+impl VaultSecrets {
+    pub fn new(address: String, token: String, path: String) -> anyhow::Result<Self> {
+        let https = https_connector()?;
+        Ok(Self {
+            address,
+            token,
+            path,
+            client: HyperClient::builder(TokioExecutor::new()).build(https),
+            cache: None,
+        })
+    }
+
+    async fn load(&self) -> anyhow::Result<HashMap<String, String>> {
+        let url: Uri = format!(
+            "{}/v1/{}",
+            self.address.trim_end_matches('/'),
+            self.path.trim_start_matches('/')
+        )
+        .parse()?;
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri(url)
+            .header("Accept", "application/json")
+            .header("X-Vault-Token", self.token.clone())
+            .body(Empty::default())?;
+        let res = self
+            .client
+            .request(req)
+            .await
+            .with_context(|| "Could not fetch from vault")?;
+        if res.status().as_u16() >= 400 {
+            anyhow::bail!(
+                "Something went wrong while reading vault secret {}: {}",
+                self.path,
+                res.status()
+            );
+        }
+        let body = res
+            .into_body()
+            .collect()
+            .await
+            .with_context(|| "Could not get body from vault")?
+            .to_bytes();
+        let response: VaultResponse = serde_json::from_slice(&body)?;
+        // KV v2 nests the secret under `data.data`, KV v1 returns it directly under `data`
+        let data = match response.data.get("data") {
+            Some(Value::Object(inner)) => inner.clone().into_iter().collect(),
+            _ => response.data,
+        };
+        Ok(data
+            .into_iter()
+            .filter_map(|(k, v)| match v {
+                Value::String(s) => Some((k, s)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    pub async fn get(&mut self, key: &str) -> anyhow::Result<Option<String>> {
+        if self.cache.is_none() {
+            self.cache = Some(self.load().await?);
+        }
+        Ok(self.cache.as_ref().and_then(|c| c.get(key).cloned()))
+    }
+}
+
+#[derive(Deserialize)]
+struct AzureToken {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct AzureSecret {
+    value: String,
+}
+
+/// Reads credentials from Azure Key Vault, authenticating as a service principal.
+/// Key Vault names cannot hold underscores, so `npm_registry_token` is read from `npm-registry-token`.
+pub struct AzureKeyVaultSecrets {
+    vault_url: String,
+    authority_host: String,
+    tenant_id: String,
+    client_id: String,
+    client_secret: String,
+    client: HyperClient<HttpsClientConnector, Full<Bytes>>,
+    token: Option<String>,
+    cache: HashMap<String, Option<String>>,
+}
+
+impl AzureKeyVaultSecrets {
+    pub fn new(
+        vault_url: String,
+        authority_host: String,
+        tenant_id: String,
+        client_id: String,
+        client_secret: String,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            vault_url: vault_url.trim_end_matches('/').to_string(),
+            authority_host: authority_host.trim_end_matches('/').to_string(),
+            tenant_id,
+            client_id,
+            client_secret,
+            client: HyperClient::builder(TokioExecutor::new()).build(https_connector()?),
+            token: None,
+            cache: HashMap::new(),
+        })
+    }
+
+    async fn access_token(&mut self) -> anyhow::Result<String> {
+        if let Some(token) = &self.token {
+            return Ok(token.clone());
+        }
+        let body: String = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("grant_type", "client_credentials")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("client_secret", &self.client_secret)
+            .append_pair("scope", "https://vault.azure.net/.default")
+            .finish();
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(format!(
+                "{}/{}/oauth2/v2.0/token",
+                self.authority_host, self.tenant_id
+            ))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(Full::new(Bytes::from(body)))?;
+        let res = self
+            .client
+            .request(req)
+            .await
+            .with_context(|| "Could not authenticate to Azure")?;
+        if res.status().as_u16() >= 400 {
+            anyhow::bail!("Could not authenticate to Azure: {}", res.status());
+        }
+        let token: AzureToken =
+            serde_json::from_slice(&res.into_body().collect().await?.to_bytes())?;
+        self.token = Some(token.access_token.clone());
+        Ok(token.access_token)
+    }
+
+    async fn load(&mut self, key: &str) -> anyhow::Result<Option<String>> {
+        let token = self.access_token().await?;
+        let name = key.replace('_', "-");
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri(format!(
+                "{}/secrets/{}?api-version=7.4",
+                self.vault_url, name
+            ))
+            .header("Accept", "application/json")
+            .header("Authorization", format!("Bearer {}", token))
+            .body(Full::default())?;
+        let res = self
+            .client
+            .request(req)
+            .await
+            .with_context(|| "Could not fetch from Azure Key Vault")?;
+        match res.status().as_u16() {
+            404 => Ok(None),
+            status if status >= 400 => anyhow::bail!(
+                "Something went wrong while reading Azure Key Vault secret {}: {}",
+                name,
+                res.status()
+            ),
+            _ => {
+                let secret: AzureSecret =
+                    serde_json::from_slice(&res.into_body().collect().await?.to_bytes())?;
+                Ok(Some(secret.value))
+            }
+        }
+    }
+
+    pub async fn get(&mut self, key: &str) -> anyhow::Result<Option<String>> {
+        if let Some(value) = self.cache.get(key) {
+            return Ok(value.clone());
+        }
+        let value = self.load(key).await?;
+        self.cache.insert(key.to_string(), value.clone());
+        Ok(value)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AwsSecretValue {
+    secret_string: Option<String>,
+}
+
+/// Credentials of the AWS environment, as set by the usual `AWS_*` variables
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            access_key_id: env::var("AWS_ACCESS_KEY_ID").ok()?,
+            secret_access_key: env::var("AWS_SECRET_ACCESS_KEY").ok()?,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+fn hmac_sha256(key: &[u8], message: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// `Authorization` header of a request signed with AWS Signature Version 4.
+/// `headers` are the signed headers, `host` and `x-amz-date` included, with lowercase names.
+#[allow(clippy::too_many_arguments)]
+fn sign_v4(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, String)],
+    body: &str,
+    time: DateTime<Utc>,
+) -> String {
+    let mut headers = headers.to_vec();
+    headers.sort();
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        method,
+        path,
+        canonical_headers,
+        signed_headers,
+        hex::encode(Sha256::digest(body.as_bytes()))
+    );
+    let date = time.format("%Y%m%d").to_string();
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        time.format("%Y%m%dT%H%M%SZ"),
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key = [date.as_str(), region, service, "aws4_request"]
+        .iter()
+        .fold(
+            format!("AWS4{}", credentials.secret_access_key).into_bytes(),
+            |key, part| hmac_sha256(&key, part),
+        );
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id,
+        scope,
+        signed_headers,
+        hex::encode(hmac_sha256(&key, &string_to_sign))
+    )
+}
+
+/// Reads credentials from an AWS Secrets Manager secret holding a JSON object of key/value pairs.
+/// The whole secret is fetched once and cached for the lifetime of the command.
+pub struct AwsSecrets {
+    region: String,
+    secret_id: String,
+    endpoint: String,
+    credentials: AwsCredentials,
+    client: HyperClient<HttpsClientConnector, Full<Bytes>>,
+    cache: Option<HashMap<String, String>>,
+}
+
+impl AwsSecrets {
+    pub fn new(
+        region: String,
+        secret_id: String,
+        endpoint: Option<String>,
+        credentials: AwsCredentials,
+    ) -> anyhow::Result<Self> {
+        let endpoint = endpoint
+            .unwrap_or_else(|| format!("https://secretsmanager.{}.amazonaws.com", region))
+            .trim_end_matches('/')
+            .to_string();
+        Ok(Self {
+            region,
+            secret_id,
+            endpoint,
+            credentials,
+            client: HyperClient::builder(TokioExecutor::new()).build(https_connector()?),
+            cache: None,
+        })
+    }
+
+    async fn load(&self) -> anyhow::Result<HashMap<String, String>> {
+        let url: Uri = format!("{}/", self.endpoint).parse()?;
+        let host = url
+            .authority()
+            .map(|a| a.to_string())
+            .ok_or_else(|| anyhow::anyhow!("Invalid AWS endpoint {}", self.endpoint))?;
+        let body = serde_json::json!({ "SecretId": self.secret_id }).to_string();
+        let time = Utc::now();
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("host", host),
+            ("x-amz-date", time.format("%Y%m%dT%H%M%SZ").to_string()),
+            ("x-amz-target", "secretsmanager.GetSecretValue".to_string()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let authorization = sign_v4(
+            &self.credentials,
+            &self.region,
+            "secretsmanager",
+            "POST",
+            "/",
+            &headers,
+            &body,
+            time,
+        );
+        let mut req = Request::builder().method(Method::POST).uri(url);
+        for (name, value) in &headers {
+            req = req.header(*name, value);
+        }
+        let req = req
+            .header("Authorization", authorization)
+            .body(Full::new(Bytes::from(body)))?;
+        let res = self
+            .client
+            .request(req)
+            .await
+            .with_context(|| "Could not fetch from AWS Secrets Manager")?;
+        if res.status().as_u16() >= 400 {
+            anyhow::bail!(
+                "Something went wrong while reading AWS secret {}: {}",
+                self.secret_id,
+                res.status()
+            );
+        }
+        let secret: AwsSecretValue =
+            serde_json::from_slice(&res.into_body().collect().await?.to_bytes())?;
+        let values: HashMap<String, Value> =
+            serde_json::from_str(&secret.secret_string.unwrap_or_default())
+                .with_context(|| format!("AWS secret {} is not a JSON object", self.secret_id))?;
+        Ok(values
+            .into_iter()
+            .filter_map(|(k, v)| match v {
+                Value::String(s) => Some((k, s)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    pub async fn get(&mut self, key: &str) -> anyhow::Result<Option<String>> {
+        if self.cache.is_none() {
+            self.cache = Some(self.load().await?);
+        }
+        Ok(self.cache.as_ref().and_then(|c| c.get(key).cloned()))
+    }
+}
+
+/// Store a credential in the OS keyring, as done by `fslabscli login`
+pub fn set_keyring_secret(key: &str, value: &str) -> anyhow::Result<()> {
+    keyring::Entry::new(KEYRING_SERVICE, key)?.set_password(value)?;
+    Ok(())
+}
+
+fn get_keyring_secret(key: &str) -> Option<String> {
+    match keyring::Entry::new(KEYRING_SERVICE, key).and_then(|e| e.get_password()) {
+        Ok(value) => Some(value),
+        Err(keyring::Error::NoEntry) => None,
+        Err(e) => {
+            log::warn!("Could not read secret `{}` from the OS keyring: {}", key, e);
+            None
+        }
+    }
+}
+
+pub enum SecretsProvider {
+    Vault(Box<VaultSecrets>),
+    AzureKeyVault(Box<AzureKeyVaultSecrets>),
+    AwsSecretsManager(Box<AwsSecrets>),
+    Keyring,
+}
+
+impl SecretsProvider {
+    pub async fn get(&mut self, key: &str) -> anyhow::Result<Option<String>> {
+        match self {
+            Self::Vault(v) => v.get(key).await,
+            Self::AzureKeyVault(a) => a.get(key).await,
+            Self::AwsSecretsManager(a) => a.get(key).await,
+            Self::Keyring => Ok(get_keyring_secret(key)),
+        }
+    }
+
+    /// Where the secrets are read from, for the audit log
+    pub fn source(&self) -> String {
+        match self {
+            Self::Vault(v) => format!("vault {}", v.path),
+            Self::AzureKeyVault(a) => format!("azure key vault {}", a.vault_url),
+            Self::AwsSecretsManager(a) => {
+                format!("aws secrets manager {} ({})", a.secret_id, a.region)
+            }
+            Self::Keyring => "OS keyring".to_string(),
+        }
+    }
+}
+
+/// Returns `value` if set, otherwise looks `key` up in the secrets provider.
+/// Every resolved secret is logged and emitted as an event with its source, never its value.
+pub async fn resolve_secret(
+    provider: &mut Option<SecretsProvider>,
+    value: Option<String>,
+    key: &str,
+    events: &EventStream,
+) -> anyhow::Result<Option<String>> {
+    let (value, source) = match (value, provider) {
+        (Some(v), _) => (Some(v), "command line".to_string()),
+        (None, Some(p)) => (p.get(key).await?, p.source()),
+        (None, None) => (None, "".to_string()),
+    };
+    if value.is_some() {
+        log::info!("Resolved secret `{}` from {}", key, source);
+        events.emit(Event::SecretResolved {
+            key,
+            source: &source,
+        });
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::{body_json, header, header_exists, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn vault_kv2_secret_is_read_once() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/secret/data/fslabscli"))
+            .and(header("X-Vault-Token", "my_token"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"data":{"data":{"npm_registry_token":"npm","docker_registry_password":"docker"},"metadata":{"version":1}}}"#,
+                "application/json",
+            ))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let mut provider = Some(SecretsProvider::Vault(Box::new(
+            VaultSecrets::new(
+                mock_server.uri(),
+                "my_token".to_string(),
+                "secret/data/fslabscli".to_string(),
+            )
+            .expect("Could not create vault client"),
+        )));
+        let events = EventStream::new(false);
+        assert_eq!(
+            resolve_secret(&mut provider, None, "npm_registry_token", &events)
+                .await
+                .expect("Could not resolve secret"),
+            Some("npm".to_string())
+        );
+        assert_eq!(
+            resolve_secret(&mut provider, None, "docker_registry_password", &events)
+                .await
+                .expect("Could not resolve secret"),
+            Some("docker".to_string())
+        );
+        assert_eq!(
+            resolve_secret(
+                &mut provider,
+                Some("cli".to_string()),
+                "npm_registry_token",
+                &events
+            )
+            .await
+            .expect("Could not resolve secret"),
+            Some("cli".to_string())
+        );
+        assert_eq!(
+            resolve_secret(&mut provider, None, "unknown", &events)
+                .await
+                .expect("Could not resolve secret"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn azure_key_vault_secret_is_read_with_service_principal() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/tenant/oauth2/v2.0/token"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"access_token": "azure_token"})),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/secrets/npm-registry-token"))
+            .and(query_param("api-version", "7.4"))
+            .and(header("Authorization", "Bearer azure_token"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"value": "npm"})),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/secrets/unknown"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+        let mut provider = Some(SecretsProvider::AzureKeyVault(Box::new(
+            AzureKeyVaultSecrets::new(
+                mock_server.uri(),
+                mock_server.uri(),
+                "tenant".to_string(),
+                "client".to_string(),
+                "secret".to_string(),
+            )
+            .expect("Could not create key vault client"),
+        )));
+        let events = EventStream::new(false);
+        for _ in 0..2 {
+            assert_eq!(
+                resolve_secret(&mut provider, None, "npm_registry_token", &events)
+                    .await
+                    .expect("Could not resolve secret"),
+                Some("npm".to_string())
+            );
+        }
+        assert_eq!(
+            resolve_secret(&mut provider, None, "unknown", &events)
+                .await
+                .expect("Could not resolve secret"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn aws_secret_is_read_once() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/"))
+            .and(header("x-amz-target", "secretsmanager.GetSecretValue"))
+            .and(header("x-amz-security-token", "session"))
+            .and(header_exists("Authorization"))
+            .and(body_json(serde_json::json!({"SecretId": "fslabscli"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "Name": "fslabscli",
+                "SecretString": "{\"cargo_registry_token\":\"cargo\"}"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let mut provider = Some(SecretsProvider::AwsSecretsManager(Box::new(
+            AwsSecrets::new(
+                "eu-west-1".to_string(),
+                "fslabscli".to_string(),
+                Some(mock_server.uri()),
+                AwsCredentials {
+                    access_key_id: "key".to_string(),
+                    secret_access_key: "secret".to_string(),
+                    session_token: Some("session".to_string()),
+                },
+            )
+            .expect("Could not create secrets manager client"),
+        )));
+        let events = EventStream::new(false);
+        for key in ["cargo_registry_token", "unknown"] {
+            resolve_secret(&mut provider, None, key, &events)
+                .await
+                .expect("Could not resolve secret");
+        }
+        assert_eq!(
+            resolve_secret(&mut provider, None, "cargo_registry_token", &events)
+                .await
+                .expect("Could not resolve secret"),
+            Some("cargo".to_string())
+        );
+    }
+
+    #[test]
+    fn test_sign_v4() {
+        // `post-vanilla` case of the AWS Signature Version 4 test suite
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        };
+        let time = DateTime::parse_from_rfc3339("2015-08-30T12:36:00Z")
+            .expect("Invalid time")
+            .with_timezone(&Utc);
+        assert_eq!(
+            sign_v4(
+                &credentials,
+                "us-east-1",
+                "service",
+                "POST",
+                "/",
+                &[
+                    ("host", "example.amazonaws.com".to_string()),
+                    ("x-amz-date", "20150830T123600Z".to_string())
+                ],
+                "",
+                time
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, SignedHeaders=host;x-amz-date, Signature=5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b"
+        );
+    }
+}
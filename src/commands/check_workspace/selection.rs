@@ -0,0 +1,278 @@
+use std::str::FromStr;
+
+use crate::commands::check_workspace::Result as Package;
+
+/// Package selection expression.
+///
+/// Supported atoms are package name globs (`orica_*`), `workspace:<glob>`, `tag:<glob>` and the
+/// `all`, `changed`, `dependencies_changed` and `publish` keywords. They can be combined
+/// with `|` (union), `&` (intersection), `-` (difference), `!` (complement) and parentheses,
+/// e.g. `changed & publish - orica_*`. `&` and `-` bind tighter than `|`.
+/// As package names can contain dashes, `-` is only treated as an operator at the start of a token.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selection {
+    All,
+    Changed,
+    DependenciesChanged,
+    Publish,
+    Name(String),
+    Workspace(String),
+    Tag(String),
+    Not(Box<Selection>),
+    Union(Box<Selection>, Box<Selection>),
+    Intersection(Box<Selection>, Box<Selection>),
+    Difference(Box<Selection>, Box<Selection>),
+}
+
+impl Selection {
+    pub fn matches(&self, package: &Package) -> bool {
+        match self {
+            Self::All => true,
+            Self::Changed => package.changed,
+            Self::DependenciesChanged => package.dependencies_changed,
+            Self::Publish => package.publish,
+            Self::Name(pattern) => glob_match(pattern, &package.package),
+            Self::Workspace(pattern) => glob_match(pattern, &package.workspace),
+            Self::Tag(pattern) => package.tags.iter().any(|t| glob_match(pattern, t)),
+            Self::Not(s) => !s.matches(package),
+            Self::Union(l, r) => l.matches(package) || r.matches(package),
+            Self::Intersection(l, r) => l.matches(package) && r.matches(package),
+            Self::Difference(l, r) => l.matches(package) && !r.matches(package),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    Or,
+    And,
+    Minus,
+    Not,
+    Atom(String),
+}
+
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::LParen),
+            ')' => tokens.push(Token::RParen),
+            '|' => tokens.push(Token::Or),
+            '&' => tokens.push(Token::And),
+            '-' => tokens.push(Token::Minus),
+            '!' => tokens.push(Token::Not),
+            c => {
+                let mut atom = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || "()|&!".contains(next) {
+                        break;
+                    }
+                    atom.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Atom(atom));
+            }
+        }
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    // union := intersection ('|' intersection)*
+    fn parse_union(&mut self) -> anyhow::Result<Selection> {
+        let mut left = self.parse_intersection()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            let right = self.parse_intersection()?;
+            left = Selection::Union(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    // intersection := unary (('&' | '-') unary)*
+    fn parse_intersection(&mut self) -> anyhow::Result<Selection> {
+        let mut left = self.parse_unary()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.next();
+                    let right = self.parse_unary()?;
+                    left = Selection::Intersection(Box::new(left), Box::new(right));
+                }
+                Some(Token::Minus) => {
+                    self.next();
+                    let right = self.parse_unary()?;
+                    left = Selection::Difference(Box::new(left), Box::new(right));
+                }
+                _ => return Ok(left),
+            }
+        }
+    }
+
+    // unary := '!' unary | '(' union ')' | atom
+    fn parse_unary(&mut self) -> anyhow::Result<Selection> {
+        match self.next() {
+            Some(Token::Not) => Ok(Selection::Not(Box::new(self.parse_unary()?))),
+            Some(Token::LParen) => {
+                let inner = self.parse_union()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(inner),
+                    _ => anyhow::bail!("Unbalanced parenthesis in selection expression"),
+                }
+            }
+            Some(Token::Atom(atom)) => Ok(parse_atom(&atom)),
+            Some(t) => anyhow::bail!("Unexpected token {:?} in selection expression", t),
+            None => anyhow::bail!("Unexpected end of selection expression"),
+        }
+    }
+}
+
+fn parse_atom(atom: &str) -> Selection {
+    if let Some(workspace) = atom.strip_prefix("workspace:") {
+        return Selection::Workspace(workspace.to_string());
+    }
+    if let Some(tag) = atom.strip_prefix("tag:") {
+        return Selection::Tag(tag.to_string());
+    }
+    match atom {
+        "all" => Selection::All,
+        "changed" => Selection::Changed,
+        "dependencies_changed" => Selection::DependenciesChanged,
+        "publish" | "publishable" => Selection::Publish,
+        name => Selection::Name(name.to_string()),
+    }
+}
+
+impl FromStr for Selection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s),
+            position: 0,
+        };
+        let selection = parser.parse_union()?;
+        if let Some(t) = parser.peek() {
+            anyhow::bail!("Unexpected token {:?} in selection expression", t);
+        }
+        Ok(selection)
+    }
+}
+
+/// Match `text` against a glob `pattern` supporting `*` and `?` wildcards
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, changed: bool, publish: bool) -> Package {
+        Package {
+            workspace: "workspace_a".to_string(),
+            package: name.to_string(),
+            changed,
+            publish,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("orica_*", "orica_server"));
+        assert!(glob_match("*_server", "orica_server"));
+        assert!(glob_match("or?ca_*r", "orica_server"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("orica_*", "hub_server"));
+        assert!(!glob_match("orica", "orica_server"));
+    }
+
+    #[test]
+    fn test_selection_parsing() {
+        let selection: Selection = "changed & publish - orica_* | cargo-fslabscli"
+            .parse()
+            .expect("Could not parse selection");
+        assert_eq!(
+            selection,
+            Selection::Union(
+                Box::new(Selection::Difference(
+                    Box::new(Selection::Intersection(
+                        Box::new(Selection::Changed),
+                        Box::new(Selection::Publish)
+                    )),
+                    Box::new(Selection::Name("orica_*".to_string()))
+                )),
+                Box::new(Selection::Name("cargo-fslabscli".to_string()))
+            )
+        );
+        assert!("(changed".parse::<Selection>().is_err());
+        assert!("changed &".parse::<Selection>().is_err());
+        assert!("changed publish".parse::<Selection>().is_err());
+    }
+
+    #[test]
+    fn test_selection_matching() {
+        let selection: Selection = "(changed | publish) - !workspace:workspace_*"
+            .parse()
+            .expect("Could not parse selection");
+        assert!(selection.matches(&package("a", true, false)));
+        assert!(selection.matches(&package("b", false, true)));
+        assert!(!selection.matches(&package("c", false, false)));
+
+        let selection: Selection = "all - orica_*".parse().expect("Could not parse selection");
+        assert!(selection.matches(&package("hub_server", false, false)));
+        assert!(!selection.matches(&package("orica_server", true, true)));
+    }
+
+    #[test]
+    fn test_selection_tags() {
+        let selection: Selection = "changed & tag:gpu* - tag:experimental"
+            .parse()
+            .expect("Could not parse selection");
+        let mut gpu = package("a", true, false);
+        gpu.tags = vec!["gpu-cuda".to_string()];
+        assert!(selection.matches(&gpu));
+        gpu.tags.push("experimental".to_string());
+        assert!(!selection.matches(&gpu));
+        assert!(!selection.matches(&package("b", true, false)));
+    }
+}
@@ -0,0 +1,56 @@
+use object_store::{path::Path, ObjectStore};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::check_workspace::binary::BinaryStore;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct PackageMetadataFslabsCiPublishTerraform {
+    #[serde(default)]
+    pub publish: bool,
+    /// Path of the terraform module, relative to the package
+    #[serde(default = "default_terraform_path")]
+    pub path: String,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl Default for PackageMetadataFslabsCiPublishTerraform {
+    fn default() -> Self {
+        Self {
+            publish: false,
+            path: default_terraform_path(),
+            error: None,
+        }
+    }
+}
+
+fn default_terraform_path() -> String {
+    "terraform".to_string()
+}
+
+impl PackageMetadataFslabsCiPublishTerraform {
+    pub async fn check(
+        &mut self,
+        name: String,
+        version: String,
+        store: &Option<BinaryStore>,
+    ) -> anyhow::Result<()> {
+        if !self.publish {
+            return Ok(());
+        }
+        let Some(object_store) = store else {
+            return Ok(());
+        };
+        log::debug!(
+            "TERRAFORM: checking if module version {} of {} already exists",
+            version,
+            name,
+        );
+        // The module is versioned along with the crate
+        let archive_path = Path::from(format!("terraform/{}/{}-v{}.tar.gz", name, name, version));
+        self.publish = object_store.get_client().head(&archive_path).await.is_err();
+        Ok(())
+    }
+}
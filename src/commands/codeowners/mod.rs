@@ -0,0 +1,148 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::commands::check_workspace::{check_workspace_or_load, Options as CheckWorkspaceOptions};
+
+const HEADER: &str = "# Generated by `fslabscli codeowners` from package.metadata.fslabs.owners";
+
+#[derive(Debug, Parser)]
+#[command(about = "Generate the CODEOWNERS file from the owners of the workspace members.")]
+pub struct Options {
+    /// CODEOWNERS file to generate or check
+    #[arg(long, default_value = ".github/CODEOWNERS")]
+    output: PathBuf,
+    /// Fail if the CODEOWNERS file is outdated or members have no owners, instead of writing it
+    #[arg(long, default_value_t = false)]
+    check: bool,
+    /// `check-workspace --json` output to reuse instead of checking the workspace again, `-` for stdin
+    #[arg(long)]
+    workspace_json: Option<PathBuf>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct CodeownersResult {
+    output: PathBuf,
+    /// Members without owners
+    unowned: Vec<String>,
+    check: bool,
+}
+
+impl Display for CodeownersResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for member in &self.unowned {
+            writeln!(f, "{} has no owners", member)?;
+        }
+        match self.check {
+            true => write!(f, "{} is up to date", self.output.to_string_lossy()),
+            false => write!(f, "Wrote {}", self.output.to_string_lossy()),
+        }
+    }
+}
+
+/// One CODEOWNERS line per owned member, parents before children as the last matching line wins
+fn render_codeowners(members: &[(PathBuf, Vec<String>)]) -> String {
+    let mut lines: Vec<(String, String)> = members
+        .iter()
+        .filter(|(_, owners)| !owners.is_empty())
+        .map(|(path, owners)| {
+            let pattern = match path == Path::new(".") {
+                true => "*".to_string(),
+                false => format!("/{}/", path.to_string_lossy().replace('\\', "/")),
+            };
+            (pattern, owners.join(" "))
+        })
+        .collect();
+    lines.sort_by(|(a, _), (b, _)| (a != "*", a).cmp(&(b != "*", b)));
+    let mut content = format!("{}\n", HEADER);
+    for (pattern, owners) in lines {
+        content.push_str(&format!("{} {}\n", pattern, owners));
+    }
+    content
+}
+
+pub async fn codeowners(
+    options: Box<Options>,
+    working_directory: PathBuf,
+) -> anyhow::Result<CodeownersResult> {
+    let members = check_workspace_or_load(
+        options.workspace_json.as_deref(),
+        CheckWorkspaceOptions::new(),
+        working_directory.clone(),
+    )
+    .await?;
+    let mut unowned: Vec<String> = members
+        .0
+        .values()
+        .filter(|m| m.owners.is_empty())
+        .map(|m| m.package.clone())
+        .collect();
+    unowned.sort();
+    let owners: Vec<(PathBuf, Vec<String>)> = members
+        .0
+        .into_values()
+        .map(|m| (m.path, m.owners))
+        .collect();
+    let content = render_codeowners(&owners);
+    let output = working_directory.join(&options.output);
+    if options.check {
+        if fs::read_to_string(&output).unwrap_or_default() != content {
+            anyhow::bail!(
+                "{} is outdated, run `fslabscli codeowners` to update it",
+                output.to_string_lossy()
+            );
+        }
+        if !unowned.is_empty() {
+            anyhow::bail!("Members without owners: {}", unowned.join(", "));
+        }
+    } else {
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&output, content)?;
+    }
+    Ok(CodeownersResult {
+        output,
+        unowned,
+        check: options.check,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn test_render_codeowners() {
+        let members = vec![
+            (
+                PathBuf::from("apps/server/plugins"),
+                vec!["@org/plugins".to_string()],
+            ),
+            (
+                PathBuf::from("apps/server"),
+                vec!["@org/server".to_string()],
+            ),
+            (PathBuf::from("libs/unowned"), vec![]),
+            (
+                PathBuf::from("."),
+                vec!["@org/devops".to_string(), "@someone".to_string()],
+            ),
+        ];
+        assert_eq!(
+            render_codeowners(&members),
+            indoc! {"
+                # Generated by `fslabscli codeowners` from package.metadata.fslabs.owners
+                * @org/devops @someone
+                /apps/server/ @org/server
+                /apps/server/plugins/ @org/plugins
+            "}
+        );
+    }
+}
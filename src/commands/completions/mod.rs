@@ -0,0 +1,130 @@
+use clap::{Command, Parser};
+use clap_complete::{generate, Shell};
+
+#[derive(Debug, Parser)]
+#[command(
+    about = "Print the shell completion script, which also completes member names for bash, zsh and fish."
+)]
+pub struct Options {
+    #[arg(value_enum)]
+    shell: Shell,
+}
+
+/// Options whose values are member names, completed by listing them with `check-workspace --list-names`
+const MEMBER_OPTIONS: [&str; 1] = ["--select"];
+
+/// Wraps the generated bash completion to complete member names dynamically
+fn bash_member_completion(bin_name: &str) -> String {
+    let function = format!("_{}", bin_name.replace('-', "__"));
+    format!(
+        r#"
+{function}_members() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    case "${{COMP_WORDS[COMP_CWORD-1]}}" in
+        {options})
+            COMPREPLY=($(compgen -W "$({bin_name} check-workspace --list-names 2>/dev/null)" -- "$cur"))
+            return 0
+            ;;
+    esac
+    {function} "$@"
+}}
+
+complete -F {function}_members -o nosort -o bashdefault -o default {bin_name}
+"#,
+        function = function,
+        options = MEMBER_OPTIONS.join("|"),
+        bin_name = bin_name
+    )
+}
+
+/// Wraps the generated zsh completion to complete member names dynamically
+fn zsh_member_completion(bin_name: &str) -> String {
+    let function = format!("_{}", bin_name.replace('-', "__"));
+    format!(
+        r#"
+{function}_members() {{
+    case "${{words[CURRENT-1]}}" in
+        {options})
+            compadd -- ${{(f)"$({bin_name} check-workspace --list-names 2>/dev/null)"}}
+            ;;
+        *)
+            {function} "$@"
+            ;;
+    esac
+}}
+
+compdef {function}_members {bin_name}
+"#,
+        function = function,
+        options = MEMBER_OPTIONS.join("|"),
+        bin_name = bin_name
+    )
+}
+
+/// Adds member names to the generated fish completion of the member options
+fn fish_member_completion(bin_name: &str) -> String {
+    MEMBER_OPTIONS
+        .iter()
+        .map(|option| {
+            format!(
+                "complete -c {bin_name} -l {option} -x -a \"({bin_name} check-workspace --list-names 2>/dev/null)\"\n",
+                bin_name = bin_name,
+                option = option.trim_start_matches("--")
+            )
+        })
+        .collect()
+}
+
+pub async fn completions(options: Box<Options>, mut command: Command) -> anyhow::Result<String> {
+    let bin_name = command
+        .get_bin_name()
+        .unwrap_or_else(|| command.get_name())
+        .to_string();
+    let mut script: Vec<u8> = vec![];
+    generate(options.shell, &mut command, &bin_name, &mut script);
+    let mut script = String::from_utf8(script)?;
+    match options.shell {
+        Shell::Bash => script.push_str(&bash_member_completion(&bin_name)),
+        Shell::Zsh => script.push_str(&zsh_member_completion(&bin_name)),
+        Shell::Fish => script.push_str(&fish_member_completion(&bin_name)),
+        _ => log::warn!(
+            "Member names are not completed dynamically for {}",
+            options.shell
+        ),
+    }
+    Ok(script)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Arg;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_completions() {
+        let script = |shell| async move {
+            let command = Command::new("fslabscli")
+                .bin_name("fslabscli")
+                .subcommand(Command::new("check-workspace").arg(Arg::new("select").long("select")));
+            completions(Box::new(Options { shell }), command)
+                .await
+                .expect("Could not generate completions")
+        };
+        let bash = script(Shell::Bash).await;
+        assert!(bash.contains("_fslabscli() {"));
+        assert!(bash.contains("        --select)"));
+        assert!(bash.contains("fslabscli check-workspace --list-names"));
+        assert!(bash.ends_with(
+            "complete -F _fslabscli_members -o nosort -o bashdefault -o default fslabscli\n"
+        ));
+        let zsh = script(Shell::Zsh).await;
+        assert!(zsh.contains(
+            "compadd -- ${(f)\"$(fslabscli check-workspace --list-names 2>/dev/null)\"}"
+        ));
+        assert!(zsh.ends_with("compdef _fslabscli_members fslabscli\n"));
+        assert!(script(Shell::Fish).await.ends_with(
+            "complete -c fslabscli -l select -x -a \"(fslabscli check-workspace --list-names 2>/dev/null)\"\n"
+        ));
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use cargo_metadata::semver::Version;
+use clap::Parser;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::utils;
+
+#[derive(Debug, Parser)]
+#[command(
+    about = "Report crates resolved to several versions across the workspaces, as Markdown or JSON."
+)]
+pub struct Options {
+    /// Only report these crates
+    #[arg(long)]
+    only: Vec<String>,
+    /// Run `cargo metadata` even if the workspace manifests did not change since the last run
+    #[arg(long, default_value_t = false)]
+    refresh_metadata: bool,
+}
+
+#[derive(Serialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct DuplicateVersion {
+    pub version: String,
+    /// Packages whose requirements resolved to that version, as `workspace/member` for workspace
+    /// members and `name version` for other crates
+    pub pulled_by: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct DuplicateDependency {
+    pub name: String,
+    pub versions: Vec<DuplicateVersion>,
+    pub suggestion: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DependencyReportResult {
+    pub duplicates: Vec<DuplicateDependency>,
+}
+
+impl Display for DependencyReportResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "| Crate | Version | Pulled by |")?;
+        writeln!(f, "| ----- | ------- | --------- |")?;
+        for duplicate in &self.duplicates {
+            for version in &duplicate.versions {
+                writeln!(
+                    f,
+                    "| {} | {} | {} |",
+                    duplicate.name,
+                    version.version,
+                    version.pulled_by.join(", ")
+                )?;
+            }
+        }
+        writeln!(f)?;
+        for duplicate in &self.duplicates {
+            writeln!(f, "- **{}**: {}", duplicate.name, duplicate.suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Versions sharing that key are semver compatible, so `cargo update` can unify them
+pub(crate) fn compatibility_key(version: &Version) -> String {
+    match (version.major, version.minor) {
+        (0, 0) => format!("0.0.{}", version.patch),
+        (0, minor) => format!("0.{}", minor),
+        (major, _) => major.to_string(),
+    }
+}
+
+fn suggest(name: &str, versions: &[DuplicateVersion]) -> String {
+    let parsed: Vec<Version> = versions
+        .iter()
+        .filter_map(|v| Version::parse(&v.version).ok())
+        .collect();
+    let Some(highest) = parsed.iter().max() else {
+        return "".to_string();
+    };
+    let incompatible: BTreeSet<&str> = versions
+        .iter()
+        .filter(|v| {
+            Version::parse(&v.version)
+                .map(|v| compatibility_key(&v) != compatibility_key(highest))
+                .unwrap_or(false)
+        })
+        .flat_map(|v| v.pulled_by.iter().map(|p| p.as_str()))
+        .collect();
+    match incompatible.is_empty() {
+        true => format!("run `cargo update -p {}` to unify on {}", name, highest),
+        false => format!(
+            "unify on {} by upgrading the requirements of {}",
+            highest,
+            incompatible.into_iter().collect::<Vec<&str>>().join(", ")
+        ),
+    }
+}
+
+/// Group `(crate, version, requester)` usages into the crates resolved to more than one version
+fn collect_duplicates(usages: Vec<(String, Version, String)>) -> Vec<DuplicateDependency> {
+    let mut crates: BTreeMap<String, BTreeMap<Version, BTreeSet<String>>> = BTreeMap::new();
+    for (name, version, requester) in usages {
+        crates
+            .entry(name)
+            .or_default()
+            .entry(version)
+            .or_default()
+            .insert(requester);
+    }
+    crates
+        .into_iter()
+        .filter(|(_, versions)| versions.len() > 1)
+        .map(|(name, versions)| {
+            let versions: Vec<DuplicateVersion> = versions
+                .into_iter()
+                .map(|(version, requesters)| DuplicateVersion {
+                    version: version.to_string(),
+                    pulled_by: requesters.into_iter().collect(),
+                })
+                .collect();
+            DuplicateDependency {
+                suggestion: suggest(&name, &versions),
+                name,
+                versions,
+            }
+        })
+        .collect()
+}
+
+pub async fn dependency_report(
+    options: Box<Options>,
+    working_directory: PathBuf,
+) -> anyhow::Result<DependencyReportResult> {
+    let roots = utils::get_cargo_roots(working_directory.clone())
+        .with_context(|| format!("Failed to get roots from {:?}", working_directory))?;
+    let mut usages = vec![];
+    for root in roots {
+        let workspace_name = root
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let metadata = utils::cargo_metadata_resolved(&root, options.refresh_metadata)
+            .with_context(|| format!("Could not resolve dependencies of {}", workspace_name))?;
+        let Some(resolve) = &metadata.resolve else {
+            continue;
+        };
+        // Attribute every resolved version to the packages requiring it, so the suggestions
+        // point at the requirements to upgrade
+        for node in &resolve.nodes {
+            let requester = &metadata[&node.id];
+            let requester = match metadata.workspace_members.contains(&node.id) {
+                true => format!("{}/{}", workspace_name, requester.name),
+                false => format!("{} {}", requester.name, requester.version),
+            };
+            for id in &node.dependencies {
+                let package = &metadata[id];
+                if options.only.is_empty() || options.only.contains(&package.name) {
+                    usages.push((
+                        package.name.clone(),
+                        package.version.clone(),
+                        requester.clone(),
+                    ));
+                }
+            }
+        }
+    }
+    Ok(DependencyReportResult {
+        duplicates: collect_duplicates(usages),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(name: &str, version: &str, requester: &str) -> (String, Version, String) {
+        (
+            name.to_string(),
+            Version::parse(version).expect("Invalid version"),
+            requester.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_collect_duplicates() {
+        let duplicates = collect_duplicates(vec![
+            usage("syn", "1.0.109", "ws_a/a"),
+            usage("syn", "2.0.48", "ws_a/b"),
+            usage("syn", "2.0.48", "ws_b/c"),
+            usage("serde", "1.0.190", "ws_a/a"),
+            usage("serde", "1.0.196", "ws_b/c"),
+            usage("log", "0.4.20", "ws_a/a"),
+            usage("log", "0.4.20", "ws_b/c"),
+            usage("nom", "0.10.0", "ws_a/a"),
+            usage("nom", "0.9.2", "toml 0.5.11"),
+        ]);
+        assert_eq!(duplicates.len(), 3);
+        assert_eq!(duplicates[0].name, "nom");
+        assert_eq!(duplicates[0].versions[0].version, "0.9.2");
+        assert_eq!(
+            duplicates[0].suggestion,
+            "unify on 0.10.0 by upgrading the requirements of toml 0.5.11"
+        );
+        let duplicates = &duplicates[1..];
+        assert_eq!(duplicates[0].name, "serde");
+        assert_eq!(
+            duplicates[0].suggestion,
+            "run `cargo update -p serde` to unify on 1.0.196"
+        );
+        assert_eq!(duplicates[1].name, "syn");
+        assert_eq!(
+            duplicates[1].versions[1],
+            DuplicateVersion {
+                version: "2.0.48".to_string(),
+                pulled_by: vec!["ws_a/b".to_string(), "ws_b/c".to_string()],
+            }
+        );
+        assert_eq!(
+            duplicates[1].suggestion,
+            "unify on 2.0.48 by upgrading the requirements of ws_a/a"
+        );
+    }
+}
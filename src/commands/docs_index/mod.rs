@@ -0,0 +1,99 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
+
+use clap::Parser;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::commands::check_workspace::{check_workspace_or_load, Options as CheckWorkspaceOptions};
+
+#[derive(Debug, Parser)]
+#[command(
+    about = "Generate an index page of the published documentation of the workspace members."
+)]
+pub struct Options {
+    /// Where to write the index page
+    #[arg(long, default_value = "index.html")]
+    output: PathBuf,
+    /// Url prefix under which the documentation is uploaded, e.g. `https://docs.example.com/docs/`
+    #[arg(long, default_value = "")]
+    base_url: String,
+    /// `check-workspace --json` output to reuse instead of checking the workspace again, `-` for stdin
+    #[arg(long)]
+    workspace_json: Option<PathBuf>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DocsIndexResult {
+    output: PathBuf,
+    packages: Vec<String>,
+}
+
+impl Display for DocsIndexResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Wrote documentation index of {} packages to {}",
+            self.packages.len(),
+            self.output.to_string_lossy()
+        )
+    }
+}
+
+fn render_index(base_url: &str, packages: &[(String, String)]) -> String {
+    let items: Vec<String> = packages
+        .iter()
+        .map(|(name, version)| {
+            format!(
+                "      <li><a href=\"{base_url}{name}/{version}/{lib}/index.html\">{name}</a> {version}</li>",
+                lib = name.replace('-', "_")
+            )
+        })
+        .collect();
+    format!(
+        "<!DOCTYPE html>\n<html>\n  <head>\n    <meta charset=\"utf-8\">\n    <title>Documentation</title>\n  </head>\n  <body>\n    <ul>\n{}\n    </ul>\n  </body>\n</html>\n",
+        items.join("\n")
+    )
+}
+
+pub async fn docs_index(
+    options: Box<Options>,
+    working_directory: PathBuf,
+) -> anyhow::Result<DocsIndexResult> {
+    let members = check_workspace_or_load(
+        options.workspace_json.as_deref(),
+        CheckWorkspaceOptions::new(),
+        working_directory,
+    )
+    .await?;
+    let mut packages: Vec<(String, String)> = members
+        .0
+        .into_values()
+        // Members whose docs are already published are listed too
+        .filter(|m| m.configures_target("docs"))
+        .map(|m| (m.package, m.version))
+        .collect();
+    packages.sort();
+    fs::write(&options.output, render_index(&options.base_url, &packages))?;
+    Ok(DocsIndexResult {
+        output: options.output,
+        packages: packages.into_iter().map(|(name, _)| name).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_index() {
+        let index = render_index(
+            "https://docs.example.com/",
+            &[("hub-app".to_string(), "0.1.0".to_string())],
+        );
+        assert!(index.contains(
+            "<li><a href=\"https://docs.example.com/hub-app/0.1.0/hub_app/index.html\">hub-app</a> 0.1.0</li>"
+        ));
+    }
+}
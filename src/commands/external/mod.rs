@@ -0,0 +1,75 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::Context;
+
+const PLUGIN_PREFIX: &str = "fslabscli-";
+
+/// Look for `fslabscli-<name>` in the directories of `path`, like cargo does for its external subcommands
+fn find_plugin(name: &str, path: &str) -> Option<PathBuf> {
+    let file_name = format!("{}{}{}", PLUGIN_PREFIX, name, env::consts::EXE_SUFFIX);
+    env::split_paths(path)
+        .map(|dir| dir.join(&file_name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Run the `fslabscli-<name>` plugin matching an unknown subcommand and return its exit code.
+/// The global flags are forwarded through `FSLABSCLI_*` environment variables.
+pub fn run_external(
+    args: Vec<String>,
+    working_directory: &Path,
+    verbose: u8,
+    json: bool,
+) -> anyhow::Result<i32> {
+    let Some((name, plugin_args)) = args.split_first() else {
+        anyhow::bail!("Missing subcommand");
+    };
+    let plugin = find_plugin(name, &env::var("PATH").unwrap_or_default()).with_context(|| {
+        format!(
+            "Unknown subcommand `{}`, and no `{}{}` found on PATH",
+            name, PLUGIN_PREFIX, name
+        )
+    })?;
+    log::debug!("Running plugin {}", plugin.to_string_lossy());
+    let status = Command::new(&plugin)
+        .args(plugin_args)
+        .env("FSLABSCLI_WORKING_DIRECTORY", working_directory)
+        .env("FSLABSCLI_VERBOSE", verbose.to_string())
+        .env("FSLABSCLI_JSON", json.to_string())
+        .status()
+        .with_context(|| format!("Could not run {}", plugin.to_string_lossy()))?;
+    Ok(status.code().unwrap_or(exitcode::SOFTWARE))
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_find_plugin() {
+        let first = TempDir::new().expect("Could not create temp dir");
+        let second = TempDir::new().expect("Could not create temp dir");
+        let plugin_name = format!("fslabscli-deploy{}", env::consts::EXE_SUFFIX);
+        first
+            .child("fslabscli-other")
+            .touch()
+            .expect("Could not create plugin");
+        second
+            .child(&plugin_name)
+            .touch()
+            .expect("Could not create plugin");
+        let path = env::join_paths([first.path(), second.path()])
+            .expect("Could not join paths")
+            .to_string_lossy()
+            .to_string();
+        assert_eq!(
+            find_plugin("deploy", &path),
+            Some(second.path().join(plugin_name))
+        );
+        assert_eq!(find_plugin("missing", &path), None);
+    }
+}
@@ -0,0 +1,219 @@
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Parser;
+use indexmap::IndexMap;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::utils;
+
+#[derive(Debug, Parser)]
+#[command(about = "Generate the dependabot configuration of the workspaces of the repository.")]
+pub struct Options {
+    /// Where to write the configuration
+    #[arg(long, default_value = ".github/dependabot.yml")]
+    output: PathBuf,
+    /// How often dependabot checks for updates
+    #[arg(long, value_enum, default_value_t = Interval::Weekly)]
+    interval: Interval,
+    /// Maximum number of open pull requests per workspace
+    #[arg(long, default_value_t = 5)]
+    open_pull_requests_limit: u32,
+}
+
+#[derive(clap::ValueEnum, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+enum Interval {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+#[derive(Serialize, Debug)]
+struct DependabotSchedule {
+    interval: Interval,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct DependabotGroup {
+    patterns: Vec<String>,
+    update_types: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct DependabotUpdate {
+    package_ecosystem: String,
+    directory: String,
+    schedule: DependabotSchedule,
+    open_pull_requests_limit: u32,
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    groups: IndexMap<String, DependabotGroup>,
+}
+
+#[derive(Serialize, Debug)]
+struct DependabotConfig {
+    version: u32,
+    updates: Vec<DependabotUpdate>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct GenerateDependabotResult {
+    output: PathBuf,
+    directories: Vec<String>,
+}
+
+impl Display for GenerateDependabotResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Wrote dependabot configuration of {} workspaces to {}",
+            self.directories.len(),
+            self.output.to_string_lossy()
+        )
+    }
+}
+
+/// Dependabot directory of a cargo root, relative to the repository root and starting with `/`
+fn dependabot_directory(root: &Path, working_directory: &Path) -> String {
+    let relative = root
+        .strip_prefix(working_directory)
+        .unwrap_or(root)
+        .to_string_lossy()
+        .replace('\\', "/");
+    format!("/{}", relative)
+}
+
+fn dependabot_config(
+    directories: &[String],
+    interval: &Interval,
+    open_pull_requests_limit: u32,
+    github_actions: bool,
+) -> DependabotConfig {
+    let mut updates: Vec<DependabotUpdate> = directories
+        .iter()
+        .map(|directory| {
+            // Semver compatible bumps of a workspace land in a single pull request,
+            // major bumps keep their own to be reviewed separately
+            let group_name = match directory.trim_matches('/') {
+                "" => "cargo".to_string(),
+                d => d.replace('/', "-"),
+            };
+            DependabotUpdate {
+                package_ecosystem: "cargo".to_string(),
+                directory: directory.clone(),
+                schedule: DependabotSchedule {
+                    interval: interval.clone(),
+                },
+                open_pull_requests_limit,
+                groups: IndexMap::from([(
+                    format!("{}-minor-and-patch", group_name),
+                    DependabotGroup {
+                        patterns: vec!["*".to_string()],
+                        update_types: vec!["minor".to_string(), "patch".to_string()],
+                    },
+                )]),
+            }
+        })
+        .collect();
+    if github_actions {
+        updates.push(DependabotUpdate {
+            package_ecosystem: "github-actions".to_string(),
+            directory: "/".to_string(),
+            schedule: DependabotSchedule {
+                interval: interval.clone(),
+            },
+            open_pull_requests_limit,
+            groups: IndexMap::new(),
+        });
+    }
+    DependabotConfig {
+        version: 2,
+        updates,
+    }
+}
+
+pub async fn generate_dependabot(
+    options: Box<Options>,
+    working_directory: PathBuf,
+) -> anyhow::Result<GenerateDependabotResult> {
+    let roots = utils::get_cargo_roots(working_directory.clone())
+        .with_context(|| format!("Failed to get roots from {:?}", working_directory))?;
+    let directories: Vec<String> = roots
+        .iter()
+        .map(|r| dependabot_directory(r, &working_directory))
+        .collect();
+    let config = dependabot_config(
+        &directories,
+        &options.interval,
+        options.open_pull_requests_limit,
+        working_directory.join(".github").join("workflows").exists(),
+    );
+    let output = working_directory.join(&options.output);
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = BufWriter::new(File::create(&output)?);
+    serde_yaml::to_writer(&mut writer, &config)?;
+    Ok(GenerateDependabotResult {
+        output,
+        directories,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn test_dependabot_config() {
+        let directories = vec![
+            dependabot_directory(Path::new("/repo"), Path::new("/repo")),
+            dependabot_directory(Path::new("/repo/apps/server"), Path::new("/repo")),
+        ];
+        let config = dependabot_config(&directories, &Interval::Weekly, 5, true);
+        assert_eq!(
+            serde_yaml::to_string(&config).expect("Could not serialize config"),
+            indoc! {r#"
+                version: 2
+                updates:
+                - package-ecosystem: cargo
+                  directory: /
+                  schedule:
+                    interval: weekly
+                  open-pull-requests-limit: 5
+                  groups:
+                    cargo-minor-and-patch:
+                      patterns:
+                      - '*'
+                      update-types:
+                      - minor
+                      - patch
+                - package-ecosystem: cargo
+                  directory: /apps/server
+                  schedule:
+                    interval: weekly
+                  open-pull-requests-limit: 5
+                  groups:
+                    apps-server-minor-and-patch:
+                      patterns:
+                      - '*'
+                      update-types:
+                      - minor
+                      - patch
+                - package-ecosystem: github-actions
+                  directory: /
+                  schedule:
+                    interval: weekly
+                  open-pull-requests-limit: 5
+            "#}
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use indexmap::IndexMap;
+
+use crate::commands::check_workspace::Result as Member;
+
+/// Render the `{{ namespace.key }}` placeholders of a `test.env` / `publish.env` value.
+///
+/// `package.{name,version,path,workspace}` are replaced by the member values while
+/// `secrets.*`, `vars.*` and `env.*` become the matching GitHub expression.
+/// Existing `${{ ... }}` expressions and placeholders of other namespaces are left untouched,
+/// only unknown `package.*` keys are rejected.
+fn render_value(value: &str, member: &Member) -> anyhow::Result<String> {
+    let mut rendered = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        let end = start + len + 2;
+        if rest[..start].ends_with('$') {
+            rendered.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        rendered.push_str(&rest[..start]);
+        let placeholder = rest[start + 2..end - 2].trim();
+        let replacement = match placeholder.split_once('.') {
+            Some(("package", "name")) => member.package.clone(),
+            Some(("package", "version")) => member.version.clone(),
+            Some(("package", "path")) => member.path.to_string_lossy().to_string(),
+            Some(("package", "workspace")) => member.workspace.clone(),
+            Some(("secrets" | "vars" | "env", key)) if !key.is_empty() => {
+                format!("${{{{ {} }}}}", placeholder)
+            }
+            Some(("package", _)) => anyhow::bail!(
+                "Unknown placeholder `{}` in env of {}",
+                placeholder,
+                member.package
+            ),
+            _ => rest[start..end].to_string(),
+        };
+        rendered.push_str(&replacement);
+        rest = &rest[end..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+pub fn render_env(
+    env: Option<IndexMap<String, String>>,
+    member: &Member,
+) -> anyhow::Result<Option<IndexMap<String, String>>> {
+    env.map(|env| {
+        env.into_iter()
+            .map(|(k, v)| Ok((k, render_value(&v, member)?)))
+            .collect()
+    })
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_value() {
+        let member = Member {
+            workspace: "hub".to_string(),
+            package: "hub_app".to_string(),
+            version: "1.2.3".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            render_value(
+                "{{ package.name }}:{{package.version}} {{ secrets.DB_PASSWORD }} ${{ github.sha }}",
+                &member
+            )
+            .expect("Could not render value"),
+            "hub_app:1.2.3 ${{ secrets.DB_PASSWORD }} ${{ github.sha }}"
+        );
+        assert_eq!(
+            render_value("{{ ports.postgres }}", &member).expect("Could not render value"),
+            "{{ ports.postgres }}"
+        );
+        assert!(render_value("{{ package.edition }}", &member).is_err());
+    }
+}
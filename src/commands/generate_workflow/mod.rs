@@ -9,6 +9,7 @@ use std::str::FromStr;
 use anyhow::Context;
 use clap::Parser;
 use indexmap::IndexMap;
+use schemars::JsonSchema;
 use serde::ser::SerializeMap;
 use serde::{Deserialize, Serialize, Serializer};
 use serde_with::{formats::PreferOne, serde_as, OneOrMany};
@@ -18,10 +19,14 @@ use void::Void;
 use itertools::Itertools;
 use publish_workflow::PublishWorkflowArgs;
 
-use crate::commands::check_workspace::{check_workspace, Options as CheckWorkspaceOptions};
+use crate::commands::check_workspace::{
+    check_workspace_or_load, Options as CheckWorkspaceOptions, Result as Member,
+};
+use crate::commands::generate_workflow::env_template::render_env;
 use crate::commands::generate_workflow::test_workflow::TestWorkflowArgs;
 use crate::utils::{deserialize_opt_string_or_map, deserialize_opt_string_or_struct, FromMap};
 
+mod env_template;
 mod publish_workflow;
 mod test_workflow;
 
@@ -39,13 +44,23 @@ else
   CHECK_CHANGED=('--check-changed' '--changed-base-ref' "origin/${BASE_REF}" '--changed-head-ref' "${HEAD_REF}")
   git fetch origin ${BASE_REF} --depth 1
 fi
-echo workspace=$(fslabscli check-workspace --json --check-publish "${CHECK_CHANGED[@]}" --binary-store-storage-account ${{ secrets.BINARY_STORE_STORAGE_ACCOUNT }} --binary-store-container-name ${{ secrets.BINARY_STORE_CONTAINER_NAME }} --binary-store-access-key ${{ secrets.BINARY_STORE_ACCESS_KEY }} --cargo-default-publish --cargo-registry foresight-mining-software-corporation --cargo-registry-url https://shipyard.rs/api/v1/shipyard/krates/by-name/ --cargo-registry-user-agent "shipyard ${{ secrets.CARGO_PRIVATE_REGISTRY_TOKEN }}") >> $GITHUB_OUTPUT"#;
+TARGETS=()
+if [ -n "${ONLY_TARGETS}" ]; then
+  TARGETS+=('--only-targets' "${ONLY_TARGETS}")
+fi
+if [ -n "${SKIP_TARGETS}" ]; then
+  TARGETS+=('--skip-targets' "${SKIP_TARGETS}")
+fi
+echo workspace=$(fslabscli check-workspace --json --check-publish "${CHECK_CHANGED[@]}" "${TARGETS[@]}" --binary-store-storage-account ${{ secrets.BINARY_STORE_STORAGE_ACCOUNT }} --binary-store-container-name ${{ secrets.BINARY_STORE_CONTAINER_NAME }} --binary-store-access-key ${{ secrets.BINARY_STORE_ACCESS_KEY }} --cargo-default-publish --cargo-registry foresight-mining-software-corporation --cargo-registry-url https://shipyard.rs/api/v1/shipyard/krates/by-name/ --cargo-registry-user-agent "shipyard ${{ secrets.CARGO_PRIVATE_REGISTRY_TOKEN }}") >> $GITHUB_OUTPUT"#;
 
 #[derive(Debug, Parser)]
 #[command(about = "Check directory for crates that need to be published.")]
 pub struct Options {
     #[arg(long)]
     output: PathBuf,
+    /// `check-workspace --json` output to reuse instead of checking the workspace again, `-` for stdin
+    #[arg(long)]
+    workspace_json: Option<PathBuf>,
     #[arg(long)]
     output_release: Option<PathBuf>,
     #[arg(long)]
@@ -62,9 +77,22 @@ pub struct Options {
     nomad_runner_label: String,
     #[arg(long, default_value_t = false)]
     test_publish_required_disabled: bool,
+    /// Runner label for the tests of members with `test.requires = ["gpu"]`
+    #[arg(long, default_value = "gpu-scale-set")]
+    gpu_runner_label: String,
+    /// Only run the tests of the cargo targets affected by the changes, through the `test_filter`
+    /// input that the test workflow must declare
+    #[arg(long, default_value_t = false)]
+    test_impact_analysis: bool,
+    /// Pass `test.args.run_in_container` to the test workflow, which must declare that input
+    #[arg(long, default_value_t = false)]
+    test_run_in_container: bool,
+    /// Pass `test.sanitizers` to the test workflow, which must declare the `sanitizers` input
+    #[arg(long, default_value_t = false)]
+    test_sanitizers: bool,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct GenerateResult {}
 
 impl Display for GenerateResult {
@@ -346,6 +374,236 @@ impl Serialize for StringBool {
     }
 }
 
+/// Inputs of the reusable publish workflow for `member`, before the ones set in its metadata
+fn publish_with(member: &Member, member_key: &str, check_job_key: &str) -> PublishWorkflowArgs {
+    let job_working_directory = member.path.to_string_lossy().to_string();
+    let publish_private_registry = Some(
+        match member.publish_detail.cargo.publish
+            && !(member.publish_detail.cargo.allow_public
+                && (member
+                    .publish_detail
+                    .cargo
+                    .registry
+                    .clone()
+                    .unwrap_or(vec!["public".to_string()])
+                    == vec!["public"]))
+        {
+            true => format!(
+                "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.cargo.publish }}}}",
+                &check_job_key, member_key
+            ),
+            false => "false".to_string(),
+        },
+    );
+    let publish_public_registry = Some(
+        match member.publish_detail.cargo.publish
+            && (member.publish_detail.cargo.allow_public
+                && (member
+                    .publish_detail
+                    .cargo
+                    .registry
+                    .clone()
+                    .unwrap_or(vec!["public".to_string()])
+                    == vec!["public"]))
+        {
+            true => format!(
+                "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.cargo.publish }}}}",
+                &check_job_key, member_key
+            ),
+            false => "false".to_string(),
+        },
+    );
+    let publish_docker = Some(match member.publish_detail.docker.publish {
+        true => format!(
+            "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.docker.publish }}}}",
+            &check_job_key, member_key
+        ),
+        false => "false".to_string(),
+    });
+    let publish_npm_napi = Some(match member.publish_detail.npm_napi.publish {
+        true => format!(
+            "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.npm_napi.publish }}}}",
+            &check_job_key, member_key
+        ),
+        false => "false".to_string(),
+    });
+    let publish_binary = Some(match member.publish_detail.binary.publish {
+        true => format!(
+            "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.binary.publish }}}}",
+            &check_job_key, member_key
+        ),
+        false => "false".to_string(),
+    });
+    // Inputs of the newer publish targets are only sent to the members using them, as
+    // reusable workflows declaring no such input reject the call
+    let publish_docs = match member.publish_detail.docs.publish {
+        true => Some(format!(
+            "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.docs.publish }}}}",
+            &check_job_key, member_key
+        )),
+        false => None,
+    };
+    let publish_book = match member.publish_detail.book.publish {
+        true => Some(format!(
+            "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.book.publish }}}}",
+            &check_job_key, member_key
+        )),
+        false => None,
+    };
+    let publish_schemas = match member.publish_detail.schemas.publish {
+        true => Some(format!(
+            "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.schemas.publish }}}}",
+            &check_job_key, member_key
+        )),
+        false => None,
+    };
+    let publish_terraform = match member.publish_detail.terraform.publish {
+        true => Some(format!(
+            "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.terraform.publish }}}}",
+            &check_job_key, member_key
+        )),
+        false => None,
+    };
+    let publish_python = match member.publish_detail.python.publish {
+        true => Some(format!(
+            "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.python.publish }}}}",
+            &check_job_key, member_key
+        )),
+        false => None,
+    };
+    PublishWorkflowArgs {
+        working_directory: Some(job_working_directory.clone()),
+        publish: Some(StringBool(member.publish)),
+        publish_private_registry,
+        publish_public_registry,
+        publish_docker,
+        publish_npm_napi,
+        publish_binary,
+        publish_docs,
+        publish_book,
+        book_path: match member.publish_detail.book.publish {
+            true => Some(member.publish_detail.book.path.clone()),
+            false => None,
+        },
+        publish_schemas,
+        schemas_path: match member.publish_detail.schemas.publish {
+            true => Some(member.publish_detail.schemas.path.clone()),
+            false => None,
+        },
+        schemas_previous_version: match member.publish_detail.schemas.publish {
+            true => Some(format!(
+                "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.schemas.previous_version }}}}",
+                &check_job_key, member_key
+            )),
+            false => None,
+        },
+        publish_terraform,
+        terraform_path: match member.publish_detail.terraform.publish {
+            true => Some(member.publish_detail.terraform.path.clone()),
+            false => None,
+        },
+        publish_python,
+        publish_python_public: match member.publish_detail.python.publish {
+            true => Some(StringBool(member.publish_detail.python.allow_public)),
+            false => None,
+        },
+        python_platforms: match member.publish_detail.python.publish {
+            true => Some(member.publish_detail.python.platforms.clone()),
+            false => None,
+        },
+        docker_image: match member.publish_detail.docker.publish {
+            true => Some(member.package.clone()),
+            false => None,
+        },
+        docker_registry: match member.publish_detail.docker.publish {
+            true => member.publish_detail.docker.repository.clone(),
+            false => None,
+        },
+        docker_input_digest: match member.publish_detail.docker.publish {
+            true => Some(format!(
+                "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.docker.input_digest }}}}",
+                &check_job_key, member_key
+            )),
+            false => None,
+        },
+        docker_retag_from: match member.publish_detail.docker.publish {
+            true => Some(format!(
+                "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.docker.retag_from }}}}",
+                &check_job_key, member_key
+            )),
+            false => None,
+        },
+        binary_sign_build: match member.publish_detail.binary.publish {
+            true => Some(StringBool(member.publish_detail.binary.sign)),
+            false => None,
+        },
+        binary_application_name: match member.publish_detail.binary.publish {
+            true => Some(member.publish_detail.binary.name.clone()),
+            false => None,
+        },
+        binary_targets: match member.publish_detail.binary.publish {
+            true => Some(member.publish_detail.binary.targets.clone()),
+            false => None,
+        },
+        skip_miri_test: member.test_detail.miri.map(|miri| StringBool(!miri)),
+        ..Default::default()
+    }
+}
+
+/// Inputs of the reusable test workflow for `member`, completed by the ones set in its metadata
+#[allow(clippy::too_many_arguments)]
+fn test_with(
+    member: &Member,
+    member_key: &str,
+    check_job_key: &str,
+    test_publish_required_disabled: bool,
+    gpu_runner_label: &str,
+    test_impact_analysis: bool,
+    run_in_container: bool,
+    sanitizers: bool,
+) -> TestWorkflowArgs {
+    let mut args: TestWorkflowArgs = match member.test_detail.args.clone() {
+        Some(a) => a.into(),
+        None => Default::default(),
+    };
+    if !run_in_container && args.run_in_container.take().is_some() {
+        log::warn!(
+            "Ignoring test.args.run_in_container of {}, pass --test-run-in-container once the test workflow declares it",
+            member.package
+        );
+    }
+    if !sanitizers && !member.test_detail.sanitizers.is_empty() {
+        log::warn!(
+            "Ignoring test.sanitizers of {}, pass --test-sanitizers once the test workflow declares it",
+            member.package
+        );
+    }
+    TestWorkflowArgs {
+        working_directory: Some(member.path.to_string_lossy().to_string()),
+        test_publish_required: Some(StringBool(
+            member.publish_detail.cargo.publish && !test_publish_required_disabled,
+        )),
+        runner_label: match member.test_detail.requires.iter().any(|r| r == "gpu") {
+            true => Some(gpu_runner_label.to_string()),
+            false => None,
+        },
+        skip_miri_test: member.test_detail.miri.map(|miri| StringBool(!miri)),
+        test_filter: match test_impact_analysis {
+            true => Some(format!(
+                "${{{{ fromJson(needs.{}.outputs.workspace).{}.test_filter }}}}",
+                check_job_key, member_key
+            )),
+            false => None,
+        },
+        sanitizers: match sanitizers && !member.test_detail.sanitizers.is_empty() {
+            true => Some(member.test_detail.sanitizers.join(",")),
+            false => None,
+        },
+        ..Default::default()
+    }
+    .merge(args)
+}
+
 pub async fn generate_workflow(
     options: Box<Options>,
     working_directory: PathBuf,
@@ -406,15 +664,35 @@ pub async fn generate_workflow(
             branches: None,
             tags: None,
             paths: None,
-            inputs: Some(IndexMap::from([(
-                "publish".to_string(),
-                GithubWorkflowInput {
-                    description: "Trigger with publish".to_string(),
-                    default: None,
-                    required: false,
-                    input_type: "boolean".to_string(),
-                },
-            )])),
+            inputs: Some(IndexMap::from([
+                (
+                    "publish".to_string(),
+                    GithubWorkflowInput {
+                        description: "Trigger with publish".to_string(),
+                        default: None,
+                        required: false,
+                        input_type: "boolean".to_string(),
+                    },
+                ),
+                (
+                    "only_targets".to_string(),
+                    GithubWorkflowInput {
+                        description: "Only publish these targets, e.g. `docker,cargo`".to_string(),
+                        default: None,
+                        required: false,
+                        input_type: "string".to_string(),
+                    },
+                ),
+                (
+                    "skip_targets".to_string(),
+                    GithubWorkflowInput {
+                        description: "Never publish these targets".to_string(),
+                        default: None,
+                        required: false,
+                        input_type: "string".to_string(),
+                    },
+                ),
+            ])),
             secrets: None,
         },
     );
@@ -434,10 +712,9 @@ pub async fn generate_workflow(
     // If we need to test for changed and publish
     let check_job_key = "check_changed_and_publish".to_string();
     // Get Directory information
-    let members = check_workspace(
-        Box::new(
-            CheckWorkspaceOptions::new().with_cargo_default_publish(options.cargo_default_publish),
-        ),
+    let members = check_workspace_or_load(
+        options.workspace_json.as_deref(),
+        CheckWorkspaceOptions::new().with_cargo_default_publish(options.cargo_default_publish),
         working_directory,
     )
     .await
@@ -544,6 +821,14 @@ echo "//npm.pkg.github.com/:_authToken=${{{{ secrets.NPM_{github_secret_key}_TOK
                 env: Some(IndexMap::from([
                     ("BASE_REF".to_string(), "${{ github.base_ref }}".to_string()),
                     ("HEAD_REF".to_string(), "${{ github.head_ref }}".to_string()),
+                    (
+                        "ONLY_TARGETS".to_string(),
+                        "${{ inputs.only_targets }}".to_string(),
+                    ),
+                    (
+                        "SKIP_TARGETS".to_string(),
+                        "${{ inputs.skip_targets }}".to_string(),
+                    ),
                 ])),
                 run: Some(CHECK_SCRIPT.to_string()),
                 ..Default::default()
@@ -569,6 +854,10 @@ echo "//npm.pkg.github.com/:_authToken=${{{{ secrets.NPM_{github_secret_key}_TOK
             .jobs
             .insert(check_job_key.clone(), check_job);
     }
+    // Jobs needing each other in a cycle would make an invalid workflow
+    if let Some(cycle) = members.publish_order().into_iter().find(|c| c.len() > 1) {
+        anyhow::bail!("Dependency cycle between {}", cycle.join(", "));
+    }
     let mut member_keys: Vec<String> = members.0.keys().cloned().collect();
     member_keys.sort();
     let base_if = "!cancelled() && !contains(needs.*.result, 'failure') && !contains(needs.*.result, 'cancelled')".to_string();
@@ -600,13 +889,20 @@ echo "//npm.pkg.github.com/:_authToken=${{{{ secrets.NPM_{github_secret_key}_TOK
         if !member.test_detail.skip.unwrap_or(false) && !split_workflows {
             publish_needs.push(test_job_key.clone());
         }
-        let mut publish_if = format!("{} && (github.event_name == 'push' || (github.event_name == 'workflow_dispatch' && inputs.publish))", base_if);
+        let publish_event_if = "github.event_name == 'push' || (github.event_name == 'workflow_dispatch' && inputs.publish)";
+        let mut publish_if = format!("{} && ({})", base_if, publish_event_if);
+        // Custom targets also run their build command on pull requests, as a dry run
+        let mut custom_if = base_if.clone();
         let mut test_if = base_if.clone();
         if !options.no_check_changed_and_publish {
             publish_if = format!(
                 "{} && (fromJSON(needs.{}.outputs.workspace).{}.publish)",
                 publish_if, &check_job_key, member_key
             );
+            custom_if = format!(
+                "{} && (fromJSON(needs.{}.outputs.workspace).{}.publish)",
+                custom_if, &check_job_key, member_key
+            );
             test_if = format!(
                 "{} && (fromJSON(needs.{}.outputs.workspace).{}.changed)",
                 test_if, &check_job_key, member_key,
@@ -616,68 +912,7 @@ echo "//npm.pkg.github.com/:_authToken=${{{{ secrets.NPM_{github_secret_key}_TOK
             Some(a) => a.into(),
             None => Default::default(),
         };
-        let cargo_test_options: TestWorkflowArgs = match member.test_detail.args.clone() {
-            Some(a) => a.into(),
-            None => Default::default(),
-        };
         let job_working_directory = member.path.to_string_lossy().to_string();
-        let publish_private_registry = Some(
-            match member.publish_detail.cargo.publish
-                && !(member.publish_detail.cargo.allow_public
-                    && (member
-                        .publish_detail
-                        .cargo
-                        .registry
-                        .clone()
-                        .unwrap_or(vec!["public".to_string()])
-                        == vec!["public"]))
-            {
-                true => format!(
-                    "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.cargo.publish }}}}",
-                    &check_job_key, member_key
-                ),
-                false => "false".to_string(),
-            },
-        );
-        let publish_public_registry = Some(
-            match member.publish_detail.cargo.publish
-                && (member.publish_detail.cargo.allow_public
-                    && (member
-                        .publish_detail
-                        .cargo
-                        .registry
-                        .clone()
-                        .unwrap_or(vec!["public".to_string()])
-                        == vec!["public"]))
-            {
-                true => format!(
-                    "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.cargo.publish }}}}",
-                    &check_job_key, member_key
-                ),
-                false => "false".to_string(),
-            },
-        );
-        let publish_docker = Some(match member.publish_detail.docker.publish {
-            true => format!(
-                "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.docker.publish }}}}",
-                &check_job_key, member_key
-            ),
-            false => "false".to_string(),
-        });
-        let publish_npm_napi = Some(match member.publish_detail.npm_napi.publish {
-            true => format!(
-                "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.npm_napi.publish }}}}",
-                &check_job_key, member_key
-            ),
-            false => "false".to_string(),
-        });
-        let publish_binary = Some(match member.publish_detail.binary.publish {
-            true => format!(
-                "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.binary.publish }}}}",
-                &check_job_key, member_key
-            ),
-            false => "false".to_string(),
-        });
         let publish_installer = Some(match member.publish_detail.binary.installer.publish {
             true => format!(
                 "${{{{ fromJson(needs.{}.outputs.workspace).{}.publish_detail.binary.publish }}}}",
@@ -685,62 +920,101 @@ echo "//npm.pkg.github.com/:_authToken=${{{{ secrets.NPM_{github_secret_key}_TOK
             ),
             false => "false".to_string(),
         });
-        let publish_with: PublishWorkflowArgs = PublishWorkflowArgs {
-            working_directory: Some(job_working_directory.clone()),
-            publish: Some(StringBool(member.publish)),
-            publish_private_registry,
-            publish_public_registry,
-            publish_docker,
-            publish_npm_napi,
-            publish_binary,
-            docker_image: match member.publish_detail.docker.publish {
-                true => Some(member.package.clone()),
-                false => None,
-            },
-            docker_registry: match member.publish_detail.docker.publish {
-                true => member.publish_detail.docker.repository.clone(),
-                false => None,
-            },
-            binary_sign_build: match member.publish_detail.binary.publish {
-                true => Some(StringBool(member.publish_detail.binary.sign)),
-                false => None,
-            },
-            binary_application_name: match member.publish_detail.binary.publish {
-                true => Some(member.publish_detail.binary.name.clone()),
-                false => None,
+        let publish_with =
+            publish_with(member, &member_key, &check_job_key).merge(cargo_publish_options.clone());
+        let test_with = test_with(
+            member,
+            &member_key,
+            &check_job_key,
+            options.test_publish_required_disabled,
+            &options.gpu_runner_label,
+            options.test_impact_analysis,
+            options.test_run_in_container,
+            options.test_sanitizers,
+        );
+
+        let test_job = match &member.test_detail.command {
+            // Members without a Cargo.toml bring their own test command
+            Some(command) => GithubWorkflowJob {
+                name: Some(format!("Test {}: {}", member.workspace, member.package)),
+                runs_on: Some(vec!["ci-scale-set".to_string()]),
+                needs: Some(test_needs),
+                job_if: Some(format!("${{{{ {} }}}}", test_if)),
+                env: render_env(member.test_detail.env.clone(), member)?,
+                steps: Some(vec![
+                    GithubWorkflowJobSteps {
+                        name: Some("Checkout repo".to_string()),
+                        uses: Some("actions/checkout@v4".to_string()),
+                        ..Default::default()
+                    },
+                    GithubWorkflowJobSteps {
+                        name: Some(format!("Test {}", member.package)),
+                        shell: Some("bash".to_string()),
+                        working_directory: Some(member.path.to_string_lossy().to_string()),
+                        run: Some(command.clone()),
+                        ..Default::default()
+                    },
+                ]),
+                ..Default::default()
             },
-            binary_targets: match member.publish_detail.binary.publish {
-                true => Some(member.publish_detail.binary.targets.clone()),
-                false => None,
+            None => GithubWorkflowJob {
+                name: Some(format!("Test {}: {}", member.workspace, member.package)),
+                uses: Some(format!(
+                    "ForesightMiningSoftwareCorporation/github/.github/workflows/rust-test.yml@{}",
+                    options.build_workflow_version
+                )),
+                needs: Some(test_needs),
+                job_if: Some(format!("${{{{ {} }}}}", test_if)),
+                with: Some(test_with.into()),
+                secrets: Some(GithubWorkflowJobSecret {
+                    inherit: true,
+                    secrets: None,
+                }),
+                env: render_env(member.test_detail.env.clone(), member)?,
+                ..Default::default()
             },
-            ..Default::default()
-        }
-        .merge(cargo_publish_options.clone());
-        let test_with: TestWorkflowArgs = TestWorkflowArgs {
-            working_directory: Some(job_working_directory.clone()),
-            test_publish_required: Some(StringBool(
-                member.publish_detail.cargo.publish && !options.test_publish_required_disabled,
-            )),
-            ..Default::default()
-        }
-        .merge(cargo_test_options.clone());
-
-        let test_job = GithubWorkflowJob {
-            name: Some(format!("Test {}: {}", member.workspace, member.package)),
-            uses: Some(format!(
-                "ForesightMiningSoftwareCorporation/github/.github/workflows/rust-test.yml@{}",
-                options.build_workflow_version
-            )),
-            needs: Some(test_needs),
-            job_if: Some(format!("${{{{ {} }}}}", test_if)),
-            with: Some(test_with.into()),
-            secrets: Some(GithubWorkflowJobSecret {
-                inherit: true,
-                secrets: None,
-            }),
-            env: member.test_detail.env.clone(),
-            ..Default::default()
         };
+        let mut custom_jobs: Vec<(String, GithubWorkflowJob)> = vec![];
+        for custom in member.publish_detail.custom.iter().filter(|c| c.publish) {
+            let working_directory = member.path.to_string_lossy().to_string();
+            let mut steps = vec![GithubWorkflowJobSteps {
+                name: Some("Checkout repo".to_string()),
+                uses: Some("actions/checkout@v4".to_string()),
+                ..Default::default()
+            }];
+            if let Some(build_command) = &custom.build_command {
+                steps.push(GithubWorkflowJobSteps {
+                    name: Some(format!("Build {}", custom.name)),
+                    shell: Some("bash".to_string()),
+                    working_directory: Some(working_directory.clone()),
+                    run: Some(build_command.clone()),
+                    ..Default::default()
+                });
+            }
+            steps.push(GithubWorkflowJobSteps {
+                name: Some(format!("Publish {}", custom.name)),
+                step_if: Some(format!("${{{{ {} }}}}", publish_event_if)),
+                shell: Some("bash".to_string()),
+                working_directory: Some(working_directory),
+                run: Some(custom.publish_script()),
+                ..Default::default()
+            });
+            custom_jobs.push((
+                format!("{}_{}", publish_job_key, custom.job_suffix()),
+                GithubWorkflowJob {
+                    name: Some(format!(
+                        "Publish {}: {} {}",
+                        member.workspace, member.package, custom.name
+                    )),
+                    runs_on: Some(vec!["ci-scale-set".to_string()]),
+                    needs: Some(publish_needs.clone()),
+                    job_if: Some(format!("${{{{ {} }}}}", custom_if)),
+                    env: render_env(custom.env.clone(), member)?,
+                    steps: Some(steps),
+                    ..Default::default()
+                },
+            ));
+        }
         let publish_job = GithubWorkflowJob {
             name: Some(format!("Publish {}: {}", member.workspace, member.package)),
             uses: Some(
@@ -753,7 +1027,7 @@ echo "//npm.pkg.github.com/:_authToken=${{{{ secrets.NPM_{github_secret_key}_TOK
             needs: Some(publish_needs),
             job_if: Some(format!("${{{{ {} }}}}", publish_if)),
             with: Some(publish_with.into()),
-            env: member.publish_detail.env.clone(),
+            env: render_env(member.publish_detail.env.clone(), member)?,
             secrets: Some(GithubWorkflowJobSecret {
                 inherit: true,
                 secrets: None,
@@ -771,6 +1045,16 @@ echo "//npm.pkg.github.com/:_authToken=${{{{ secrets.NPM_{github_secret_key}_TOK
                 false => &mut test_workflow,
             };
             wf.jobs.insert(publish_job_key.clone(), publish_job);
+            for (key, job) in custom_jobs {
+                if wf.jobs.contains_key(&key) {
+                    anyhow::bail!(
+                        "Custom publish target of {} would replace the existing job {}, rename it",
+                        member.package,
+                        key
+                    );
+                }
+                wf.jobs.insert(key, job);
+            }
             if member.publish_detail.binary.installer.publish {
                 let mut installer_needs = match options.no_depends_on_template_jobs {
                     false => initial_jobs.clone(),
@@ -843,3 +1127,130 @@ echo "//npm.pkg.github.com/:_authToken=${{{{ secrets.NPM_{github_secret_key}_TOK
     }
     Ok(GenerateResult {})
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn publish_inputs(member: &Member) -> IndexMap<String, Value> {
+        publish_with(member, "member", "check_changed_and_publish").into()
+    }
+
+    fn test_inputs(
+        member: &Member,
+        test_impact_analysis: bool,
+        run_in_container: bool,
+        sanitizers: bool,
+    ) -> IndexMap<String, Value> {
+        test_with(
+            member,
+            "member",
+            "check_changed_and_publish",
+            false,
+            "gpu-scale-set",
+            test_impact_analysis,
+            run_in_container,
+            sanitizers,
+        )
+        .into()
+    }
+
+    #[test]
+    fn test_publish_with_targets_inputs() {
+        let mut member = Member {
+            package: "member".to_string(),
+            path: PathBuf::from("member"),
+            ..Default::default()
+        };
+        let inputs = publish_inputs(&member);
+        assert!(!inputs.contains_key("publish_docs"));
+        assert!(!inputs.contains_key("publish_python"));
+        assert!(!inputs.contains_key("publish_python_public"));
+        assert!(!inputs.contains_key("python_platforms"));
+        assert!(!inputs.contains_key("publish_terraform"));
+        assert!(!inputs.contains_key("terraform_path"));
+        assert!(!inputs.contains_key("publish_schemas"));
+        assert!(!inputs.contains_key("schemas_path"));
+        assert!(!inputs.contains_key("schemas_previous_version"));
+        assert!(!inputs.contains_key("publish_book"));
+        assert!(!inputs.contains_key("book_path"));
+        member.publish_detail.docs.publish = true;
+        let inputs = publish_inputs(&member);
+        assert_eq!(
+            inputs.get("publish_docs"),
+            Some(&Value::from(
+                "${{ fromJson(needs.check_changed_and_publish.outputs.workspace).member.publish_detail.docs.publish }}"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_test_with_filter_input() {
+        let member = Member {
+            package: "member".to_string(),
+            path: PathBuf::from("member"),
+            ..Default::default()
+        };
+        assert!(!test_inputs(&member, false, false, false).contains_key("test_filter"));
+        assert_eq!(
+            test_inputs(&member, true, false, false).get("test_filter"),
+            Some(&Value::from(
+                "${{ fromJson(needs.check_changed_and_publish.outputs.workspace).member.test_filter }}"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_test_with_run_in_container_input() {
+        let mut member = Member {
+            package: "member".to_string(),
+            path: PathBuf::from("member"),
+            ..Default::default()
+        };
+        assert!(!test_inputs(&member, false, true, false).contains_key("run_in_container"));
+        member.test_detail.args = Some(IndexMap::from([(
+            "run_in_container".to_string(),
+            Value::from("ghcr.io/org/cuda:12"),
+        )]));
+        assert!(!test_inputs(&member, false, false, false).contains_key("run_in_container"));
+        assert_eq!(
+            test_inputs(&member, false, true, false).get("run_in_container"),
+            Some(&Value::from("ghcr.io/org/cuda:12"))
+        );
+    }
+
+    #[test]
+    fn test_test_with_sanitizers_input() {
+        let mut member = Member {
+            package: "member".to_string(),
+            path: PathBuf::from("member"),
+            ..Default::default()
+        };
+        assert!(!test_inputs(&member, false, false, true).contains_key("sanitizers"));
+        member.test_detail.sanitizers = vec!["address".to_string(), "thread".to_string()];
+        assert!(!test_inputs(&member, false, false, false).contains_key("sanitizers"));
+        assert_eq!(
+            test_inputs(&member, false, false, true).get("sanitizers"),
+            Some(&Value::from("address,thread"))
+        );
+    }
+
+    #[test]
+    fn test_test_with_gpu_runner_label() {
+        let mut member = Member {
+            package: "member".to_string(),
+            path: PathBuf::from("member"),
+            ..Default::default()
+        };
+        member.test_detail.args = Some(IndexMap::from([(
+            "runner_label".to_string(),
+            Value::from("custom"),
+        )]));
+        assert!(!test_inputs(&member, false, false, false).contains_key("runner_label"));
+        member.test_detail.requires = vec!["gpu".to_string()];
+        assert_eq!(
+            test_inputs(&member, false, false, false).get("runner_label"),
+            Some(&Value::from("gpu-scale-set"))
+        );
+    }
+}
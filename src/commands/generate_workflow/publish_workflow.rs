@@ -25,6 +25,28 @@ pub struct PublishWorkflowArgs {
     pub publish_npm_napi: Option<String>,
     /// Should an installer be built and published
     pub publish_installer: Option<String>,
+    /// Should the crate documentation be built and published
+    pub publish_docs: Option<String>,
+    /// Should the mdBook be built and published
+    pub publish_book: Option<String>,
+    /// Path of the mdBook, relative to the working directory
+    pub book_path: Option<String>,
+    /// Should the schemas bundle be checked and published
+    pub publish_schemas: Option<String>,
+    /// Path of the schemas, relative to the working directory
+    pub schemas_path: Option<String>,
+    /// Previously published schemas version to run the breaking changes check against
+    pub schemas_previous_version: Option<String>,
+    /// Should the terraform module be validated and published
+    pub publish_terraform: Option<String>,
+    /// Path of the terraform module, relative to the working directory
+    pub terraform_path: Option<String>,
+    /// Should the python wheels be built and published
+    pub publish_python: Option<String>,
+    /// Should the python wheels also be published to pypi.org
+    pub publish_python_public: Option<StringBool>,
+    /// Platforms to build the python wheels for
+    pub python_platforms: Option<Vec<String>>,
     /// Rust toolchain to install.
     /// Do not set this to moving targets like "stable".
     /// Instead, leave it empty and regularly bump the default in this file.
@@ -57,6 +79,10 @@ pub struct PublishWorkflowArgs {
     pub docker_image: Option<String>,
     /// Docker registry
     pub docker_registry: Option<String>,
+    /// Digest of the docker build inputs, used as an additional image tag
+    pub docker_input_digest: Option<String>,
+    /// Tag of an image built from identical inputs, retagged as the version instead of rebuilding
+    pub docker_retag_from: Option<String>,
     /// Force the publish test to be marked as non required
     pub force_nonrequired_publish_test: Option<StringBool>,
     /// Should the binary bin be signed
@@ -86,6 +112,19 @@ impl PublishWorkflowArgs {
             publish_binary: self.publish_binary.or(other.publish_binary),
             publish_npm_napi: self.publish_npm_napi.or(other.publish_npm_napi),
             publish_installer: self.publish_installer.or(other.publish_installer),
+            publish_docs: self.publish_docs.or(other.publish_docs),
+            publish_book: self.publish_book.or(other.publish_book),
+            book_path: self.book_path.or(other.book_path),
+            publish_schemas: self.publish_schemas.or(other.publish_schemas),
+            schemas_path: self.schemas_path.or(other.schemas_path),
+            schemas_previous_version: self
+                .schemas_previous_version
+                .or(other.schemas_previous_version),
+            publish_terraform: self.publish_terraform.or(other.publish_terraform),
+            terraform_path: self.terraform_path.or(other.terraform_path),
+            publish_python: self.publish_python.or(other.publish_python),
+            publish_python_public: self.publish_python_public.or(other.publish_python_public),
+            python_platforms: self.python_platforms.or(other.python_platforms),
             toolchain: self.toolchain.or(other.toolchain),
             miri_toolchain: self.miri_toolchain.or(other.miri_toolchain),
             release_channel: self.release_channel.or(other.release_channel),
@@ -100,6 +139,8 @@ impl PublishWorkflowArgs {
             dockerfile: self.dockerfile.or(other.dockerfile),
             docker_image: self.docker_image.or(other.docker_image),
             docker_registry: self.docker_registry.or(other.docker_registry),
+            docker_input_digest: self.docker_input_digest.or(other.docker_input_digest),
+            docker_retag_from: self.docker_retag_from.or(other.docker_retag_from),
             force_nonrequired_publish_test: self
                 .force_nonrequired_publish_test
                 .or(other.force_nonrequired_publish_test),
@@ -137,6 +178,22 @@ impl From<IndexMap<String, Value>> for PublishWorkflowArgs {
                 "publish_binary" => me.publish_binary = parse_string(v),
                 "publish_npm_napi" => me.publish_npm_napi = parse_string(v),
                 "publish_installer" => me.publish_installer = parse_string(v),
+                "publish_docs" => me.publish_docs = parse_string(v),
+                "publish_book" => me.publish_book = parse_string(v),
+                "book_path" => me.book_path = parse_string(v),
+                "publish_schemas" => me.publish_schemas = parse_string(v),
+                "schemas_path" => me.schemas_path = parse_string(v),
+                "schemas_previous_version" => me.schemas_previous_version = parse_string(v),
+                "publish_terraform" => me.publish_terraform = parse_string(v),
+                "terraform_path" => me.terraform_path = parse_string(v),
+                "publish_python" => me.publish_python = parse_string(v),
+                "publish_python_public" => me.publish_python_public = Some(v.into()),
+                "python_platforms" => {
+                    me.python_platforms = match v {
+                        Value::String(s) => serde_json::from_str(&s).ok(),
+                        _ => None,
+                    }
+                }
                 "toolchain" => me.toolchain = parse_string(v),
                 "miri_toolchain" => me.miri_toolchain = parse_string(v),
                 "release_channel" => me.release_channel = parse_string(v),
@@ -151,6 +208,8 @@ impl From<IndexMap<String, Value>> for PublishWorkflowArgs {
                 "dockerfile" => me.dockerfile = parse_string(v),
                 "docker_image" => me.docker_image = parse_string(v),
                 "docker_registry" => me.docker_registry = parse_string(v),
+                "docker_input_digest" => me.docker_input_digest = parse_string(v),
+                "docker_retag_from" => me.docker_retag_from = parse_string(v),
                 "force_nonrequired_publish_test" => {
                     me.force_nonrequired_publish_test = Some(v.into())
                 }
@@ -212,6 +271,48 @@ impl From<PublishWorkflowArgs> for IndexMap<String, Value> {
         if let Some(publish_installer) = val.publish_installer {
             map.insert("publish_installer".to_string(), publish_installer.into());
         }
+        if let Some(publish_docs) = val.publish_docs {
+            map.insert("publish_docs".to_string(), publish_docs.into());
+        }
+        if let Some(publish_book) = val.publish_book {
+            map.insert("publish_book".to_string(), publish_book.into());
+        }
+        if let Some(book_path) = val.book_path {
+            map.insert("book_path".to_string(), book_path.into());
+        }
+        if let Some(publish_schemas) = val.publish_schemas {
+            map.insert("publish_schemas".to_string(), publish_schemas.into());
+        }
+        if let Some(schemas_path) = val.schemas_path {
+            map.insert("schemas_path".to_string(), schemas_path.into());
+        }
+        if let Some(schemas_previous_version) = val.schemas_previous_version {
+            map.insert(
+                "schemas_previous_version".to_string(),
+                schemas_previous_version.into(),
+            );
+        }
+        if let Some(publish_terraform) = val.publish_terraform {
+            map.insert("publish_terraform".to_string(), publish_terraform.into());
+        }
+        if let Some(terraform_path) = val.terraform_path {
+            map.insert("terraform_path".to_string(), terraform_path.into());
+        }
+        if let Some(publish_python) = val.publish_python {
+            map.insert("publish_python".to_string(), publish_python.into());
+        }
+        if let Some(publish_python_public) = val.publish_python_public {
+            map.insert(
+                "publish_python_public".to_string(),
+                publish_python_public.into(),
+            );
+        }
+        if let Some(python_platforms) = val.python_platforms {
+            map.insert(
+                "python_platforms".to_string(),
+                format!("[\"{}\"]", python_platforms.join("\",\"")).into(),
+            );
+        }
         if let Some(toolchain) = val.toolchain {
             map.insert("toolchain".to_string(), toolchain.into());
         }
@@ -266,6 +367,15 @@ impl From<PublishWorkflowArgs> for IndexMap<String, Value> {
         if let Some(docker_registry) = val.docker_registry {
             map.insert("docker_registry".to_string(), docker_registry.into());
         }
+        if let Some(docker_input_digest) = val.docker_input_digest {
+            map.insert(
+                "docker_input_digest".to_string(),
+                docker_input_digest.into(),
+            );
+        }
+        if let Some(docker_retag_from) = val.docker_retag_from {
+            map.insert("docker_retag_from".to_string(), docker_retag_from.into());
+        }
         if let Some(force_nonrequired_publish_test) = val.force_nonrequired_publish_test {
             map.insert(
                 "force_nonrequired_publish_test".to_string(),
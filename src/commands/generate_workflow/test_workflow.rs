@@ -31,6 +31,14 @@ pub struct TestWorkflowArgs {
     pub test_publish_required: Option<StringBool>,
     /// Should a postgres service be started and feeded through env variable
     pub service_database: Option<StringBool>,
+    /// Docker image, or Dockerfile path, to run the whole test job in
+    pub run_in_container: Option<String>,
+    /// Label of the runners to run the tests on
+    pub runner_label: Option<String>,
+    /// Nextest filterset restricting the tests to the targets affected by the changes
+    pub test_filter: Option<String>,
+    /// Sanitizers to run the tests under in additional nightly runs, comma separated
+    pub sanitizers: Option<String>,
 }
 
 impl TestWorkflowArgs {
@@ -49,6 +57,10 @@ impl TestWorkflowArgs {
             skip_miri_test: self.skip_miri_test.or(other.skip_miri_test),
             test_publish_required: self.test_publish_required.or(other.test_publish_required),
             service_database: self.service_database.or(other.service_database),
+            run_in_container: self.run_in_container.or(other.run_in_container),
+            runner_label: self.runner_label.or(other.runner_label),
+            test_filter: self.test_filter.or(other.test_filter),
+            sanitizers: self.sanitizers.or(other.sanitizers),
         }
     }
 }
@@ -110,6 +122,18 @@ impl From<TestWorkflowArgs> for IndexMap<String, Value> {
         if let Some(service_database) = val.service_database {
             map.insert("service_database".to_string(), service_database.into());
         }
+        if let Some(run_in_container) = val.run_in_container {
+            map.insert("run_in_container".to_string(), run_in_container.into());
+        }
+        if let Some(runner_label) = val.runner_label {
+            map.insert("runner_label".to_string(), runner_label.into());
+        }
+        if let Some(test_filter) = val.test_filter {
+            map.insert("test_filter".to_string(), test_filter.into());
+        }
+        if let Some(sanitizers) = val.sanitizers {
+            map.insert("sanitizers".to_string(), sanitizers.into());
+        }
         map
     }
 }
@@ -179,6 +203,12 @@ impl From<IndexMap<String, Value>> for TestWorkflowArgs {
                 "skip_miri_test" => me.skip_miri_test = Some(v.into()),
                 "test_publish_required" => me.test_publish_required = Some(v.into()),
                 "service_database" => me.service_database = Some(v.into()),
+                "run_in_container" => {
+                    me.run_in_container = match v {
+                        Value::String(s) => Some(s),
+                        _ => None,
+                    }
+                }
                 _ => {}
             };
         }
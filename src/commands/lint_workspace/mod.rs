@@ -0,0 +1,356 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Context;
+use cargo_metadata::{MetadataCommand, Package};
+use clap::Parser;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::commands::check_workspace::read_toolchain;
+use crate::utils;
+
+#[derive(Debug, Parser)]
+#[command(about = "Check the workspaces follow the monorepo conventions.")]
+pub struct Options {
+    /// Crates that must not be resolved to more than one version in a workspace
+    #[arg(long)]
+    ban_duplicates: Vec<String>,
+    /// Ignore a rule for a package (or workspace), as `<rule>=<name>`; `<rule>=*` disables the rule
+    #[arg(long)]
+    allow: Vec<Allow>,
+}
+
+#[derive(clap::ValueEnum, Serialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    /// Publishable crates must have a `package.metadata.fslabs` section
+    MissingFslabsMetadata,
+    /// Package names must be unique across workspaces
+    DuplicatePackageName,
+    /// Path dependencies must require the version of the crate they point to
+    PathDependencyVersion,
+    /// Workspaces must use the version 2 feature resolver
+    Resolver,
+    /// Workspaces must pin the same toolchain as the repository root
+    Toolchain,
+    /// Banned crates must be resolved to a single version
+    DuplicateDependency,
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::MissingFslabsMetadata => "missing-fslabs-metadata",
+            Self::DuplicatePackageName => "duplicate-package-name",
+            Self::PathDependencyVersion => "path-dependency-version",
+            Self::Resolver => "resolver",
+            Self::Toolchain => "toolchain",
+            Self::DuplicateDependency => "duplicate-dependency",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Allow {
+    rule: Rule,
+    name: String,
+}
+
+impl FromStr for Allow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rule, name) = s
+            .split_once('=')
+            .with_context(|| format!("Allow `{}` should be formatted as <rule>=<name>", s))?;
+        let rule = <Rule as clap::ValueEnum>::from_str(rule, true)
+            .map_err(|e| anyhow::anyhow!("Unknown rule `{}`: {}", rule, e))?;
+        Ok(Self {
+            rule,
+            name: name.to_string(),
+        })
+    }
+}
+
+#[derive(Serialize, JsonSchema, Clone, Debug)]
+pub struct Violation {
+    pub rule: Rule,
+    pub workspace: String,
+    pub package: Option<String>,
+    pub message: String,
+}
+
+impl Violation {
+    fn is_allowed(&self, allows: &[Allow]) -> bool {
+        allows.iter().any(|a| {
+            a.rule == self.rule
+                && (a.name == "*"
+                    || a.name == self.workspace
+                    || self.package.as_ref() == Some(&a.name))
+        })
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LintWorkspaceResult {
+    pub violations: Vec<Violation>,
+}
+
+impl Display for LintWorkspaceResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for violation in &self.violations {
+            writeln!(
+                f,
+                "[{}] {}{}: {}",
+                violation.rule,
+                violation.workspace,
+                violation
+                    .package
+                    .as_ref()
+                    .map(|p| format!("/{}", p))
+                    .unwrap_or_default(),
+                violation.message
+            )?;
+        }
+        write!(f, "{} violation(s)", self.violations.len())
+    }
+}
+
+struct Workspace {
+    name: String,
+    root: PathBuf,
+    packages: Vec<Package>,
+}
+
+/// Feature resolver used by a workspace, as cargo infers it from the root manifest
+fn resolver_version(manifest: &toml::Value) -> String {
+    if let Some(resolver) = manifest
+        .get("workspace")
+        .and_then(|w| w.get("resolver"))
+        .and_then(|r| r.as_str())
+    {
+        return resolver.to_string();
+    }
+    if let Some(resolver) = manifest
+        .get("package")
+        .and_then(|p| p.get("resolver"))
+        .and_then(|r| r.as_str())
+    {
+        return resolver.to_string();
+    }
+    match manifest
+        .get("package")
+        .and_then(|p| p.get("edition"))
+        .and_then(|e| e.as_str())
+    {
+        Some("2015") | Some("2018") | None => "1".to_string(),
+        Some(_) => "2".to_string(),
+    }
+}
+
+fn lint_packages(workspaces: &[Workspace], violations: &mut Vec<Violation>) {
+    let versions: HashMap<&str, &cargo_metadata::semver::Version> = workspaces
+        .iter()
+        .flat_map(|w| w.packages.iter())
+        .map(|p| (p.name.as_str(), &p.version))
+        .collect();
+    let mut owners: HashMap<&str, Vec<&str>> = HashMap::new();
+    for workspace in workspaces {
+        for package in &workspace.packages {
+            owners
+                .entry(package.name.as_str())
+                .or_default()
+                .push(workspace.name.as_str());
+            let publishable = package
+                .publish
+                .as_ref()
+                .map(|r| !r.is_empty())
+                .unwrap_or(true);
+            if publishable && package.metadata.get("fslabs").is_none() {
+                violations.push(Violation {
+                    rule: Rule::MissingFslabsMetadata,
+                    workspace: workspace.name.clone(),
+                    package: Some(package.name.clone()),
+                    message: "publishable crate without package.metadata.fslabs".to_string(),
+                });
+            }
+            for dependency in package.dependencies.iter().filter(|d| d.path.is_some()) {
+                let Some(version) = versions.get(dependency.name.as_str()) else {
+                    continue;
+                };
+                if !dependency.req.matches(version) {
+                    violations.push(Violation {
+                        rule: Rule::PathDependencyVersion,
+                        workspace: workspace.name.clone(),
+                        package: Some(package.name.clone()),
+                        message: format!(
+                            "path dependency {} requires {} but is at {}",
+                            dependency.name, dependency.req, version
+                        ),
+                    });
+                }
+            }
+        }
+    }
+    for (package, workspaces) in owners {
+        if workspaces.len() > 1 {
+            violations.push(Violation {
+                rule: Rule::DuplicatePackageName,
+                workspace: workspaces[0].to_string(),
+                package: Some(package.to_string()),
+                message: format!("package is also defined in {}", workspaces[1..].join(", ")),
+            });
+        }
+    }
+}
+
+fn lint_roots(
+    workspaces: &[Workspace],
+    repository_toolchain: Option<String>,
+    violations: &mut Vec<Violation>,
+) -> anyhow::Result<()> {
+    for workspace in workspaces {
+        let manifest: toml::Value =
+            toml::from_str(&fs::read_to_string(workspace.root.join("Cargo.toml"))?)?;
+        let resolver = resolver_version(&manifest);
+        if resolver != "2" {
+            violations.push(Violation {
+                rule: Rule::Resolver,
+                workspace: workspace.name.clone(),
+                package: None,
+                message: format!("workspace uses resolver {}", resolver),
+            });
+        }
+        let toolchain = read_toolchain(&workspace.root).or(repository_toolchain.clone());
+        if toolchain != repository_toolchain {
+            violations.push(Violation {
+                rule: Rule::Toolchain,
+                workspace: workspace.name.clone(),
+                package: None,
+                message: format!(
+                    "workspace pins toolchain {} instead of {}",
+                    toolchain.unwrap_or_default(),
+                    repository_toolchain.clone().unwrap_or("none".to_string())
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn lint_duplicate_dependencies(
+    workspaces: &[Workspace],
+    banned: &[String],
+    violations: &mut Vec<Violation>,
+) -> anyhow::Result<()> {
+    for workspace in workspaces {
+        let metadata = MetadataCommand::new()
+            .current_dir(workspace.root.clone())
+            .exec()
+            .with_context(|| format!("Could not resolve dependencies of {}", workspace.name))?;
+        for name in banned {
+            let versions: BTreeSet<String> = metadata
+                .packages
+                .iter()
+                .filter(|p| &p.name == name)
+                .map(|p| p.version.to_string())
+                .collect();
+            if versions.len() > 1 {
+                violations.push(Violation {
+                    rule: Rule::DuplicateDependency,
+                    workspace: workspace.name.clone(),
+                    package: Some(name.clone()),
+                    message: format!(
+                        "resolved to versions {}",
+                        versions.into_iter().collect::<Vec<String>>().join(", ")
+                    ),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn lint_workspace(
+    options: Box<Options>,
+    working_directory: PathBuf,
+) -> anyhow::Result<LintWorkspaceResult> {
+    let roots = utils::get_cargo_roots(working_directory.clone())
+        .with_context(|| format!("Failed to get roots from {:?}", working_directory))?;
+    let mut workspaces = vec![];
+    for root in roots {
+        let name = workspace_name(&root);
+        let metadata = utils::cargo_metadata(&root, false)?;
+        workspaces.push(Workspace {
+            name,
+            root,
+            packages: metadata.packages,
+        });
+    }
+    let mut violations = vec![];
+    lint_packages(&workspaces, &mut violations);
+    lint_roots(
+        &workspaces,
+        read_toolchain(&working_directory),
+        &mut violations,
+    )?;
+    if !options.ban_duplicates.is_empty() {
+        lint_duplicate_dependencies(&workspaces, &options.ban_duplicates, &mut violations)?;
+    }
+    violations.retain(|v| !v.is_allowed(&options.allow));
+    Ok(LintWorkspaceResult { violations })
+}
+
+fn workspace_name(root: &Path) -> String {
+    root.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolver_version() {
+        let manifest = |s: &str| toml::from_str::<toml::Value>(s).expect("Could not parse toml");
+        assert_eq!(
+            resolver_version(&manifest("[workspace]\nresolver = \"2\"\n")),
+            "2"
+        );
+        assert_eq!(
+            resolver_version(&manifest("[workspace]\nmembers = []\n")),
+            "1"
+        );
+        assert_eq!(
+            resolver_version(&manifest("[package]\nname = \"a\"\nedition = \"2021\"\n")),
+            "2"
+        );
+        assert_eq!(
+            resolver_version(&manifest("[package]\nname = \"a\"\nedition = \"2018\"\n")),
+            "1"
+        );
+    }
+
+    #[test]
+    fn test_allow() {
+        let violation = Violation {
+            rule: Rule::Resolver,
+            workspace: "workspace_a".to_string(),
+            package: Some("crate_a".to_string()),
+            message: "".to_string(),
+        };
+        let allow = |s: &str| s.parse::<Allow>().expect("Could not parse allow");
+        assert!(violation.is_allowed(&[allow("resolver=workspace_a")]));
+        assert!(violation.is_allowed(&[allow("resolver=crate_a")]));
+        assert!(violation.is_allowed(&[allow("resolver=*")]));
+        assert!(!violation.is_allowed(&[allow("toolchain=*")]));
+        assert!("unknown=*".parse::<Allow>().is_err());
+        assert!("resolver".parse::<Allow>().is_err());
+    }
+}
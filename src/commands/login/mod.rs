@@ -0,0 +1,93 @@
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::io::{self, BufRead};
+use std::path::PathBuf;
+
+use clap::Parser;
+use console::Term;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::commands::check_workspace::secrets::set_keyring_secret;
+
+#[derive(Debug, Parser)]
+#[command(
+    about = "Store registry credentials in the OS keyring, used by `check-workspace --secrets-backend keyring`."
+)]
+pub struct Options {
+    #[arg(value_enum)]
+    credential: Credential,
+    /// Read the secret from the first line of stdin instead of prompting for it
+    #[arg(long, default_value_t = false)]
+    secret_stdin: bool,
+}
+
+/// Variable holding the secret, to store it without prompting nor exposing it in the arguments
+const SECRET_ENV: &str = "FSLABSCLI_SECRET";
+
+#[derive(clap::ValueEnum, Clone, Debug, Serialize)]
+enum Credential {
+    /// Private cargo registry user agent (`--cargo-registry-user-agent`)
+    Cargo,
+    /// Private cargo registry token (`--cargo-registry-token`)
+    CargoToken,
+    /// Docker registry password (`--docker-registry-password`)
+    Docker,
+    /// Npm registry token (`--npm-registry-token`)
+    Npm,
+    /// Binary store access key (`--binary-store-access-key`)
+    BinaryStore,
+    /// Private python registry token (`--pypi-registry-token`)
+    Pypi,
+}
+
+impl Credential {
+    fn key(&self) -> &'static str {
+        match self {
+            Self::Cargo => "cargo_registry_user_agent",
+            Self::CargoToken => "cargo_registry_token",
+            Self::Docker => "docker_registry_password",
+            Self::Npm => "npm_registry_token",
+            Self::BinaryStore => "binary_store_access_key",
+            Self::Pypi => "pypi_registry_token",
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LoginResult {
+    credential: String,
+}
+
+impl Display for LoginResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Stored `{}` in the OS keyring", self.credential)
+    }
+}
+
+pub async fn login(
+    options: Box<Options>,
+    _working_directory: PathBuf,
+) -> anyhow::Result<LoginResult> {
+    let key = options.credential.key();
+    let secret = match (options.secret_stdin, env::var(SECRET_ENV)) {
+        (true, _) => {
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+        (false, Ok(secret)) => secret,
+        (false, Err(_)) => {
+            let term = Term::stderr();
+            term.write_line(&format!("Enter value for `{}`:", key))?;
+            term.read_secure_line()?
+        }
+    };
+    if secret.is_empty() {
+        anyhow::bail!("Refusing to store an empty secret");
+    }
+    set_keyring_secret(key, &secret)?;
+    Ok(LoginResult {
+        credential: key.to_string(),
+    })
+}
@@ -0,0 +1,128 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::{Command, Parser};
+use clap_mangen::Man;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+#[derive(Debug, Parser)]
+#[command(about = "Generate the man pages of the CLI and of each subcommand.")]
+pub struct Options {
+    /// Directory to write the pages to
+    #[arg(long, default_value = "man")]
+    output: PathBuf,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ManResult {
+    output: PathBuf,
+    pages: Vec<PathBuf>,
+}
+
+impl Display for ManResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Wrote {} man pages to {}",
+            self.pages.len(),
+            self.output.to_string_lossy()
+        )
+    }
+}
+
+fn write_page(command: Command, output: &Path) -> anyhow::Result<PathBuf> {
+    let man = Man::new(command);
+    let path = output.join(man.get_filename());
+    let mut page: Vec<u8> = vec![];
+    man.render(&mut page)?;
+    fs::write(&path, page)?;
+    Ok(path)
+}
+
+/// One page for the CLI and one per subcommand, e.g. `fslabscli-check-workspace.1`, listing all
+/// of its options, global ones included
+pub async fn man(options: Box<Options>, mut command: Command) -> anyhow::Result<ManResult> {
+    fs::create_dir_all(&options.output)?;
+    // Propagates the global options to the subcommands
+    command.build();
+    let name = command
+        .get_bin_name()
+        .unwrap_or_else(|| command.get_name())
+        .to_string();
+    let mut pages = vec![];
+    for subcommand in command.get_subcommands() {
+        if subcommand.is_hide_set() || subcommand.get_name() == "help" {
+            continue;
+        }
+        let page_name = format!("{}-{}", name, subcommand.get_name());
+        pages.push(write_page(
+            subcommand
+                .clone()
+                .name(page_name.clone())
+                .display_name(page_name.clone())
+                .bin_name(page_name),
+            &options.output,
+        )?);
+    }
+    pages.insert(
+        0,
+        write_page(
+            command.name(name.clone()).display_name(name),
+            &options.output,
+        )?,
+    );
+    Ok(ManResult {
+        output: options.output,
+        pages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::TempDir;
+    use clap::{Arg, ArgAction};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_man() {
+        let tmp = TempDir::new().expect("Could not create temp dir");
+        let command = Command::new("fslabscli")
+            .bin_name("fslabscli")
+            .arg(
+                Arg::new("verbose")
+                    .long("verbose")
+                    .global(true)
+                    .action(ArgAction::SetTrue),
+            )
+            .subcommand(
+                Command::new("check-workspace").arg(
+                    Arg::new("vault_address")
+                        .long("vault-address")
+                        .env("VAULT_ADDR"),
+                ),
+            );
+        let result = man(
+            Box::new(Options {
+                output: tmp.path().to_path_buf(),
+            }),
+            command,
+        )
+        .await
+        .expect("Could not generate man pages");
+        assert_eq!(
+            result.pages,
+            vec![
+                tmp.path().join("fslabscli.1"),
+                tmp.path().join("fslabscli-check-workspace.1")
+            ]
+        );
+        let page = fs::read_to_string(tmp.path().join("fslabscli-check-workspace.1"))
+            .expect("Could not read page");
+        assert!(page.contains("vault\\-address"));
+        assert!(page.contains("VAULT_ADDR"));
+        assert!(page.contains("verbose"));
+    }
+}
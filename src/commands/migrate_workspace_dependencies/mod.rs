@@ -0,0 +1,430 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use cargo_metadata::MetadataCommand;
+use clap::Parser;
+use schemars::JsonSchema;
+use serde::Serialize;
+use toml_edit::{DocumentMut, InlineTable, Item, Table, Value};
+
+use crate::utils;
+
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+#[derive(Debug, Parser)]
+#[command(about = "Move dependencies shared by workspace members to `[workspace.dependencies]`.")]
+pub struct Options {
+    /// Only hoist dependencies used by at least that many members
+    #[arg(long, default_value_t = 2)]
+    min_members: usize,
+    /// Only report what would be changed
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+#[derive(Serialize, JsonSchema, Default, Debug, PartialEq)]
+pub struct WorkspaceMigration {
+    pub workspace: String,
+    /// Dependencies added to `[workspace.dependencies]`
+    pub hoisted: Vec<String>,
+    /// Number of member dependencies converted to `workspace = true`
+    pub converted: usize,
+    /// Dependencies left untouched because members disagree on their version
+    pub conflicts: Vec<String>,
+    /// Lockfile entries added (`+name version`) or removed (`-name version`) by the migration
+    pub lockfile_changes: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct MigrateWorkspaceDependenciesResult {
+    pub workspaces: Vec<WorkspaceMigration>,
+    pub dry_run: bool,
+}
+
+impl Display for MigrateWorkspaceDependenciesResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for workspace in &self.workspaces {
+            writeln!(
+                f,
+                "{}: hoisted {} dependencies, converted {} member dependencies",
+                workspace.workspace,
+                workspace.hoisted.len(),
+                workspace.converted
+            )?;
+            if !workspace.conflicts.is_empty() {
+                writeln!(
+                    f,
+                    "  conflicting versions: {}",
+                    workspace.conflicts.join(", ")
+                )?;
+            }
+            if !workspace.lockfile_changes.is_empty() {
+                writeln!(
+                    f,
+                    "  lockfile changes: {}",
+                    workspace.lockfile_changes.join(", ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The part of a dependency declaration that `[workspace.dependencies]` holds
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct DependencySpec {
+    version: String,
+    package: Option<String>,
+    registry: Option<String>,
+    default_features: Option<bool>,
+}
+
+impl DependencySpec {
+    /// Registry dependencies only, path and git dependencies are left alone
+    fn from_item(item: &Item) -> Option<Self> {
+        if let Some(version) = item.as_str() {
+            return Some(Self {
+                version: version.to_string(),
+                package: None,
+                registry: None,
+                default_features: None,
+            });
+        }
+        let table = item.as_table_like()?;
+        if ["workspace", "path", "git"]
+            .iter()
+            .any(|k| table.contains_key(k))
+        {
+            return None;
+        }
+        let string = |key: &str| {
+            table
+                .get(key)
+                .and_then(|i| i.as_str())
+                .map(|s| s.to_string())
+        };
+        Some(Self {
+            version: string("version")?,
+            package: string("package"),
+            registry: string("registry"),
+            default_features: table.get("default-features").and_then(|i| i.as_bool()),
+        })
+    }
+
+    fn to_item(&self) -> Item {
+        if self.package.is_none() && self.registry.is_none() && self.default_features.is_none() {
+            return Item::Value(Value::from(self.version.clone()));
+        }
+        let mut table = InlineTable::new();
+        table.insert("version", Value::from(self.version.clone()));
+        if let Some(package) = &self.package {
+            table.insert("package", Value::from(package.clone()));
+        }
+        if let Some(registry) = &self.registry {
+            table.insert("registry", Value::from(registry.clone()));
+        }
+        if let Some(default_features) = self.default_features {
+            table.insert("default-features", Value::from(default_features));
+        }
+        Item::Value(Value::InlineTable(table))
+    }
+}
+
+/// Member declaration inheriting from the workspace, keeping the member specific keys
+fn workspace_item(item: &Item) -> Item {
+    let mut table = InlineTable::new();
+    table.insert("workspace", Value::from(true));
+    if let Some(existing) = item.as_table_like() {
+        for key in ["features", "optional"] {
+            if let Some(value) = existing.get(key).and_then(|i| i.as_value()) {
+                table.insert(key, value.clone());
+            }
+        }
+    }
+    Item::Value(Value::InlineTable(table))
+}
+
+fn migrate(
+    workspace: String,
+    root: &mut DocumentMut,
+    members: &mut [DocumentMut],
+    min_members: usize,
+) -> WorkspaceMigration {
+    let mut migration = WorkspaceMigration {
+        workspace,
+        ..Default::default()
+    };
+    // Every registry dependency spec, with the members declaring it
+    let mut usages: BTreeMap<String, BTreeMap<DependencySpec, BTreeSet<usize>>> = BTreeMap::new();
+    for (index, member) in members.iter().enumerate() {
+        for table_name in DEPENDENCY_TABLES {
+            let Some(table) = member.get(table_name).and_then(|t| t.as_table_like()) else {
+                continue;
+            };
+            for (name, item) in table.iter() {
+                if let Some(spec) = DependencySpec::from_item(item) {
+                    usages
+                        .entry(name.to_string())
+                        .or_default()
+                        .entry(spec)
+                        .or_default()
+                        .insert(index);
+                }
+            }
+        }
+    }
+    if !root.contains_key("workspace") {
+        root["workspace"] = Item::Table(Table::new());
+    }
+    if root["workspace"].get("dependencies").is_none() {
+        root["workspace"]["dependencies"] = Item::Table(Table::new());
+    }
+    let mut inherited: BTreeMap<String, DependencySpec> = BTreeMap::new();
+    for (name, specs) in usages {
+        let existing = root["workspace"]["dependencies"]
+            .get(&name)
+            .and_then(DependencySpec::from_item);
+        match existing {
+            Some(spec) => {
+                if specs.contains_key(&spec) {
+                    inherited.insert(name, spec);
+                }
+            }
+            None if specs.len() > 1 => migration.conflicts.push(name),
+            None => {
+                let (spec, users) = specs.into_iter().next().unwrap();
+                if users.len() >= min_members {
+                    root["workspace"]["dependencies"][&name] = spec.to_item();
+                    migration.hoisted.push(name.clone());
+                    inherited.insert(name, spec);
+                }
+            }
+        }
+    }
+    for member in members.iter_mut() {
+        for table_name in DEPENDENCY_TABLES {
+            let Some(table) = member
+                .get_mut(table_name)
+                .and_then(|t| t.as_table_like_mut())
+            else {
+                continue;
+            };
+            for (name, item) in table.iter_mut() {
+                let Some(spec) = inherited.get(name.get()) else {
+                    continue;
+                };
+                if DependencySpec::from_item(item).as_ref() == Some(spec) {
+                    *item = workspace_item(item);
+                    migration.converted += 1;
+                }
+            }
+        }
+    }
+    migration
+}
+
+/// `name version` of every package of a lockfile
+fn locked_packages(lockfile: &str) -> BTreeSet<String> {
+    let Ok(document) = lockfile.parse::<DocumentMut>() else {
+        return BTreeSet::new();
+    };
+    document
+        .get("package")
+        .and_then(|p| p.as_array_of_tables())
+        .map(|packages| {
+            packages
+                .iter()
+                .filter_map(|p| {
+                    Some(format!(
+                        "{} {}",
+                        p.get("name")?.as_str()?,
+                        p.get("version")?.as_str()?
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Packages added (`+name version`) and removed (`-name version`) between two lockfiles
+fn lockfile_diff(before: &str, after: &str) -> Vec<String> {
+    let (before, after) = (locked_packages(before), locked_packages(after));
+    after
+        .difference(&before)
+        .map(|p| format!("+{}", p))
+        .chain(before.difference(&after).map(|p| format!("-{}", p)))
+        .collect()
+}
+
+pub async fn migrate_workspace_dependencies(
+    options: Box<Options>,
+    working_directory: PathBuf,
+) -> anyhow::Result<MigrateWorkspaceDependenciesResult> {
+    let roots = utils::get_cargo_roots(working_directory.clone())
+        .with_context(|| format!("Failed to get roots from {:?}", working_directory))?;
+    let mut workspaces = vec![];
+    for root in roots {
+        let workspace_name = root
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let root_manifest = root.join("Cargo.toml");
+        let mut root_document: DocumentMut = fs::read_to_string(&root_manifest)?.parse()?;
+        if !root_document.contains_key("workspace") {
+            // Single crate, nothing to share
+            continue;
+        }
+        let metadata = MetadataCommand::new()
+            .current_dir(root.clone())
+            .no_deps()
+            .exec()
+            .with_context(|| format!("Could not load metadata of {}", workspace_name))?;
+        let manifests: Vec<PathBuf> = metadata
+            .workspace_packages()
+            .iter()
+            .map(|p| p.manifest_path.clone().into_std_path_buf())
+            .filter(|p| p != &root_manifest)
+            .collect();
+        let mut members = manifests
+            .iter()
+            .map(|m| Ok(fs::read_to_string(m)?.parse::<DocumentMut>()?))
+            .collect::<anyhow::Result<Vec<DocumentMut>>>()?;
+        let mut migration = migrate(
+            workspace_name,
+            &mut root_document,
+            &mut members,
+            options.min_members,
+        );
+        if !options.dry_run && migration.converted > 0 {
+            fs::write(&root_manifest, root_document.to_string())?;
+            for (manifest, member) in manifests.iter().zip(members.iter()) {
+                fs::write(manifest, member.to_string())?;
+            }
+            // Loading the workspace re-resolves the lockfile without bumping locked versions
+            let lockfile = root.join("Cargo.lock");
+            let locked = fs::read_to_string(&lockfile).unwrap_or_default();
+            MetadataCommand::new()
+                .current_dir(root.clone())
+                .exec()
+                .with_context(|| {
+                    format!("Could not update the lockfile of {}", migration.workspace)
+                })?;
+            migration.lockfile_changes =
+                lockfile_diff(&locked, &fs::read_to_string(&lockfile).unwrap_or_default());
+        }
+        workspaces.push(migration);
+    }
+    Ok(MigrateWorkspaceDependenciesResult {
+        workspaces,
+        dry_run: options.dry_run,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn test_migrate() {
+        let mut root: DocumentMut = indoc! {r#"
+            [workspace]
+            members = ["a", "b"]
+
+            [workspace.dependencies]
+            log = "0.4"
+        "#}
+        .parse()
+        .expect("Could not parse root manifest");
+        let mut members: Vec<DocumentMut> = vec![
+            indoc! {r#"
+                [package]
+                name = "a"
+
+                [dependencies]
+                serde = { version = "1.0", features = ["derive"] }
+                tokio = "1.36"
+                log = "0.4"
+                b = { path = "../b" }
+            "#}
+            .parse()
+            .expect("Could not parse member manifest"),
+            indoc! {r#"
+                [package]
+                name = "b"
+
+                [dependencies]
+                tokio = "1.35"
+                anyhow = "1.0"
+
+                [dev-dependencies]
+                serde = { version = "1.0", optional = true }
+            "#}
+            .parse()
+            .expect("Could not parse member manifest"),
+        ];
+        let migration = migrate("ws".to_string(), &mut root, &mut members, 2);
+        assert_eq!(
+            migration,
+            WorkspaceMigration {
+                workspace: "ws".to_string(),
+                hoisted: vec!["serde".to_string()],
+                converted: 3,
+                conflicts: vec!["tokio".to_string()],
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            root["workspace"]["dependencies"]["serde"].as_str(),
+            Some("1.0")
+        );
+        assert_eq!(
+            members[0]["dependencies"].to_string(),
+            indoc! {r#"
+                serde = { workspace = true, features = ["derive"] }
+                tokio = "1.36"
+                log = { workspace = true }
+                b = { path = "../b" }
+            "#}
+        );
+        assert_eq!(
+            members[1]["dev-dependencies"]["serde"].to_string(),
+            "{ workspace = true, optional = true }"
+        );
+    }
+
+    #[test]
+    fn test_lockfile_diff() {
+        let before = indoc! {r#"
+            version = 3
+
+            [[package]]
+            name = "member"
+            version = "0.1.0"
+
+            [[package]]
+            name = "serde"
+            version = "1.0.190"
+        "#};
+        let after = indoc! {r#"
+            version = 3
+
+            [[package]]
+            name = "member"
+            version = "0.1.0"
+
+            [[package]]
+            name = "serde"
+            version = "1.0.196"
+        "#};
+        assert_eq!(
+            lockfile_diff(before, after),
+            vec!["+serde 1.0.196".to_string(), "-serde 1.0.190".to_string()]
+        );
+        assert!(lockfile_diff(before, before).is_empty());
+    }
+}
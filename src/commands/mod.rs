@@ -1,3 +1,19 @@
+pub mod bump_dependents;
 pub mod check_workspace;
+pub mod codeowners;
+pub mod completions;
+pub mod dependency_report;
+pub mod docs_index;
+pub mod external;
+pub mod generate_dependabot;
 pub mod generate_workflow;
+pub mod lint_workspace;
+pub mod login;
+pub mod man;
+pub mod migrate_workspace_dependencies;
+pub mod new;
+pub mod preview_update;
+pub mod prune_cache;
+pub mod schema;
 pub mod summaries;
+pub mod warm_cache;
@@ -0,0 +1,220 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Parser;
+use schemars::JsonSchema;
+use serde::Serialize;
+use toml_edit::{Array, DocumentMut, Item, Table, Value};
+
+#[derive(Debug, Parser)]
+#[command(about = "Create a crate following the fslabs conventions.")]
+pub struct Options {
+    /// Crate to create, as `<workspace>/<crate>`
+    path: PathBuf,
+    #[arg(long, value_enum, default_value_t = Template::Lib)]
+    template: Template,
+    /// Private registry the crate gets published to
+    #[arg(long, default_value = "foresight-mining-software-corporation")]
+    registry: String,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
+enum Template {
+    /// Library published to the private registry
+    Lib,
+    /// Binary published to the binary store
+    Bin,
+    /// Service published as a docker image
+    Service,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct NewResult {
+    pub package: String,
+    pub path: PathBuf,
+    pub files: Vec<PathBuf>,
+}
+
+impl Display for NewResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Created {} in {}",
+            self.package,
+            self.path.to_string_lossy()
+        )?;
+        for file in &self.files {
+            writeln!(f, "  {}", file.to_string_lossy())?;
+        }
+        Ok(())
+    }
+}
+
+fn manifest(name: &str, template: &Template, registry: &str) -> String {
+    let mut manifest = format!(
+        "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        name
+    );
+    match template {
+        Template::Lib => manifest.push_str(&format!(
+            "publish = [\"{}\"]\n\n[dependencies]\n\n[package.metadata.fslabs.publish.cargo]\npublish = true\n",
+            registry
+        )),
+        Template::Bin => manifest.push_str(&format!(
+            "publish = false\n\n[dependencies]\n\n[package.metadata.fslabs.publish.binary]\npublish = true\nname = \"{}\"\ntargets = [\"x86_64-unknown-linux-gnu\"]\n",
+            name
+        )),
+        Template::Service => manifest.push_str(
+            "publish = false\n\n[dependencies]\n\n[package.metadata.fslabs.publish.docker]\npublish = true\n",
+        ),
+    }
+    manifest.push_str("\n[package.metadata.fslabs.test]\nskip = false\n");
+    manifest
+}
+
+fn dockerfile(name: &str) -> String {
+    format!(
+        "FROM rust:1 AS builder\nWORKDIR /app\nCOPY . .\nRUN cargo build --release --bin {name}\n\nFROM debian:bookworm-slim\nCOPY --from=builder /app/target/release/{name} /usr/local/bin/{name}\nENTRYPOINT [\"/usr/local/bin/{name}\"]\n"
+    )
+}
+
+/// Add `member` to the members of the workspace at `workspace_root`, creating the workspace if needed
+fn register_member(workspace_root: &Path, member: &str) -> anyhow::Result<()> {
+    let manifest_path = workspace_root.join("Cargo.toml");
+    let mut document: DocumentMut = match manifest_path.exists() {
+        true => fs::read_to_string(&manifest_path)?.parse()?,
+        false => {
+            let mut document = DocumentMut::new();
+            let mut workspace = Table::new();
+            workspace.insert("resolver", Item::Value(Value::from("2")));
+            document.insert("workspace", Item::Table(workspace));
+            document
+        }
+    };
+    let Some(workspace) = document.get_mut("workspace").and_then(|w| w.as_table_mut()) else {
+        anyhow::bail!(
+            "{} is a single crate, not a workspace",
+            manifest_path.to_string_lossy()
+        );
+    };
+    if !workspace.contains_key("members") {
+        workspace.insert("members", Item::Value(Value::Array(Array::new())));
+    }
+    let members = workspace["members"]
+        .as_array_mut()
+        .with_context(|| "workspace.members should be an array")?;
+    let already_member = members.iter().filter_map(|m| m.as_str()).any(|m| {
+        m == member
+            || m.strip_suffix('*')
+                .map(|prefix| member.starts_with(prefix))
+                .unwrap_or(false)
+    });
+    if !already_member {
+        members.push(member);
+    }
+    fs::write(manifest_path, document.to_string())?;
+    Ok(())
+}
+
+pub async fn new_crate(
+    options: Box<Options>,
+    working_directory: PathBuf,
+) -> anyhow::Result<NewResult> {
+    let name = options
+        .path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .with_context(|| "The crate path should end with the crate name")?;
+    let workspace_root = working_directory.join(
+        options
+            .path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .with_context(|| "The crate path should be formatted as <workspace>/<crate>")?,
+    );
+    let crate_dir = workspace_root.join(&name);
+    if crate_dir.exists() {
+        anyhow::bail!("{} already exists", crate_dir.to_string_lossy());
+    }
+    let src_dir = crate_dir.join("src");
+    fs::create_dir_all(&src_dir)?;
+    let mut files = vec![crate_dir.join("Cargo.toml")];
+    fs::write(
+        &files[0],
+        manifest(&name, &options.template, &options.registry),
+    )?;
+    let source = match options.template {
+        Template::Lib => (src_dir.join("lib.rs"), "".to_string()),
+        _ => (
+            src_dir.join("main.rs"),
+            "fn main() {\n    println!(\"Hello, world!\");\n}\n".to_string(),
+        ),
+    };
+    fs::write(&source.0, source.1)?;
+    files.push(source.0);
+    if options.template == Template::Service {
+        let path = crate_dir.join("Dockerfile");
+        fs::write(&path, dockerfile(&name))?;
+        files.push(path);
+    }
+    register_member(&workspace_root, &name)?;
+    Ok(NewResult {
+        package: name,
+        path: crate_dir,
+        files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_new_crate() {
+        let tmp = TempDir::new().expect("Could not create temp dir");
+        let new = |path: &str, template: Template| {
+            Box::new(Options {
+                path: PathBuf::from(path),
+                template,
+                registry: "my_registry".to_string(),
+            })
+        };
+        new_crate(new("workspace_a/crate_a", Template::Lib), tmp.to_path_buf())
+            .await
+            .expect("Could not create lib");
+        let result = new_crate(
+            new("workspace_a/crate_b", Template::Service),
+            tmp.to_path_buf(),
+        )
+        .await
+        .expect("Could not create service");
+        assert_eq!(result.files.len(), 3);
+        let workspace: toml::Value = toml::from_str(
+            &fs::read_to_string(tmp.join("workspace_a/Cargo.toml"))
+                .expect("Could not read workspace"),
+        )
+        .expect("Could not parse workspace");
+        assert_eq!(
+            workspace["workspace"]["members"],
+            toml::Value::Array(vec!["crate_a".into(), "crate_b".into()])
+        );
+        let manifest: toml::Value = toml::from_str(
+            &fs::read_to_string(tmp.join("workspace_a/crate_a/Cargo.toml"))
+                .expect("Could not read manifest"),
+        )
+        .expect("Could not parse manifest");
+        assert_eq!(
+            manifest["package"]["metadata"]["fslabs"]["publish"]["cargo"]["publish"],
+            toml::Value::Boolean(true)
+        );
+        assert!(
+            new_crate(new("workspace_a/crate_a", Template::Lib), tmp.to_path_buf())
+                .await
+                .is_err()
+        );
+    }
+}
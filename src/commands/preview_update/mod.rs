@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::Context;
+use cargo_metadata::semver::Version;
+use clap::Parser;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::dependency_report::compatibility_key;
+use crate::utils;
+
+#[derive(Debug, Parser)]
+#[command(
+    about = "Preview what `cargo update` would change in the lockfile of every workspace, without touching the lockfiles."
+)]
+pub struct Options {
+    /// Only update these crates, as with `cargo update --package`
+    #[arg(long)]
+    package: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Deserialize)]
+struct LockedPackage {
+    name: String,
+    version: String,
+}
+
+#[derive(Serialize, JsonSchema, Debug, PartialEq)]
+pub struct LockfileChange {
+    pub name: String,
+    pub from: Vec<String>,
+    pub to: Vec<String>,
+    /// One of the new versions is not semver compatible with any of the previous ones
+    pub new_major: bool,
+    /// The crate was not in the lockfile before
+    pub new_crate: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct WorkspacePreview {
+    pub workspace: PathBuf,
+    pub changes: Vec<LockfileChange>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PreviewUpdateResult {
+    pub workspaces: Vec<WorkspacePreview>,
+}
+
+impl Display for PreviewUpdateResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for workspace in &self.workspaces {
+            writeln!(f, "## {}", workspace.workspace.to_string_lossy())?;
+            writeln!(f)?;
+            if workspace.changes.is_empty() {
+                writeln!(f, "No changes")?;
+                writeln!(f)?;
+                continue;
+            }
+            writeln!(f, "| Crate | From | To | Notes |")?;
+            writeln!(f, "| ----- | ---- | -- | ----- |")?;
+            for change in &workspace.changes {
+                let notes = match (change.new_crate, change.new_major) {
+                    (true, _) => "new crate",
+                    (false, true) => "new major",
+                    _ => "",
+                };
+                writeln!(
+                    f,
+                    "| {} | {} | {} | {} |",
+                    change.name,
+                    change.from.join(", "),
+                    change.to.join(", "),
+                    notes
+                )?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn locked_versions(lockfile: &str) -> anyhow::Result<BTreeMap<String, BTreeSet<String>>> {
+    let lockfile: Lockfile = toml::from_str(lockfile)?;
+    let mut versions: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for package in lockfile.package {
+        versions
+            .entry(package.name)
+            .or_default()
+            .insert(package.version);
+    }
+    Ok(versions)
+}
+
+/// Locked versions once the changes reported by `cargo update --dry-run` are applied
+fn apply_dry_run(
+    locked: &BTreeMap<String, BTreeSet<String>>,
+    output: &str,
+) -> BTreeMap<String, BTreeSet<String>> {
+    let mut versions = locked.clone();
+    for line in output.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let version = |word: Option<&&str>| {
+            word.and_then(|w| w.strip_prefix('v'))
+                .filter(|v| Version::parse(v).is_ok())
+                .map(|v| v.to_string())
+        };
+        // e.g. `Updating serde v1.0.190 -> v1.0.196`, skipping `Updating crates.io index`
+        let (Some(action), Some(name), Some(from)) =
+            (words.first(), words.get(1), version(words.get(2)))
+        else {
+            continue;
+        };
+        let entry = versions.entry(name.to_string()).or_default();
+        match *action {
+            "Updating" | "Downgrading" => {
+                if let Some(to) = version(words.get(4)) {
+                    entry.remove(&from);
+                    entry.insert(to);
+                }
+            }
+            "Adding" => {
+                entry.insert(from);
+            }
+            "Removing" => {
+                entry.remove(&from);
+            }
+            _ => {}
+        }
+        if entry.is_empty() {
+            versions.remove(*name);
+        }
+    }
+    versions
+}
+
+/// Crates whose locked versions differ between `before` and `after`
+fn diff_versions(
+    before: &BTreeMap<String, BTreeSet<String>>,
+    after: &BTreeMap<String, BTreeSet<String>>,
+) -> Vec<LockfileChange> {
+    let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    let empty = BTreeSet::new();
+    let mut changes = vec![];
+    for name in names {
+        let from = before.get(name).unwrap_or(&empty);
+        let to = after.get(name).unwrap_or(&empty);
+        if from == to {
+            continue;
+        }
+        let keys = |versions: &BTreeSet<String>| -> BTreeSet<String> {
+            versions
+                .iter()
+                .filter_map(|v| Version::parse(v).ok())
+                .map(|v| compatibility_key(&v))
+                .collect()
+        };
+        let previous_keys = keys(from);
+        changes.push(LockfileChange {
+            name: name.clone(),
+            from: from.iter().cloned().collect(),
+            to: to.iter().cloned().collect(),
+            new_major: !from.is_empty() && !keys(to).is_subset(&previous_keys),
+            new_crate: from.is_empty(),
+        });
+    }
+    changes
+}
+
+pub async fn preview_update(
+    options: Box<Options>,
+    working_directory: PathBuf,
+) -> anyhow::Result<PreviewUpdateResult> {
+    let roots = utils::get_cargo_roots(working_directory.clone())
+        .with_context(|| format!("Failed to get roots from {:?}", working_directory))?;
+    let mut workspaces = vec![];
+    for root in roots {
+        let lockfile = root.join("Cargo.lock");
+        let Ok(before) = fs::read_to_string(&lockfile) else {
+            log::warn!("No lockfile in {}, skipping", root.to_string_lossy());
+            continue;
+        };
+        let before = locked_versions(&before)
+            .with_context(|| format!("Could not parse {}", lockfile.to_string_lossy()))?;
+        let mut command = Command::new("cargo");
+        command.args(["update", "--dry-run"]).current_dir(&root);
+        for package in &options.package {
+            command.args(["--package", package]);
+        }
+        let output = command.output()?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Could not update {}: {}",
+                root.to_string_lossy(),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        let after = apply_dry_run(&before, &String::from_utf8_lossy(&output.stderr));
+        workspaces.push(WorkspacePreview {
+            workspace: root
+                .strip_prefix(&working_directory)
+                .unwrap_or(&root)
+                .to_path_buf(),
+            changes: diff_versions(&before, &after),
+        });
+    }
+    Ok(PreviewUpdateResult { workspaces })
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn test_apply_dry_run() {
+        let before = indoc! {r#"
+            version = 3
+
+            [[package]]
+            name = "serde"
+            version = "1.0.190"
+
+            [[package]]
+            name = "syn"
+            version = "1.0.109"
+
+            [[package]]
+            name = "log"
+            version = "0.4.20"
+
+            [[package]]
+            name = "unchanged"
+            version = "0.1.0"
+        "#};
+        let output = indoc! {"
+                Updating crates.io index
+                Updating serde v1.0.190 -> v1.0.196
+                  Adding syn v2.0.48
+                  Adding unicode-ident v1.0.12
+                Removing log v0.4.20
+            warning: not updating lockfile due to dry run
+        "};
+        let change =
+            |name: &str, from: &[&str], to: &[&str], new_major, new_crate| LockfileChange {
+                name: name.to_string(),
+                from: from.iter().map(|v| v.to_string()).collect(),
+                to: to.iter().map(|v| v.to_string()).collect(),
+                new_major,
+                new_crate,
+            };
+        let before = locked_versions(before).expect("Could not parse lockfile");
+        let after = apply_dry_run(&before, output);
+        assert_eq!(
+            diff_versions(&before, &after),
+            vec![
+                change("log", &["0.4.20"], &[], false, false),
+                change("serde", &["1.0.190"], &["1.0.196"], false, false),
+                change("syn", &["1.0.109"], &["1.0.109", "2.0.48"], true, false),
+                change("unicode-ident", &[], &["1.0.12"], false, true),
+            ]
+        );
+    }
+}
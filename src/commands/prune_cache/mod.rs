@@ -0,0 +1,210 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use clap::Parser;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+#[derive(Debug, Parser)]
+#[command(about = "Trim the cargo caches and shared target directories of a runner.")]
+pub struct Options {
+    /// Cargo home to trim the registry and git caches of
+    #[arg(long, env = "CARGO_HOME")]
+    cargo_home: Option<PathBuf>,
+    /// Shared target directories to trim
+    #[arg(long)]
+    target_dir: Vec<PathBuf>,
+    /// Remove entries not modified for that many days
+    #[arg(long, default_value_t = 30)]
+    max_age_days: u64,
+    /// Then remove the oldest entries until the caches fit in that many MiB
+    #[arg(long)]
+    max_size_mb: Option<u64>,
+    /// Only report what would be removed
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+#[derive(Serialize, JsonSchema, Clone, Debug)]
+pub struct CacheEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    #[serde(skip)]
+    pub modified: SystemTime,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PruneCacheResult {
+    pub removed: Vec<CacheEntry>,
+    pub reclaimed_bytes: u64,
+    pub remaining_bytes: u64,
+    pub dry_run: bool,
+}
+
+impl Display for PruneCacheResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for entry in &self.removed {
+            writeln!(f, "{} ({} bytes)", entry.path.to_string_lossy(), entry.size)?;
+        }
+        write!(
+            f,
+            "{} {} entries, reclaimed {} MiB, {} MiB remaining",
+            match self.dry_run {
+                true => "Would remove",
+                false => "Removed",
+            },
+            self.removed.len(),
+            self.reclaimed_bytes / 1024 / 1024,
+            self.remaining_bytes / 1024 / 1024
+        )
+    }
+}
+
+/// Size and latest modification time of a file or directory tree
+fn measure(path: &Path) -> anyhow::Result<(u64, SystemTime)> {
+    let metadata = fs::symlink_metadata(path)?;
+    let mut size = metadata.len();
+    let mut modified = metadata.modified()?;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let (s, m) = measure(&entry?.path())?;
+            size += s;
+            modified = modified.max(m);
+        }
+    }
+    Ok((size, modified))
+}
+
+/// Entries found at `depth` levels below `root`, e.g. `registry/cache/<index>/<crate>` is at depth 2
+fn collect_entries(root: &Path, depth: usize) -> Vec<CacheEntry> {
+    let Ok(read_dir) = fs::read_dir(root) else {
+        return vec![];
+    };
+    let mut entries = vec![];
+    for entry in read_dir.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if depth > 1 {
+            entries.extend(collect_entries(&path, depth - 1));
+            continue;
+        }
+        match measure(&path) {
+            Ok((size, modified)) => entries.push(CacheEntry {
+                path,
+                size,
+                modified,
+            }),
+            Err(e) => log::warn!("Could not measure {}: {}", path.to_string_lossy(), e),
+        }
+    }
+    entries
+}
+
+/// Entries older than `max_age`, then the oldest ones until the remaining ones fit in `max_size`
+fn select_for_removal(
+    mut entries: Vec<CacheEntry>,
+    now: SystemTime,
+    max_age: Duration,
+    max_size: Option<u64>,
+) -> (Vec<CacheEntry>, Vec<CacheEntry>) {
+    entries.sort_by_key(|e| e.modified);
+    let (mut removed, mut kept): (Vec<CacheEntry>, Vec<CacheEntry>) = entries
+        .into_iter()
+        .partition(|e| now.duration_since(e.modified).unwrap_or_default() > max_age);
+    if let Some(max_size) = max_size {
+        let mut total: u64 = kept.iter().map(|e| e.size).sum();
+        while total > max_size && !kept.is_empty() {
+            let entry = kept.remove(0);
+            total -= entry.size;
+            removed.push(entry);
+        }
+    }
+    (removed, kept)
+}
+
+pub async fn prune_cache(
+    options: Box<Options>,
+    _working_directory: PathBuf,
+) -> anyhow::Result<PruneCacheResult> {
+    let cargo_home = match options.cargo_home {
+        Some(c) => c,
+        None => PathBuf::from(std::env::var("HOME")?).join(".cargo"),
+    };
+    let mut entries = vec![];
+    entries.extend(collect_entries(
+        &cargo_home.join("registry").join("cache"),
+        2,
+    ));
+    entries.extend(collect_entries(&cargo_home.join("registry").join("src"), 2));
+    entries.extend(collect_entries(
+        &cargo_home.join("git").join("checkouts"),
+        2,
+    ));
+    entries.extend(collect_entries(&cargo_home.join("git").join("db"), 1));
+    for target_dir in &options.target_dir {
+        // <target>/<profile>/{build,deps,incremental}/<entry>
+        for profile in collect_entries(target_dir, 1) {
+            for kind in ["build", "deps", "incremental"] {
+                entries.extend(collect_entries(&profile.path.join(kind), 1));
+            }
+        }
+    }
+    let (removed, kept) = select_for_removal(
+        entries,
+        SystemTime::now(),
+        Duration::from_secs(options.max_age_days * 24 * 60 * 60),
+        options.max_size_mb.map(|m| m * 1024 * 1024),
+    );
+    if !options.dry_run {
+        for entry in &removed {
+            let result = match entry.path.is_dir() {
+                true => fs::remove_dir_all(&entry.path),
+                false => fs::remove_file(&entry.path),
+            };
+            if let Err(e) = result {
+                log::warn!("Could not remove {}: {}", entry.path.to_string_lossy(), e);
+            }
+        }
+    }
+    Ok(PruneCacheResult {
+        reclaimed_bytes: removed.iter().map(|e| e.size).sum(),
+        remaining_bytes: kept.iter().map(|e| e.size).sum(),
+        removed,
+        dry_run: options.dry_run,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, size: u64, age_days: u64, now: SystemTime) -> CacheEntry {
+        CacheEntry {
+            path: PathBuf::from(name),
+            size,
+            modified: now - Duration::from_secs(age_days * 24 * 60 * 60),
+        }
+    }
+
+    #[test]
+    fn test_select_for_removal() {
+        let now = SystemTime::now();
+        let entries = vec![
+            entry("old", 10, 40, now),
+            entry("older_recent", 20, 5, now),
+            entry("recent", 30, 1, now),
+        ];
+        let max_age = Duration::from_secs(30 * 24 * 60 * 60);
+        let (removed, kept) = select_for_removal(entries.clone(), now, max_age, None);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(kept.len(), 2);
+        let (removed, kept) = select_for_removal(entries, now, max_age, Some(40));
+        let removed: Vec<PathBuf> = removed.into_iter().map(|e| e.path).collect();
+        assert_eq!(
+            removed,
+            vec![PathBuf::from("old"), PathBuf::from("older_recent")]
+        );
+        assert_eq!(kept.len(), 1);
+    }
+}
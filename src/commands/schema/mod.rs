@@ -0,0 +1,113 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+use clap::Parser;
+use schemars::{schema_for, JsonSchema};
+
+use crate::commands::bump_dependents::BumpDependentsResult;
+use crate::commands::check_workspace::Results as CheckWorkspaceResult;
+use crate::commands::codeowners::CodeownersResult;
+use crate::commands::dependency_report::DependencyReportResult;
+use crate::commands::docs_index::DocsIndexResult;
+use crate::commands::generate_dependabot::GenerateDependabotResult;
+use crate::commands::generate_workflow::GenerateResult;
+use crate::commands::lint_workspace::LintWorkspaceResult;
+use crate::commands::login::LoginResult;
+use crate::commands::man::ManResult;
+use crate::commands::migrate_workspace_dependencies::MigrateWorkspaceDependenciesResult;
+use crate::commands::new::NewResult;
+use crate::commands::preview_update::PreviewUpdateResult;
+use crate::commands::prune_cache::PruneCacheResult;
+use crate::commands::summaries::SummariesResult;
+use crate::commands::warm_cache::WarmCacheResult;
+use crate::utils::VersionedOutput;
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum OutputCommand {
+    CheckWorkspace,
+    GenerateReleaseWorkflow,
+    Summaries,
+    Login,
+    DocsIndex,
+    PruneCache,
+    LintWorkspace,
+    DependencyReport,
+    MigrateWorkspaceDependencies,
+    New,
+    GenerateDependabot,
+    Codeowners,
+    WarmCache,
+    BumpDependents,
+    PreviewUpdate,
+    Man,
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Print the JSON Schema of the `--json` output of a command.")]
+pub struct Options {
+    #[arg(value_enum)]
+    command: OutputCommand,
+}
+
+pub struct SchemaResult(serde_json::Value);
+
+impl Display for SchemaResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            serde_json::to_string_pretty(&self.0).map_err(|_| std::fmt::Error)?
+        )
+    }
+}
+
+fn output_schema<T: JsonSchema>() -> anyhow::Result<serde_json::Value> {
+    Ok(serde_json::to_value(schema_for!(VersionedOutput<T>))?)
+}
+
+pub async fn schema(
+    options: Box<Options>,
+    _working_directory: PathBuf,
+) -> anyhow::Result<SchemaResult> {
+    let schema = match options.command {
+        OutputCommand::CheckWorkspace => output_schema::<CheckWorkspaceResult>(),
+        OutputCommand::GenerateReleaseWorkflow => output_schema::<GenerateResult>(),
+        OutputCommand::Summaries => output_schema::<SummariesResult>(),
+        OutputCommand::Login => output_schema::<LoginResult>(),
+        OutputCommand::DocsIndex => output_schema::<DocsIndexResult>(),
+        OutputCommand::PruneCache => output_schema::<PruneCacheResult>(),
+        OutputCommand::LintWorkspace => output_schema::<LintWorkspaceResult>(),
+        OutputCommand::DependencyReport => output_schema::<DependencyReportResult>(),
+        OutputCommand::MigrateWorkspaceDependencies => {
+            output_schema::<MigrateWorkspaceDependenciesResult>()
+        }
+        OutputCommand::New => output_schema::<NewResult>(),
+        OutputCommand::GenerateDependabot => output_schema::<GenerateDependabotResult>(),
+        OutputCommand::Codeowners => output_schema::<CodeownersResult>(),
+        OutputCommand::WarmCache => output_schema::<WarmCacheResult>(),
+        OutputCommand::BumpDependents => output_schema::<BumpDependentsResult>(),
+        OutputCommand::PreviewUpdate => output_schema::<PreviewUpdateResult>(),
+        OutputCommand::Man => output_schema::<ManResult>(),
+    }?;
+    Ok(SchemaResult(schema))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_schema() {
+        let schema = output_schema::<CheckWorkspaceResult>().expect("Could not build schema");
+        assert_eq!(
+            schema["properties"]["schema_version"]["type"],
+            serde_json::json!("integer")
+        );
+        assert!(schema["definitions"]["Result"]["properties"]["publish_detail"].is_object());
+        let schema = output_schema::<LintWorkspaceResult>().expect("Could not build schema");
+        assert_eq!(
+            schema["required"],
+            serde_json::json!(["schema_version", "violations"])
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use octocrab::Octocrab;
+
+/// Code forge hosting the change request the summary is commented on
+pub trait Forge {
+    /// Delete the comments previously posted by the current user
+    async fn hide_previous_comments(&self) -> anyhow::Result<()>;
+    /// Post the comments, in order
+    async fn comment(&self, comments: Vec<String>) -> anyhow::Result<()>;
+}
+
+/// Comment the summary, split in `comments`, replacing the previous ones with `hide_previous`
+pub async fn post_summary(
+    forge: &impl Forge,
+    comments: Vec<String>,
+    hide_previous: bool,
+) -> anyhow::Result<()> {
+    if hide_previous {
+        if let Err(e) = forge.hide_previous_comments().await {
+            log::warn!("Could not hide previous comments: {}", e);
+        }
+    }
+    forge.comment(comments).await
+}
+
+/// Pull request of a GitHub repository
+pub struct Github {
+    octocrab: Octocrab,
+    owner: String,
+    repo: String,
+    issue_number: u64,
+}
+
+impl Github {
+    pub fn new(octocrab: Octocrab, owner: String, repo: String, issue_number: u64) -> Self {
+        Self {
+            octocrab,
+            owner,
+            repo,
+            issue_number,
+        }
+    }
+}
+
+impl Forge for Github {
+    async fn hide_previous_comments(&self) -> anyhow::Result<()> {
+        let user = self
+            .octocrab
+            .current()
+            .user()
+            .await
+            .map(|u| u.login)
+            .unwrap_or_else(|_| "fmsc-bot[bot]".to_string());
+        let issues = self.octocrab.issues(&self.owner, &self.repo);
+        let first_page = issues
+            .list_comments(self.issue_number)
+            .per_page(100)
+            .send()
+            .await?;
+        for comment in self.octocrab.all_pages(first_page).await? {
+            if comment.user.login != user {
+                continue;
+            }
+            if let Err(e) = issues.delete_comment(comment.id).await {
+                log::warn!("Could not delete comment: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn comment(&self, comments: Vec<String>) -> anyhow::Result<()> {
+        let issues = self.octocrab.issues(&self.owner, &self.repo);
+        for comment in comments {
+            issues.create_comment(self.issue_number, comment).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{Method, Request};
+use hyper_util::client::legacy::Client as HyperClient;
+use hyper_util::rt::TokioExecutor;
+use serde::Deserialize;
+
+use crate::commands::summaries::forge::Forge;
+use crate::http_client::{https_connector, HttpsClientConnector};
+
+#[derive(Deserialize)]
+struct GitlabUser {
+    username: String,
+}
+
+#[derive(Deserialize)]
+struct GitlabNote {
+    id: u64,
+    author: GitlabUser,
+}
+
+/// Merge request of a GitLab project
+pub struct Gitlab {
+    client: HyperClient<HttpsClientConnector, Full<Bytes>>,
+    api_url: String,
+    token: String,
+    /// Notes endpoint of the merge request
+    notes: String,
+}
+
+impl Gitlab {
+    /// Client for the merge request `iid` of `project`, e.g. `group/project`
+    pub fn new(api_url: String, token: String, project: &str, iid: u64) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: HyperClient::builder(TokioExecutor::new()).build(https_connector()?),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            token,
+            notes: format!(
+                "/projects/{}/merge_requests/{}/notes",
+                url::form_urlencoded::byte_serialize(project.as_bytes()).collect::<String>(),
+                iid
+            ),
+        })
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: String,
+        body: Option<String>,
+    ) -> anyhow::Result<Bytes> {
+        let url = format!("{}{}", self.api_url, path);
+        let req = Request::builder()
+            .method(method.clone())
+            .uri(url.clone())
+            .header("PRIVATE-TOKEN", &self.token)
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json")
+            .body(Full::new(Bytes::from(body.unwrap_or_default())))?;
+        let res = self.client.request(req).await?;
+        if res.status().as_u16() >= 400 {
+            anyhow::bail!("GitLab answered {} to {} {}", res.status(), method, url);
+        }
+        Ok(res.into_body().collect().await?.to_bytes())
+    }
+
+    /// Every note of the merge request, going through all the pages
+    async fn list_notes(&self) -> anyhow::Result<Vec<GitlabNote>> {
+        let mut notes = vec![];
+        for page in 1.. {
+            let page: Vec<GitlabNote> = serde_json::from_slice(
+                &self
+                    .request(
+                        Method::GET,
+                        format!("{}?per_page=100&page={}", self.notes, page),
+                        None,
+                    )
+                    .await?,
+            )?;
+            if page.is_empty() {
+                break;
+            }
+            notes.extend(page);
+        }
+        Ok(notes)
+    }
+}
+
+impl Forge for Gitlab {
+    async fn hide_previous_comments(&self) -> anyhow::Result<()> {
+        let user: GitlabUser =
+            serde_json::from_slice(&self.request(Method::GET, "/user".into(), None).await?)?;
+        for note in self.list_notes().await? {
+            if note.author.username != user.username {
+                continue;
+            }
+            if let Err(e) = self
+                .request(Method::DELETE, format!("{}/{}", self.notes, note.id), None)
+                .await
+            {
+                log::warn!("Could not delete comment: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn comment(&self, comments: Vec<String>) -> anyhow::Result<()> {
+        for comment in comments {
+            self.request(
+                Method::POST,
+                self.notes.clone(),
+                Some(serde_json::json!({ "body": comment }).to_string()),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::{body_json, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::commands::summaries::forge::post_summary;
+
+    #[tokio::test]
+    async fn test_comment_merge_request() {
+        let server = MockServer::start().await;
+        let notes = "/projects/group%2Fproject/merge_requests/12/notes";
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"username": "bot"})),
+            )
+            .mount(&server)
+            .await;
+        let pages = [
+            serde_json::json!([
+                {"id": 1, "author": {"username": "bot"}},
+                {"id": 2, "author": {"username": "someone"}}
+            ]),
+            serde_json::json!([{"id": 3, "author": {"username": "bot"}}]),
+            serde_json::json!([]),
+        ];
+        for (page, notes_page) in pages.into_iter().enumerate() {
+            Mock::given(method("GET"))
+                .and(path(notes))
+                .and(query_param("page", (page + 1).to_string()))
+                .respond_with(ResponseTemplate::new(200).set_body_json(notes_page))
+                .mount(&server)
+                .await;
+        }
+        for id in [1, 3] {
+            Mock::given(method("DELETE"))
+                .and(path(format!("{}/{}", notes, id)))
+                .respond_with(ResponseTemplate::new(204))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("POST"))
+            .and(path(notes))
+            .and(header("PRIVATE-TOKEN", "token"))
+            .and(body_json(serde_json::json!({"body": "All good"})))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let gitlab = Gitlab::new(server.uri(), "token".to_string(), "group/project", 12)
+            .expect("Could not build client");
+        post_summary(&gitlab, vec!["All good".to_string()], true)
+            .await
+            .expect("Could not comment");
+    }
+}
@@ -3,35 +3,67 @@ use std::collections::HashMap;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::fs;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use clap::Parser;
 use http_body_util::BodyExt;
 use http_body_util::Empty;
 use hyper::body::Bytes;
+use hyper::header::{HeaderValue, AUTHORIZATION, USER_AGENT};
 use hyper::{Method, Request};
-use hyper_rustls::ConfigBuilderExt;
-use hyper_rustls::HttpsConnector;
-use hyper_util::client::legacy::connect::HttpConnector;
 use hyper_util::client::legacy::Client as HyperClient;
 use hyper_util::rt::TokioExecutor;
 use num::integer::lcm;
-use octocrab::Octocrab;
+use octocrab::service::middleware::base_uri::BaseUriLayer;
+use octocrab::service::middleware::extra_headers::ExtraHeadersLayer;
+use octocrab::{AuthState, Octocrab, OctocrabBuilder};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use template::Summary;
 
+use crate::commands::summaries::forge::{post_summary, Github};
+use crate::commands::summaries::gitlab::Gitlab;
+use crate::commands::summaries::publish_failure::PublishFailure;
 use crate::commands::summaries::template::SummaryTableCell;
+use crate::http_client::{https_connector, HttpsClientConnector};
 
+mod forge;
+mod gitlab;
+mod publish_failure;
 mod template;
 
 static GH_MAX_COMMENT_LENGTH: usize = 65536;
 
+/// GitHub client going through [`https_connector`], so it honors `--ca-bundle` and the proxy
+fn github_client(api_url: &str, token: &str) -> anyhow::Result<Octocrab> {
+    let client = HyperClient::builder(TokioExecutor::new()).build::<_, String>(https_connector()?);
+    let headers = vec![
+        (USER_AGENT, HeaderValue::from_static("fslabscli")),
+        (
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token))?,
+        ),
+    ];
+    Ok(OctocrabBuilder::new_empty()
+        .with_service(client)
+        .with_layer(&BaseUriLayer::new(api_url.parse()?))
+        .with_layer(&ExtraHeadersLayer::new(Arc::new(headers)))
+        .with_auth(AuthState::None)
+        .build()?)
+}
+
 #[derive(Debug, Parser)]
 #[command(about = "Generate summary of github run.")]
 pub struct Options {
     #[arg(long, default_value_t, value_enum)]
     run_type: RunType,
+    /// Write the markdown summary to this file
     #[arg(long, env = "GITHUB_STEP_SUMMARY")]
-    output: PathBuf,
+    output: Option<PathBuf>,
+    /// Also append the markdown summary to the file pointed to by `GITHUB_STEP_SUMMARY`, when set
+    /// and not already written through `--output`
+    #[arg(long, default_value_t = false)]
+    github_step_summary: bool,
     #[arg(long)]
     github_token: Option<String>,
     #[arg(long)]
@@ -40,12 +72,38 @@ pub struct Options {
     github_issue_number: Option<u64>,
     #[arg(long)]
     github_repo: Option<String>,
+    /// API of the GitHub instance hosting the repository, for GitHub Enterprise Server
+    #[arg(long, env = "GITHUB_API_URL", default_value = "https://api.github.com")]
+    github_api_url: String,
+    /// Where the pull or merge request to comment is hosted
+    #[arg(long, default_value_t, value_enum)]
+    forge: ForgeKind,
+    #[arg(long, env = "GITLAB_TOKEN")]
+    gitlab_token: Option<String>,
+    #[arg(
+        long,
+        env = "CI_API_V4_URL",
+        default_value = "https://gitlab.com/api/v4"
+    )]
+    gitlab_api_url: String,
+    /// Path of the project, e.g. `group/project`
+    #[arg(long, env = "CI_PROJECT_PATH")]
+    gitlab_project: Option<String>,
+    #[arg(long, env = "CI_MERGE_REQUEST_IID")]
+    gitlab_merge_request_iid: Option<u64>,
     #[arg(long, default_value_t = false)]
     hide_previous_pr_comment: bool,
     #[arg(long, default_value = "https://ci.fslabs.ca")]
     mining_bot_url: String,
 }
 
+#[derive(clap::ValueEnum, Clone, Default, Debug, Serialize)]
+enum ForgeKind {
+    #[default]
+    Github,
+    Gitlab,
+}
+
 #[derive(clap::ValueEnum, Clone, Default, Debug, Serialize)]
 enum RunType {
     #[default]
@@ -53,12 +111,14 @@ enum RunType {
     Publishing,
 }
 
-#[derive(Serialize)]
-pub struct SummariesResult {}
+#[derive(Serialize, JsonSchema, Default)]
+pub struct SummariesResult {
+    markdown: String,
+}
 
 impl Display for SummariesResult {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "")
+        write!(f, "{}", self.markdown)
     }
 }
 
@@ -124,6 +184,9 @@ pub struct CheckOutput {
     pub required: bool,
     pub number: Option<usize>,
     pub log_url: Option<String>,
+    /// Output of the check, shown in the summary when it failed
+    #[serde(default)]
+    pub output: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -222,7 +285,7 @@ pub struct CheckedOutput {
 }
 
 async fn get_workflow_info(
-    client: &HyperClient<HttpsConnector<HttpConnector>, Empty<Bytes>>,
+    client: &HyperClient<HttpsClientConnector, Empty<Bytes>>,
     url: String,
 ) -> anyhow::Result<WorkflowJobInstance> {
     let req = Request::builder()
@@ -277,7 +340,7 @@ pub async fn checks_summaries(
     }
 
     // For each package we need to check if the checks wer a success, and for each check type, generate a report
-    let mut summary = Summary::new(options.output);
+    let mut summary = Summary::new();
     let mut overall_success = true;
     let mut failed = 0;
     let mut failed_o = 0;
@@ -285,15 +348,7 @@ pub async fn checks_summaries(
     let mut cancelled = 0;
     let mut succeeded = 0;
 
-    let https = hyper_rustls::HttpsConnectorBuilder::new()
-        .with_tls_config(
-            rustls::ClientConfig::builder()
-                .with_native_roots()?
-                .with_no_client_auth(),
-        )
-        .https_or_http()
-        .enable_http1()
-        .build();
+    let https = https_connector()?;
     let client = HyperClient::builder(TokioExecutor::new()).build(https);
     for (package, checks) in checks_map {
         let mut success = true;
@@ -433,6 +488,31 @@ pub async fn checks_summaries(
             row.push(SummaryTableCell::new(imgs.join(""), 1));
             rows.push(row);
         }
+        // Inline the output of the failed checks so they can be read without opening the logs
+        let failed_outputs: Vec<String> = check_outputs
+            .iter()
+            .flat_map(|checked| {
+                checked.sub_checks.iter().filter_map(|(name, subcheck)| {
+                    match (subcheck.outcome, subcheck.output.clone()) {
+                        (CheckOutcome::Failure, Some(output)) => {
+                            // Name the cause of a failed publish instead of leaving it to the log
+                            let cause = match name.as_str() {
+                                "publish_dryrun" => PublishFailure::classify(&output)
+                                    .map(|f| format!(": {}", f))
+                                    .unwrap_or_default(),
+                                _ => "".to_string(),
+                            };
+                            Some(summary.detail(
+                                format!("{} / {} output{}", checked.check_name, name, cause),
+                                summary.code_block(output, None),
+                                false,
+                            ))
+                        }
+                        _ => None,
+                    }
+                })
+            })
+            .collect();
 
         summary.add_content(
             summary.detail(
@@ -440,7 +520,7 @@ pub async fn checks_summaries(
                     format!("{} - {}", package, get_success_emoji(success)),
                     Some(2),
                 ),
-                summary.table(rows),
+                format!("{}\n{}", summary.table(rows), failed_outputs.join("\n")),
                 !success,
             ),
             true,
@@ -470,72 +550,69 @@ pub async fn checks_summaries(
         options.mining_bot_url, succeeded, failed, failed_o, skipped, cancelled
     );
     summary.prepend_content(format!("![{}]({})", messages.join(", "), icon_svg), true);
-    summary.write(true).await?;
-    if let (
-        Some(github_token),
-        Some(github_event_name),
-        Some(github_issue_number),
-        Some(github_repo),
-    ) = (
-        options.github_token,
-        options.github_event_name,
-        options.github_issue_number,
-        options.github_repo,
-    ) {
-        if github_event_name == "pull_request" || github_event_name == "pull_request_target" {
-            // We have a github token we should try to update the pr
-            let octocrab = Octocrab::builder().personal_token(github_token).build()?;
-            if let Some((owner, repo)) = github_repo.split_once('/') {
-                let issues_client = octocrab.issues(owner, repo);
-                let output = summary.get_content();
-                if options.hide_previous_pr_comment {
-                    // Hide previsous
-                    let user = octocrab
-                        .current()
-                        .user()
-                        .await
-                        .map(|u| u.login)
-                        .unwrap_or_else(|_| "fmsc-bot[bot]".to_string());
-                    if let Ok(existing_comments) = issues_client
-                        .list_comments(github_issue_number)
-                        .send()
-                        .await
-                        .map_err(|e| {
-                            println!("Could not list comments: {:?}", e);
-                            e
-                        })
-                    {
-                        for existing_comment in existing_comments {
-                            if existing_comment.user.login != user {
-                                continue;
-                            }
-                            // Delete all of our comments? Maybe we nmeed to be more clever
-                            let _ = issues_client
-                                .delete_comment(existing_comment.id)
-                                .await
-                                .map_err(|e| {
-                                    println!("Could not delete comment: {:?}", e);
-                                    e
-                                });
-                        }
+    if let Some(output) = &options.output {
+        summary.write(output, true).await?;
+    }
+    if options.github_step_summary {
+        match std::env::var_os("GITHUB_STEP_SUMMARY").map(PathBuf::from) {
+            Some(step_summary) if options.output.as_ref() == Some(&step_summary) => {}
+            Some(step_summary) => summary.write(&step_summary, false).await?,
+            None => log::warn!("GITHUB_STEP_SUMMARY is not set, not writing the step summary"),
+        }
+    }
+    let comments = split_comments(summary.get_content());
+    let posted = match options.forge {
+        ForgeKind::Github => match (
+            options.github_token,
+            options.github_event_name,
+            options.github_issue_number,
+            options.github_repo,
+        ) {
+            (
+                Some(github_token),
+                Some(github_event_name),
+                Some(issue_number),
+                Some(github_repo),
+            ) if github_event_name == "pull_request"
+                || github_event_name == "pull_request_target" =>
+            {
+                match github_repo.split_once('/') {
+                    Some((owner, repo)) => {
+                        let github = Github::new(
+                            github_client(&options.github_api_url, &github_token)?,
+                            owner.to_string(),
+                            repo.to_string(),
+                            issue_number,
+                        );
+                        Some(
+                            post_summary(&github, comments, options.hide_previous_pr_comment).await,
+                        )
                     }
-                }
-                let comments = split_comments(output);
-                for comment in comments {
-                    let _ = issues_client
-                        .create_comment(github_issue_number, comment)
-                        .await
-                        .map_err(|e| {
-                            println!("Could not create comment: {:?}", e);
-                            e
-                        });
+                    None => None,
                 }
             }
-        }
+            _ => None,
+        },
+        ForgeKind::Gitlab => match (
+            options.gitlab_token,
+            options.gitlab_project,
+            options.gitlab_merge_request_iid,
+        ) {
+            (Some(token), Some(project), Some(iid)) => {
+                let gitlab = Gitlab::new(options.gitlab_api_url, token, &project, iid)?;
+                Some(post_summary(&gitlab, comments, options.hide_previous_pr_comment).await)
+            }
+            _ => None,
+        },
+    };
+    if let Some(Err(e)) = posted {
+        log::warn!("Could not create comment: {:?}", e);
     }
 
     match overall_success {
-        true => Ok(SummariesResult {}),
+        true => Ok(SummariesResult {
+            markdown: summary.get_content(),
+        }),
         false => anyhow::bail!("Required test failed"),
     }
 }
@@ -569,7 +646,7 @@ pub async fn publishing_summaries(
     _options: Box<Options>,
     _summaries_directory: PathBuf,
 ) -> anyhow::Result<SummariesResult> {
-    Ok(SummariesResult {})
+    Ok(SummariesResult::default())
 }
 
 pub async fn summaries(
@@ -0,0 +1,88 @@
+use std::fmt::{Display, Formatter};
+
+/// Well-known reasons for `cargo publish` to fail, so a summary can tell them apart at a glance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PublishFailure {
+    AlreadyPublished,
+    MissingField,
+    RateLimited,
+    Unauthorized,
+}
+
+impl PublishFailure {
+    /// Failure class of the `cargo publish` output, if it is a known one
+    pub fn classify(output: &str) -> Option<Self> {
+        let output = output.to_lowercase();
+        let matches = |patterns: &[&str]| patterns.iter().any(|p| output.contains(p));
+        if matches(&["already uploaded", "already exists"]) {
+            Some(Self::AlreadyPublished)
+        } else if matches(&["missing or empty metadata fields", "missing field"]) {
+            Some(Self::MissingField)
+        } else if matches(&["got 429", "too many requests", "published too many"]) {
+            Some(Self::RateLimited)
+        } else if matches(&[
+            "got 401",
+            "got 403",
+            "unauthorized",
+            "forbidden",
+            "no token found",
+            "cargo login",
+            "invalid token",
+        ]) {
+            Some(Self::Unauthorized)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for PublishFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AlreadyPublished => write!(f, "already published"),
+            Self::MissingField => write!(f, "missing manifest field"),
+            Self::RateLimited => write!(f, "rate limited"),
+            Self::Unauthorized => write!(f, "authentication failure"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let already_published = indoc! {"
+            Uploading hub-core v0.3.1 (/work/libs/core)
+            error: failed to publish to registry at https://crates.io
+
+            Caused by:
+              the remote server responded with an error: crate version `0.3.1` is already uploaded
+        "};
+        assert_eq!(
+            PublishFailure::classify(already_published),
+            Some(PublishFailure::AlreadyPublished)
+        );
+        assert_eq!(
+            PublishFailure::classify(
+                "error: failed to publish: missing or empty metadata fields: description, license"
+            ),
+            Some(PublishFailure::MissingField)
+        );
+        assert_eq!(
+            PublishFailure::classify("failed to get a 200 OK response, got 429 Too Many Requests"),
+            Some(PublishFailure::RateLimited)
+        );
+        assert_eq!(
+            PublishFailure::classify("error: no token found for `foresight`, please run `cargo login --registry foresight`"),
+            Some(PublishFailure::Unauthorized)
+        );
+        assert_eq!(
+            PublishFailure::classify("error: could not compile `hub-core`"),
+            None
+        );
+    }
+}
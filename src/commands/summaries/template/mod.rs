@@ -1,5 +1,5 @@
 use std::io::prelude::*;
-use std::{collections::HashMap, fs::OpenOptions, path::PathBuf};
+use std::{collections::HashMap, fs::OpenOptions, path::Path};
 
 pub struct SummaryTableCell {
     pub header: bool,
@@ -29,15 +29,13 @@ impl SummaryTableCell {
 
 pub struct Summary {
     pub buffer: String,
-    pub file_path: PathBuf,
 }
 
 #[allow(dead_code)]
 impl Summary {
-    pub fn new(file_path: PathBuf) -> Self {
+    pub fn new() -> Self {
         Self {
             buffer: String::new(),
-            file_path,
         }
     }
 
@@ -75,21 +73,22 @@ impl Summary {
         self.buffer.clone()
     }
 
-    pub async fn write(&self, overwrite: bool) -> anyhow::Result<()> {
+    pub async fn write(&self, file_path: &Path, overwrite: bool) -> anyhow::Result<()> {
         let mut options = OpenOptions::new();
+        options.create(true);
         if overwrite {
             options.write(true).truncate(true);
         } else {
             options.append(true);
         }
-        let mut file = options.open(self.file_path.as_path())?;
+        let mut file = options.open(file_path)?;
         file.write_all(self.buffer.as_bytes())?;
         Ok(())
     }
 
-    pub async fn clear(&mut self) -> anyhow::Result<()> {
+    pub async fn clear(&mut self, file_path: &Path) -> anyhow::Result<()> {
         self.buffer = "".to_string();
-        self.write(true).await
+        self.write(file_path, true).await
     }
 
     pub fn add_content(&mut self, content: String, add_eol: bool) {
@@ -0,0 +1,127 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::Context;
+use clap::Parser;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::commands::check_workspace::{
+    base_images, check_workspace_or_load, Options as CheckWorkspaceOptions,
+};
+use crate::utils;
+
+#[derive(Debug, Parser)]
+#[command(
+    about = "Pre-pull the docker base images and build caches of the workspace members, to keep runner caches hot."
+)]
+pub struct Options {
+    /// Also run `cargo fetch` in every workspace
+    #[arg(long, default_value_t = false)]
+    cargo_fetch: bool,
+    /// `check-workspace --json` output to reuse instead of checking the workspace again, `-` for stdin
+    #[arg(long)]
+    workspace_json: Option<PathBuf>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct WarmCacheResult {
+    pulled: Vec<String>,
+    /// `publish.docker.cache_from` build caches imported into the local builder
+    warmed: Vec<String>,
+    failed: Vec<String>,
+    fetched: Vec<PathBuf>,
+}
+
+impl Display for WarmCacheResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for image in &self.failed {
+            writeln!(f, "Could not pull {}", image)?;
+        }
+        write!(
+            f,
+            "Pulled {} images, warmed {} build caches, fetched {} workspaces",
+            self.pulled.len(),
+            self.warmed.len(),
+            self.fetched.len()
+        )
+    }
+}
+
+pub async fn warm_cache(
+    options: Box<Options>,
+    working_directory: PathBuf,
+) -> anyhow::Result<WarmCacheResult> {
+    let members = check_workspace_or_load(
+        options.workspace_json.as_deref(),
+        CheckWorkspaceOptions::new(),
+        working_directory.clone(),
+    )
+    .await?;
+    let mut images: BTreeSet<String> = BTreeSet::new();
+    let mut caches: Vec<(String, PathBuf, PathBuf)> = vec![];
+    for member in members.0.values() {
+        let docker = &member.publish_detail.docker;
+        if !docker.publish {
+            continue;
+        }
+        let package_path = working_directory.join(&member.path);
+        let (dockerfile, context) = member.publish_detail.docker_build_paths();
+        let dockerfile = package_path.join(dockerfile);
+        match fs::read_to_string(&dockerfile) {
+            Ok(content) => images.extend(base_images(&content)),
+            Err(e) => log::warn!("Could not read {}: {}", dockerfile.to_string_lossy(), e),
+        }
+        if let Some(cache_from) = &docker.cache_from {
+            caches.push((cache_from.clone(), dockerfile, package_path.join(context)));
+        }
+    }
+    let mut pulled = vec![];
+    let mut failed = vec![];
+    for image in images {
+        let status = Command::new("docker").args(["pull", &image]).status();
+        match status {
+            Ok(s) if s.success() => pulled.push(image),
+            _ => failed.push(image),
+        }
+    }
+    // Only import the cache the publish workflow builds from, without producing an image
+    let mut warmed = vec![];
+    for (cache_from, dockerfile, context) in caches {
+        let status = Command::new("docker")
+            .args(["buildx", "build", "--cache-from", &cache_from])
+            .args(["--output", "type=cacheonly", "--file"])
+            .arg(&dockerfile)
+            .arg(&context)
+            .status();
+        match status {
+            Ok(s) if s.success() => warmed.push(cache_from),
+            _ => failed.push(cache_from),
+        }
+    }
+    let mut fetched = vec![];
+    if options.cargo_fetch {
+        for root in utils::get_cargo_roots(working_directory.clone())
+            .with_context(|| format!("Failed to get roots from {:?}", working_directory))?
+        {
+            let status = Command::new("cargo")
+                .arg("fetch")
+                .current_dir(&root)
+                .status()?;
+            if status.success() {
+                fetched.push(root);
+            } else {
+                log::warn!("Could not fetch {}", root.to_string_lossy());
+            }
+        }
+    }
+    Ok(WarmCacheResult {
+        pulled,
+        warmed,
+        failed,
+        fetched,
+    })
+}
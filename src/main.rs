@@ -1,18 +1,41 @@
 use std::fmt::Display;
 use std::path::PathBuf;
 
-use clap::{ArgAction, Parser, Subcommand};
+use clap::{ArgAction, CommandFactory, Parser, Subcommand};
 use log::LevelFilter;
-use log4rs::append::console::ConsoleAppender;
+use log4rs::append::console::{ConsoleAppender, Target};
 use log4rs::config::{Appender, Root};
 use log4rs::encode::pattern::PatternEncoder;
 use serde::Serialize;
 
-use crate::commands::check_workspace::{check_workspace, Options as CheckWorkspaceOptions};
+use crate::commands::bump_dependents::{bump_dependents, Options as BumpDependentsOptions};
+use crate::commands::check_workspace::{
+    check_workspace, list_member_names, Options as CheckWorkspaceOptions,
+};
+use crate::commands::codeowners::{codeowners, Options as CodeownersOptions};
+use crate::commands::completions::{completions, Options as CompletionsOptions};
+use crate::commands::dependency_report::{dependency_report, Options as DependencyReportOptions};
+use crate::commands::docs_index::{docs_index, Options as DocsIndexOptions};
+use crate::commands::external::run_external;
+use crate::commands::generate_dependabot::{
+    generate_dependabot, Options as GenerateDependabotOptions,
+};
 use crate::commands::generate_workflow::{generate_workflow, Options as GenerateWorkflowOptions};
+use crate::commands::lint_workspace::{lint_workspace, Options as LintWorkspaceOptions};
+use crate::commands::login::{login, Options as LoginOptions};
+use crate::commands::man::{man, Options as ManOptions};
+use crate::commands::migrate_workspace_dependencies::{
+    migrate_workspace_dependencies, Options as MigrateWorkspaceDependenciesOptions,
+};
+use crate::commands::new::{new_crate, Options as NewOptions};
+use crate::commands::preview_update::{preview_update, Options as PreviewUpdateOptions};
+use crate::commands::prune_cache::{prune_cache, Options as PruneCacheOptions};
+use crate::commands::schema::{schema, Options as SchemaOptions};
 use crate::commands::summaries::{summaries, Options as SummariesOptions};
+use crate::commands::warm_cache::{warm_cache, Options as WarmCacheOptions};
 
 mod commands;
+mod http_client;
 mod utils;
 
 #[derive(Debug, Parser)] // requires `derive` feature
@@ -32,6 +55,9 @@ struct Cli {
     json: bool,
     #[arg(short, long, global = true, default_value = ".", required = false)]
     working_directory: PathBuf,
+    /// PEM certificates to trust on top of the native ones when reaching registries
+    #[arg(long, global = true, env = "FSLABSCLI_CA_BUNDLE")]
+    ca_bundle: Option<PathBuf>,
     #[arg(hide = true, default_value = "fslabscli")]
     cargo_subcommand: CargoSubcommand,
     #[command(subcommand)]
@@ -50,6 +76,24 @@ enum Commands {
     CheckWorkspace(Box<CheckWorkspaceOptions>),
     GenerateReleaseWorkflow(Box<GenerateWorkflowOptions>),
     Summaries(Box<SummariesOptions>),
+    Login(Box<LoginOptions>),
+    DocsIndex(Box<DocsIndexOptions>),
+    PruneCache(Box<PruneCacheOptions>),
+    LintWorkspace(Box<LintWorkspaceOptions>),
+    DependencyReport(Box<DependencyReportOptions>),
+    MigrateWorkspaceDependencies(Box<MigrateWorkspaceDependenciesOptions>),
+    New(Box<NewOptions>),
+    GenerateDependabot(Box<GenerateDependabotOptions>),
+    Codeowners(Box<CodeownersOptions>),
+    WarmCache(Box<WarmCacheOptions>),
+    BumpDependents(Box<BumpDependentsOptions>),
+    PreviewUpdate(Box<PreviewUpdateOptions>),
+    Schema(Box<SchemaOptions>),
+    Completions(Box<CompletionsOptions>),
+    Man(Box<ManOptions>),
+    /// Unknown subcommands run the `fslabscli-<name>` binary found on PATH
+    #[command(external_subcommand)]
+    External(Vec<String>),
 }
 
 pub fn setup_logging(verbosity: u8) {
@@ -61,15 +105,16 @@ pub fn setup_logging(verbosity: u8) {
         4.. => LevelFilter::Trace,
     };
 
-    // Encoders
-    let stdout: ConsoleAppender = ConsoleAppender::builder()
+    // Encoders, logging to stderr as stdout carries the results or the `--quiet` NDJSON events
+    let stderr: ConsoleAppender = ConsoleAppender::builder()
+        .target(Target::Stderr)
         .encoder(Box::new(PatternEncoder::new(
             "{h({d(%Y-%m-%d %H:%M:%S)(utc)} - {l}: {m}{n})}",
         )))
         .build();
 
     let log_config = log4rs::config::Config::builder()
-        .appender(Appender::builder().build("stderr", Box::new(stdout)))
+        .appender(Appender::builder().build("stderr", Box::new(stderr)))
         .build(Root::builder().appender("stderr").build(logging_level))
         .unwrap();
     log4rs::init_config(log_config)
@@ -79,7 +124,7 @@ pub fn setup_logging(verbosity: u8) {
 
 fn display_or_json<T: Serialize + Display>(json: bool, results: T) -> String {
     if json {
-        serde_json::to_string(&results).unwrap()
+        serde_json::to_string(&utils::VersionedOutput::new(results)).unwrap()
     } else {
         format!("{}", results)
     }
@@ -87,13 +132,32 @@ fn display_or_json<T: Serialize + Display>(json: bool, results: T) -> String {
 
 #[tokio::main]
 async fn main() {
-    let cli = Cli::parse();
+    // Without the `fslabscli` argument cargo passes, the hidden positional would swallow the
+    // name of external subcommands
+    let mut args: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    if args.get(1).map(|a| a != "fslabscli").unwrap_or(false) {
+        args.insert(1, "fslabscli".into());
+    }
+    let cli = Cli::parse_from(args);
     setup_logging(cli.verbose);
+    if let Some(ca_bundle) = cli.ca_bundle.clone() {
+        http_client::set_ca_bundle(ca_bundle);
+    }
     let working_directory = cli
         .working_directory
         .canonicalize()
         .expect("Could not get full path from working_directory");
+    let mut has_violations = false;
     let result = match cli.command {
+        Commands::CheckWorkspace(options) if options.list_names => {
+            list_member_names(working_directory).map(|names| names.join("\n"))
+        }
+        // In quiet mode stdout only carries the NDJSON events
+        Commands::CheckWorkspace(options) if options.quiet => {
+            check_workspace(options, working_directory)
+                .await
+                .map(|_| String::new())
+        }
         Commands::CheckWorkspace(options) => check_workspace(options, working_directory)
             .await
             .map(|r| display_or_json(cli.json, r)),
@@ -103,10 +167,66 @@ async fn main() {
         Commands::Summaries(options) => summaries(options, working_directory)
             .await
             .map(|r| display_or_json(cli.json, r)),
+        Commands::Login(options) => login(options, working_directory)
+            .await
+            .map(|r| display_or_json(cli.json, r)),
+        Commands::DocsIndex(options) => docs_index(options, working_directory)
+            .await
+            .map(|r| display_or_json(cli.json, r)),
+        Commands::PruneCache(options) => prune_cache(options, working_directory)
+            .await
+            .map(|r| display_or_json(cli.json, r)),
+        Commands::LintWorkspace(options) => {
+            lint_workspace(options, working_directory).await.map(|r| {
+                has_violations = !r.violations.is_empty();
+                display_or_json(cli.json, r)
+            })
+        }
+        Commands::DependencyReport(options) => dependency_report(options, working_directory)
+            .await
+            .map(|r| display_or_json(cli.json, r)),
+        Commands::MigrateWorkspaceDependencies(options) => {
+            migrate_workspace_dependencies(options, working_directory)
+                .await
+                .map(|r| display_or_json(cli.json, r))
+        }
+        Commands::New(options) => new_crate(options, working_directory)
+            .await
+            .map(|r| display_or_json(cli.json, r)),
+        Commands::GenerateDependabot(options) => generate_dependabot(options, working_directory)
+            .await
+            .map(|r| display_or_json(cli.json, r)),
+        Commands::Codeowners(options) => codeowners(options, working_directory)
+            .await
+            .map(|r| display_or_json(cli.json, r)),
+        Commands::WarmCache(options) => warm_cache(options, working_directory)
+            .await
+            .map(|r| display_or_json(cli.json, r)),
+        Commands::BumpDependents(options) => bump_dependents(options, working_directory)
+            .await
+            .map(|r| display_or_json(cli.json, r)),
+        Commands::PreviewUpdate(options) => preview_update(options, working_directory)
+            .await
+            .map(|r| display_or_json(cli.json, r)),
+        // The schema is printed as is, it is not a versioned output itself
+        Commands::Schema(options) => schema(options, working_directory)
+            .await
+            .map(|r| r.to_string()),
+        Commands::Completions(options) => completions(options, Cli::command()).await,
+        Commands::Man(options) => man(options, Cli::command())
+            .await
+            .map(|r| display_or_json(cli.json, r)),
+        Commands::External(args) => run_external(args, &working_directory, cli.verbose, cli.json)
+            .map(|code| -> String { std::process::exit(code) }),
     };
     match result {
         Ok(r) => {
-            println!("{}", r);
+            if !r.is_empty() {
+                println!("{}", r);
+            }
+            if has_violations {
+                std::process::exit(exitcode::DATAERR);
+            }
             std::process::exit(exitcode::OK);
         }
         Err(e) => {
@@ -115,3 +235,42 @@ async fn main() {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+    use crate::commands::check_workspace::events::{Event, EventStream};
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_logs_stay_off_stdout() {
+        // Logging can only be set up once per process, so the test runs itself in a child
+        if std::env::var_os("FSLABSCLI_LOGGING_CHILD").is_some() {
+            setup_logging(1);
+            log::warn!("registry is slow");
+            EventStream::new(true).emit(Event::Finished {
+                packages: 0,
+                duration_ms: 0,
+            });
+            return;
+        }
+        let output = std::process::Command::new(
+            std::env::current_exe().expect("Could not find test binary"),
+        )
+        .args(["--exact", "tests::test_logs_stay_off_stdout", "--nocapture"])
+        .env("FSLABSCLI_LOGGING_CHILD", "1")
+        .output()
+        .expect("Could not run test binary");
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(!stdout.contains("registry is slow"));
+        assert!(stdout.contains(r#"{"event":"finished","packages":0,"duration_ms":0}"#));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("registry is slow"));
+    }
+}
@@ -1,15 +1,46 @@
 use std::fmt;
 use std::fmt::Display;
+use std::fs;
 use std::fs::read_dir;
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::str::FromStr;
-
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+
+use anyhow::Context;
+use cargo_metadata::semver::Version;
+use cargo_metadata::{Metadata, MetadataCommand};
+use ignore::WalkBuilder;
 use indexmap::IndexMap;
+use schemars::JsonSchema;
 use serde::de::{Error as SerdeError, MapAccess, Visitor};
-use serde::{de, Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
 use void::Void;
 
+/// Version of the `--json` outputs, bumped on incompatible changes
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// `--json` output of a command, tagged with the version of its schema
+#[derive(Serialize, JsonSchema)]
+pub struct VersionedOutput<T> {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub output: T,
+}
+
+impl<T> VersionedOutput<T> {
+    pub fn new(output: T) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            output,
+        }
+    }
+}
+
 pub fn get_cargo_roots(root: PathBuf) -> anyhow::Result<Vec<PathBuf>> {
     let mut roots: Vec<PathBuf> = Vec::new();
     if Path::exists(root.join("Cargo.toml").as_path()) {
@@ -28,6 +59,226 @@ pub fn get_cargo_roots(root: PathBuf) -> anyhow::Result<Vec<PathBuf>> {
     Ok(roots)
 }
 
+/// Where the parsed `cargo metadata --no-deps` of a workspace is cached, relative to its root
+const METADATA_CACHE: &str = "target/fslabscli/metadata.json";
+/// Where the parsed `cargo metadata` of a workspace, with its dependencies, is cached
+const RESOLVED_METADATA_CACHE: &str = "target/fslabscli/metadata-resolved.json";
+
+#[derive(Serialize, Deserialize)]
+struct CachedMetadata {
+    key: String,
+    metadata: Metadata,
+}
+
+/// Files and directories whose presence drives cargo's automatic target discovery
+const TARGET_DISCOVERY_PATHS: &[&str] = &[
+    "build.rs",
+    "src/lib.rs",
+    "src/main.rs",
+    "src/bin",
+    "tests",
+    "examples",
+    "benches",
+];
+
+/// Hash of the workspace location, of every manifest and lockfile it holds, of the paths cargo
+/// discovers targets from and of the cargo version
+fn metadata_cache_key(root: &Path) -> anyhow::Result<String> {
+    let mut manifests: Vec<PathBuf> = WalkBuilder::new(root)
+        .filter_entry(|e| e.file_name() != "target")
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name() == "Cargo.toml" || e.file_name() == "Cargo.lock")
+        .map(|e| e.into_path())
+        .collect();
+    manifests.sort();
+    // Only the paths of the discovered targets matter, not their content
+    let mut targets: Vec<PathBuf> = manifests
+        .iter()
+        .filter(|m| m.file_name().is_some_and(|f| f == "Cargo.toml"))
+        .filter_map(|m| m.parent())
+        .flat_map(|dir| TARGET_DISCOVERY_PATHS.iter().map(|p| dir.join(p)))
+        .filter(|p| p.exists())
+        .flat_map(|p| {
+            WalkBuilder::new(p)
+                .build()
+                .filter_map(|e| e.ok())
+                .map(|e| e.into_path())
+        })
+        .collect();
+    targets.sort();
+    let cargo_version = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".into()))
+        .arg("--version")
+        .output()
+        .map(|o| o.stdout)
+        .unwrap_or_default();
+    let mut hasher = Sha256::new();
+    hasher.update(root.to_string_lossy().as_bytes());
+    hasher.update(cargo_version);
+    for manifest in manifests {
+        hasher.update(manifest.to_string_lossy().as_bytes());
+        hasher.update(fs::read(&manifest)?);
+    }
+    for target in targets {
+        hasher.update(target.to_string_lossy().as_bytes());
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// `cargo metadata --no-deps` of the workspace at `root`, reused from the previous run while its
+/// manifests are unchanged, unless `refresh` is set
+pub fn cargo_metadata(root: &Path, refresh: bool) -> anyhow::Result<Metadata> {
+    load_metadata(root, refresh, false)
+}
+
+/// `cargo metadata` of the workspace at `root` with its resolved dependency graph, cached like
+/// [`cargo_metadata`]
+pub fn cargo_metadata_resolved(root: &Path, refresh: bool) -> anyhow::Result<Metadata> {
+    load_metadata(root, refresh, true)
+}
+
+fn load_metadata(root: &Path, refresh: bool, resolve: bool) -> anyhow::Result<Metadata> {
+    let cache = root.join(match resolve {
+        true => RESOLVED_METADATA_CACHE,
+        false => METADATA_CACHE,
+    });
+    let key = metadata_cache_key(root)?;
+    if !refresh {
+        if let Some(cached) = fs::read(&cache)
+            .ok()
+            .and_then(|c| serde_json::from_slice::<CachedMetadata>(&c).ok())
+            .filter(|c| c.key == key)
+        {
+            log::debug!("Using cached metadata of {}", root.to_string_lossy());
+            return Ok(cached.metadata);
+        }
+    }
+    let mut command = MetadataCommand::new();
+    command.current_dir(root);
+    if !resolve {
+        command.no_deps();
+    }
+    let metadata = command
+        .exec()
+        .with_context(|| format!("Could not load metadata of {}", root.to_string_lossy()))?;
+    let cached = CachedMetadata { key, metadata };
+    if let Err(e) = cache
+        .parent()
+        .map(fs::create_dir_all)
+        .unwrap_or(Ok(()))
+        .and_then(|_| fs::write(&cache, serde_json::to_vec(&cached)?))
+    {
+        log::debug!(
+            "Could not cache metadata of {}: {}",
+            root.to_string_lossy(),
+            e
+        );
+    }
+    Ok(cached.metadata)
+}
+
+/// [`cargo_metadata`] of every root, loaded by up to `jobs` threads at once
+pub fn cargo_metadata_all(
+    roots: &[PathBuf],
+    refresh: bool,
+    jobs: usize,
+) -> Vec<anyhow::Result<Metadata>> {
+    let next = AtomicUsize::new(0);
+    let mut loaded: Vec<(usize, anyhow::Result<Metadata>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.clamp(1, roots.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut loaded = vec![];
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(root) = roots.get(index) else {
+                            break;
+                        };
+                        let started = Instant::now();
+                        let metadata = cargo_metadata(root, refresh);
+                        log::debug!(
+                            "Loaded metadata of {} in {:?}",
+                            root.to_string_lossy(),
+                            started.elapsed()
+                        );
+                        loaded.push((index, metadata));
+                    }
+                    loaded
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("Metadata loading panicked"))
+            .collect()
+    });
+    loaded.sort_by_key(|(index, _)| *index);
+    loaded.into_iter().map(|(_, metadata)| metadata).collect()
+}
+
+/// Manifests under a workspace root which are not members of that workspace
+#[derive(Debug, Default, PartialEq)]
+pub struct ForeignManifests {
+    /// Roots of the outermost workspaces nested in the directory of the workspace
+    pub nested_workspaces: Vec<PathBuf>,
+    /// Other manifests, e.g. crates in `workspace.exclude` or test fixtures
+    pub skipped: Vec<PathBuf>,
+}
+
+pub fn foreign_manifests(root: &Path, metadata: &Metadata) -> ForeignManifests {
+    let members: Vec<PathBuf> = metadata
+        .packages
+        .iter()
+        .map(|p| p.manifest_path.clone().into_std_path_buf())
+        .collect();
+    let manifests: Vec<PathBuf> = WalkBuilder::new(root)
+        .add_custom_ignore_filename(".fslabscliignore")
+        .filter_entry(|e| e.file_name() != "target")
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name() == "Cargo.toml")
+        .map(|e| e.into_path())
+        .filter(|m| m != &root.join("Cargo.toml") && !members.contains(m))
+        .collect();
+    let workspaces: Vec<PathBuf> = manifests
+        .iter()
+        .filter(|m| {
+            fs::read_to_string(m)
+                .ok()
+                .and_then(|c| c.parse::<toml::Table>().ok())
+                .map(|t| t.contains_key("workspace"))
+                .unwrap_or(false)
+        })
+        .filter_map(|m| m.parent().map(Path::to_path_buf))
+        .collect();
+    let mut foreign = ForeignManifests::default();
+    for workspace in &workspaces {
+        if !workspaces
+            .iter()
+            .any(|w| w != workspace && workspace.starts_with(w))
+        {
+            foreign.nested_workspaces.push(workspace.clone());
+        }
+    }
+    // Manifests of nested workspaces are checked when loading them
+    foreign.skipped = manifests
+        .into_iter()
+        .filter(|m| !foreign.nested_workspaces.iter().any(|w| m.starts_with(w)))
+        .collect();
+    foreign.nested_workspaces.sort();
+    foreign.skipped.sort();
+    foreign
+}
+
+/// Whether two versions have the same semver precedence, build metadata being ignored.
+/// Versions which are not semver are compared as strings.
+pub fn same_version(a: &str, b: &str) -> bool {
+    match (Version::parse(a), Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp_precedence(&b).is_eq(),
+        _ => a == b,
+    }
+}
+
 pub trait FromMap {
     fn from_map(map: IndexMap<String, String>) -> Result<Self, Void>
     where
@@ -208,7 +459,10 @@ mod tests {
 
     use assert_fs::TempDir;
 
-    use crate::utils::get_cargo_roots;
+    use crate::utils::{
+        cargo_metadata, cargo_metadata_all, foreign_manifests, get_cargo_roots, same_version,
+        METADATA_CACHE,
+    };
 
     #[test]
     fn test_get_cargo_roots_simple_crate() {
@@ -297,4 +551,84 @@ mod tests {
         ];
         assert_eq!(roots, expected_results);
     }
+
+    #[test]
+    fn test_cargo_metadata_cache() {
+        let dir = TempDir::new().expect("Could not create temp dir");
+        let path = dir.path().canonicalize().expect("Could not canonicalize");
+        let manifest = "[package]\nname = \"cached\"\nversion = \"0.1.0\"\nedition = \"2021\"\n";
+        create_dir_all(path.join("src")).expect("Could not create src");
+        fs::write(path.join("src/lib.rs"), "").expect("Could not write lib.rs");
+        fs::write(path.join("Cargo.toml"), manifest).expect("Could not write Cargo.toml");
+        let metadata = cargo_metadata(&path, false).expect("Could not load metadata");
+        assert_eq!(metadata.packages[0].version.to_string(), "0.1.0");
+        assert!(path.join(METADATA_CACHE).exists());
+        fs::write(path.join("Cargo.toml"), manifest.replace("0.1.0", "0.2.0"))
+            .expect("Could not write Cargo.toml");
+        let metadata = cargo_metadata(&path, false).expect("Could not load metadata");
+        assert_eq!(metadata.packages[0].version.to_string(), "0.2.0");
+        create_dir_all(path.join("src/bin")).expect("Could not create src/bin");
+        fs::write(path.join("src/bin/tool.rs"), "fn main() {}").expect("Could not write tool.rs");
+        let metadata = cargo_metadata(&path, false).expect("Could not load metadata");
+        assert!(metadata.packages[0]
+            .targets
+            .iter()
+            .any(|t| t.name == "tool"));
+    }
+
+    #[test]
+    fn test_cargo_metadata_all() {
+        let dir = TempDir::new().expect("Could not create temp dir");
+        let path = dir.path().canonicalize().expect("Could not canonicalize");
+        let names = ["first", "second", "third"];
+        for name in names {
+            create_dir_all(path.join(name).join("src")).expect("Could not create src");
+            fs::write(path.join(name).join("src/lib.rs"), "").expect("Could not write lib.rs");
+            fs::write(
+                path.join(name).join("Cargo.toml"),
+                format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name),
+            )
+            .expect("Could not write Cargo.toml");
+        }
+        let roots = get_cargo_roots(path).expect("Could not get roots");
+        let loaded: Vec<String> = cargo_metadata_all(&roots, false, 2)
+            .into_iter()
+            .map(|m| m.expect("Could not load metadata").packages[0].name.clone())
+            .collect();
+        assert_eq!(loaded, names);
+    }
+
+    #[test]
+    fn test_foreign_manifests() {
+        let dir = TempDir::new().expect("Could not create temp dir");
+        let path = dir.path().canonicalize().expect("Could not canonicalize");
+        let write = |manifest: &str, content: &str| {
+            let manifest = path.join(manifest);
+            let crate_dir = manifest.parent().expect("No parent");
+            create_dir_all(crate_dir.join("src")).expect("Could not create src");
+            fs::write(crate_dir.join("src/lib.rs"), "").expect("Could not write lib.rs");
+            fs::write(&manifest, content).expect("Could not write manifest");
+        };
+        let package = |name: &str| format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name);
+        write(
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"member\"]\nexclude = [\"excluded\", \"tools\"]\n",
+        );
+        write("member/Cargo.toml", &package("member"));
+        write("excluded/Cargo.toml", &package("excluded"));
+        write("tools/Cargo.toml", "[workspace]\nmembers = [\"cli\"]\n");
+        write("tools/cli/Cargo.toml", &package("cli"));
+        let metadata = cargo_metadata(&path, false).expect("Could not load metadata");
+        let foreign = foreign_manifests(&path, &metadata);
+        assert_eq!(foreign.nested_workspaces, vec![path.join("tools")]);
+        assert_eq!(foreign.skipped, vec![path.join("excluded/Cargo.toml")]);
+    }
+
+    #[test]
+    fn test_same_version() {
+        assert!(same_version("1.2.0-rc.1", "1.2.0-rc.1+build.5"));
+        assert!(!same_version("1.2.0-rc.1", "1.2.0"));
+        assert!(!same_version("1.2.0-rc.1", "1.2.0-rc.10"));
+        assert!(same_version("2024.1", "2024.1"));
+    }
 }